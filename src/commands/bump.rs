@@ -0,0 +1,29 @@
+use anyhow::Result;
+use console::style;
+
+use super::{Outcome, require_existing};
+use crate::tools;
+
+pub fn run(target: &tools::Target) -> Result<Outcome, tools::CliError> {
+    require_existing(target)?;
+
+    let _lock = tools::FileLock::acquire(&target.path())?;
+
+    // load the local secret file
+    let mut env_file = target.load_env_file()?;
+
+    // increment the version
+    let new_version = env_file.version.unwrap_or(0) + 1;
+    env_file.version = Some(new_version);
+
+    // write the updated file
+    env_file.write()?;
+
+    println!(
+        "Bumped the version of the secret file {} to {}",
+        style(&target.filepath).magenta(),
+        style(new_version).cyan()
+    );
+
+    Ok(Outcome::Done)
+}
@@ -0,0 +1,39 @@
+use anyhow::Result;
+use console::style;
+
+use super::{Outcome, env_file_ids, require_existing};
+use crate::tools;
+
+pub async fn run(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    remote_version: Option<String>,
+) -> Result<Outcome, tools::CliError> {
+    let encryption = &target.encryption;
+
+    // check if the file exists
+    require_existing(target)?;
+
+    // load the local secret file
+    let env_file = target.load_env_file()?;
+    let (secret_id, field_id) = env_file_ids(&env_file)?;
+
+    // load the remote secret from the store
+    let secret = store.get(secret_id.clone(), remote_version.clone()).await?;
+
+    // decrypt the remote secret
+    let decrypted_remote_secret = encryption.decrypt(secret.load_field(field_id.clone())?)?;
+
+    // display diff
+    println!(
+        "Comparing local file {} with remote secret {}{}",
+        style(&target.filepath).magenta(),
+        style(format!("{}/{}", secret_id, field_id)).cyan(),
+        remote_version
+            .map(|v| format!(" at version {}", style(v).cyan()))
+            .unwrap_or_default()
+    );
+    tools::display_diff(decrypted_remote_secret, env_file.content);
+
+    Ok(Outcome::Done)
+}
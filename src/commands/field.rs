@@ -0,0 +1,274 @@
+use anyhow::Result;
+use console::style;
+
+use super::Outcome;
+use crate::tools;
+
+pub async fn ls(
+    store: &dyn tools::SecretStore,
+    secret_id: String,
+) -> Result<Outcome, tools::CliError> {
+    let secret = store.get(secret_id.clone(), None).await?;
+    let leases = tools::Leases::load(&secret)?;
+    let mut fields = secret.list_fields();
+    fields.sort();
+
+    if fields.is_empty() {
+        println!("Secret {} has no fields.", style(&secret_id).cyan());
+        return Ok(Outcome::Done);
+    }
+
+    println!("{:<32}{}", style("FIELD").bold(), style("LEASE").bold());
+    for field_id in fields {
+        let lease = match leases.live(&field_id) {
+            Some(lease) => format!("locked by {}", lease.describe()),
+            None => "-".to_string(),
+        };
+        println!("{:<32}{}", style(&field_id).cyan(), lease);
+    }
+
+    Ok(Outcome::Done)
+}
+
+pub async fn rm(
+    store: &dyn tools::SecretStore,
+    manifest: Option<&tools::Manifest>,
+    field: String,
+    yes: bool,
+) -> Result<Outcome, tools::CliError> {
+    let (secret_id, field_id) = parse_field(&field)?;
+    let mut secret = store.get(secret_id.clone(), None).await?;
+    secret.load_field(field_id.clone())?;
+
+    let mut leases = tools::Leases::load(&secret)?;
+    check_lease(&leases, &field)?;
+
+    if !yes
+        && !dialoguer::Confirm::new()
+            .with_prompt(format!("Remove field {}?", style(&field).cyan()))
+            .default(false)
+            .interact()
+            .expect("Failed to confirm removal")
+    {
+        return Err(tools::CliError::AbortedError(
+            "Removal cancelled. The secret was not changed.".to_string(),
+        ));
+    }
+
+    // our own lease goes away with the field
+    let mut changes = vec![(field_id.clone(), None)];
+    if leases.live(&field_id).is_some() {
+        leases.release(&field_id);
+        changes.push(leases.change(&field_id)?);
+    }
+    store
+        .update_fields(secret_id.clone(), &mut secret, changes)
+        .await?;
+
+    println!("Removed field {}", style(&field).cyan());
+
+    let linked = tools::EnvFile::find_linked(&search_root(manifest), &secret_id, &field_id);
+    for path in linked {
+        eprintln!(
+            "{} {} still points at the removed field",
+            style("Warning:").yellow().bold(),
+            style(path.to_string_lossy()).cyan()
+        );
+    }
+
+    Ok(Outcome::Done)
+}
+
+pub async fn transfer(
+    store: &dyn tools::SecretStore,
+    manifest: Option<&tools::Manifest>,
+    from: String,
+    to: String,
+    force: bool,
+    keep_source: bool,
+) -> Result<Outcome, tools::CliError> {
+    let (from_secret_id, from_field_id) = parse_field(&from)?;
+    let (to_secret_id, to_field_id) = parse_field(&to)?;
+    if from_secret_id == to_secret_id && from_field_id == to_field_id {
+        return Err(tools::CliError::InvalidFieldError(format!(
+            "{} is both the source and the destination",
+            from
+        )));
+    }
+
+    let mut from_secret = store.get(from_secret_id.clone(), None).await?;
+    let value = from_secret.load_field(from_field_id.clone())?;
+    let mut from_leases = tools::Leases::load(&from_secret)?;
+    if !keep_source {
+        check_lease(&from_leases, &from)?;
+    }
+
+    let same_secret = from_secret_id == to_secret_id;
+    let mut to_secret = if same_secret {
+        None
+    } else {
+        Some(store.get(to_secret_id.clone(), None).await?)
+    };
+
+    let existing = to_secret.as_ref().unwrap_or(&from_secret);
+    let previous = existing.data.get(&to_field_id).cloned();
+    if previous.is_some() {
+        if !force {
+            return Err(tools::CliError::ForceRequiredError(format!(
+                "field {} already exists. Use `--force` to overwrite it.",
+                to
+            )));
+        }
+        check_lease(&tools::Leases::load(existing)?, &to)?;
+    }
+
+    // our own lease goes away with the moved field
+    let mut source_changes = Vec::new();
+    if !keep_source {
+        source_changes.push((from_field_id.clone(), None));
+        if from_leases.live(&from_field_id).is_some() {
+            from_leases.release(&from_field_id);
+            source_changes.push(from_leases.change(&from_field_id)?);
+        }
+    }
+
+    // one version per secret, so a field never exists twice or not at all within a secret
+    let destination_change = (to_field_id.clone(), Some(value));
+    match &mut to_secret {
+        Some(to_secret) => {
+            store
+                .update_fields(to_secret_id.clone(), to_secret, vec![destination_change])
+                .await?;
+            if !source_changes.is_empty()
+                && let Err(e) = store
+                    .update_fields(from_secret_id.clone(), &mut from_secret, source_changes)
+                    .await
+            {
+                undo_copy(store, to_secret_id, to_field_id, to_secret, previous, &from).await;
+                return Err(e);
+            }
+        }
+        None => {
+            let mut changes = vec![destination_change];
+            changes.extend(source_changes);
+            store
+                .update_fields(from_secret_id.clone(), &mut from_secret, changes)
+                .await?;
+        }
+    }
+
+    println!(
+        "{} field {} to {}",
+        if keep_source { "Copied" } else { "Moved" },
+        style(&from).cyan(),
+        style(&to).cyan()
+    );
+    if keep_source {
+        return Ok(Outcome::Done);
+    }
+
+    // point the local files at the new location
+    for path in tools::EnvFile::find_linked(&search_root(manifest), &from_secret_id, &from_field_id)
+    {
+        let _lock = tools::FileLock::acquire(&path)?;
+        let mut env_file = tools::EnvFile::new_local(path.clone())?;
+        env_file.secret_id = Some(to_secret_id.clone());
+        env_file.field_id = Some(to_field_id.clone());
+        env_file.write()?;
+        println!(
+            "Updated the headers of {}",
+            style(path.to_string_lossy()).cyan()
+        );
+    }
+
+    // the manifest takes precedence over the headers, but is left for the user to edit
+    if let Some(manifest) = manifest {
+        for file in &manifest.files {
+            if file.secret_id == from_secret_id && file.field_id == from_field_id {
+                eprintln!(
+                    "{} '{}' in {} still points at {}. Change it to secret_id = \"{}\" and field_id = \"{}\".",
+                    style("Warning:").yellow().bold(),
+                    file.name,
+                    tools::MANIFEST_FILE,
+                    from,
+                    to_secret_id,
+                    to_field_id
+                );
+            }
+        }
+    }
+
+    Ok(Outcome::Done)
+}
+
+// a move across secrets takes two versions, so the copy is undone when the source cannot be removed
+async fn undo_copy(
+    store: &dyn tools::SecretStore,
+    to_secret_id: String,
+    to_field_id: String,
+    to_secret: &mut tools::Secret,
+    previous: Option<String>,
+    from: &str,
+) {
+    let to = format!("{}/{}", to_secret_id, to_field_id);
+    let restored = previous.is_some();
+    match store
+        .update_fields(to_secret_id, to_secret, vec![(to_field_id, previous)])
+        .await
+    {
+        Ok(()) => eprintln!(
+            "{} {} could not be removed, so {} was {}",
+            style("Warning:").yellow().bold(),
+            style(from).cyan(),
+            style(&to).cyan(),
+            if restored {
+                "restored"
+            } else {
+                "removed again"
+            }
+        ),
+        Err(e) => eprintln!(
+            "{} {} was copied to {}, but could not be removed and undoing the copy failed: {}. Both fields now exist, remove one of them with `tc-secrets field rm`.",
+            style("Warning:").yellow().bold(),
+            style(from).cyan(),
+            style(&to).cyan(),
+            e
+        ),
+    }
+}
+
+fn parse_field(field: &str) -> Result<(String, String), tools::CliError> {
+    // secret names may contain slashes, field names don't
+    match field.rsplit_once('/') {
+        Some((secret_id, field_id))
+            if !secret_id.is_empty()
+                && !field_id.is_empty()
+                && !tools::is_lease_field(field_id) =>
+        {
+            Ok((secret_id.to_string(), field_id.to_string()))
+        }
+        _ => Err(tools::CliError::InvalidFieldError(format!(
+            "'{}' is not of the form <secret>/<field>",
+            field
+        ))),
+    }
+}
+
+fn check_lease(leases: &tools::Leases, field: &str) -> Result<(), tools::CliError> {
+    let (_, field_id) = parse_field(field)?;
+    match leases.held_by_other(&field_id, &tools::lease_owner()) {
+        Some(lease) => Err(tools::CliError::LeaseHeldError(format!(
+            "{} is locked by {}",
+            field,
+            lease.describe()
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn search_root(manifest: Option<&tools::Manifest>) -> std::path::PathBuf {
+    match manifest.map(tools::Manifest::root) {
+        Some(root) if !root.as_os_str().is_empty() => root.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use console::style;
+
+use super::{Outcome, env_file_ids, require_existing};
+use crate::tools;
+
+pub async fn run(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    limit: usize,
+) -> Result<Outcome, tools::CliError> {
+    let encryption = &target.encryption;
+    require_existing(target)?;
+
+    let env_file = target.load_env_file()?;
+    let (secret_id, field_id) = env_file_ids(&env_file)?;
+
+    let info = store.describe(secret_id.clone()).await?;
+    println!(
+        "History of remote secret {}",
+        style(format!("{}/{}", info.name, field_id)).cyan()
+    );
+    if let Some(description) = &info.description {
+        println!("{}", description);
+    }
+    println!(
+        "Last changed at {}\n",
+        info.last_changed.as_deref().unwrap_or("unknown")
+    );
+    println!(
+        "{:<38}{:<22}{:<10}{}",
+        style("VERSION ID").bold(),
+        style("CREATED").bold(),
+        style("FIELD").bold(),
+        style("STAGES").bold()
+    );
+
+    // every version is read separately, so only the newest ones are read
+    let history = store.history(secret_id.clone()).await?;
+    let total = history.len();
+    let mut unreadable = 0;
+
+    for version in history.into_iter().take(limit) {
+        // one version that can't be read, e.g. because it was deleted, doesn't hide the others
+        let field_version = match store
+            .get(secret_id.clone(), Some(version.version_id.clone()))
+            .await
+        {
+            Ok(secret) => field_version(&secret, encryption, &field_id),
+            Err(_) => {
+                unreadable += 1;
+                "!".to_string()
+            }
+        };
+
+        println!(
+            "{:<38}{:<22}{:<10}{}",
+            version.version_id,
+            version.created_date.unwrap_or_default(),
+            field_version,
+            version.stages.join(",")
+        );
+    }
+
+    if total > limit {
+        println!(
+            "\nShowing the {} newest of {} versions. Use `--limit` to see more.",
+            limit, total
+        );
+    }
+    if unreadable > 0 {
+        eprintln!(
+            "{} {} of the listed versions could not be read and are marked with !",
+            style("Warning:").yellow().bold(),
+            unreadable
+        );
+    }
+
+    Ok(Outcome::Done)
+}
+
+// the field may not exist yet or use another password in older versions
+fn field_version(secret: &tools::Secret, encryption: &tools::Encryption, field_id: &str) -> String {
+    match secret.load_field(field_id.to_string()) {
+        Ok(content) => match encryption
+            .decrypt(content)
+            .and_then(tools::EnvFile::new_remote)
+        {
+            Ok(env_file) => env_file
+                .version
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "0".to_string()),
+            Err(_) => "?".to_string(),
+        },
+        Err(_) => "-".to_string(),
+    }
+}
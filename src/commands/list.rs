@@ -0,0 +1,84 @@
+use anyhow::Result;
+use console::style;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::Outcome;
+use crate::tools;
+
+#[derive(Serialize)]
+struct SecretListing {
+    name: String,
+    description: Option<String>,
+    last_changed: Option<String>,
+    tags: BTreeMap<String, String>,
+    fields: Vec<String>,
+    error: Option<String>,
+}
+
+pub async fn run(
+    store: &dyn tools::SecretStore,
+    filter: &tools::SecretFilter,
+    json: bool,
+) -> Result<Outcome, tools::CliError> {
+    let mut listings = Vec::new();
+    for info in store.list(filter).await? {
+        let mut listing = SecretListing {
+            name: info.name,
+            description: info.description,
+            last_changed: info.last_changed,
+            tags: info.tags,
+            fields: Vec::new(),
+            error: None,
+        };
+
+        // secrets that are not readable or not managed by tc-secrets are still listed
+        match store.get(listing.name.clone(), None).await {
+            Ok(secret) => {
+                listing.fields = secret.list_fields();
+                listing.fields.sort();
+            }
+            Err(e) => listing.error = Some(e.to_string()),
+        }
+        listings.push(listing);
+    }
+
+    if json {
+        let content =
+            serde_json::to_string_pretty(&listings).expect("Failed to serialize the secrets");
+        println!("{}", content);
+    } else {
+        display(&listings);
+    }
+
+    Ok(Outcome::Done)
+}
+
+fn display(listings: &[SecretListing]) {
+    if listings.is_empty() {
+        println!("No secrets found.");
+        return;
+    }
+
+    println!(
+        "{:<32}{:<22}{:<32}{}",
+        style("NAME").bold(),
+        style("LAST CHANGED").bold(),
+        style("FIELDS").bold(),
+        style("DESCRIPTION").bold()
+    );
+    for listing in listings {
+        let fields = match &listing.error {
+            Some(_) => "?".to_string(),
+            None if listing.fields.is_empty() => "-".to_string(),
+            None => listing.fields.join(","),
+        };
+        println!(
+            "{:<32}{:<22}{:<32}{}",
+            style(&listing.name).cyan(),
+            listing.last_changed.as_deref().unwrap_or("-"),
+            fields,
+            listing.description.as_deref().unwrap_or_default()
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::Result;
+use console::style;
+
+use super::{Outcome, env_file_ids, require_existing};
+use crate::tools;
+
+pub async fn lock(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    reason: Option<String>,
+    minutes: i64,
+    steal: bool,
+) -> Result<Outcome, tools::CliError> {
+    require_existing(target)?;
+    let (secret_id, field_id) = env_file_ids(&target.load_env_file()?)?;
+    let field = format!("{}/{}", secret_id, field_id);
+
+    let mut secret = store.get(secret_id.clone(), None).await?;
+    let mut leases = tools::Leases::load(&secret)?;
+    let owner = tools::lease_owner();
+
+    if let Some(lease) = leases.held_by_other(&field_id, &owner).cloned() {
+        if !steal {
+            return Err(tools::CliError::LeaseHeldError(format!(
+                "{} is locked by {}. Use `--steal` to override the lease.",
+                field,
+                lease.describe()
+            )));
+        }
+        leases.steal(&field_id, &owner);
+        eprintln!(
+            "{} {} stole the lease of {} from {}",
+            style("Warning:").yellow().bold(),
+            owner,
+            style(&field).cyan(),
+            lease.owner
+        );
+    }
+
+    // locking again renews our own lease
+    leases.acquire(&field_id, &owner, reason, minutes * 60);
+    store
+        .update_fields(secret_id, &mut secret, vec![leases.change(&field_id)?])
+        .await?;
+
+    println!(
+        "Locked {} for {}",
+        style(&field).cyan(),
+        leases.live(&field_id).unwrap().describe()
+    );
+
+    Ok(Outcome::Done)
+}
+
+pub async fn unlock(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    steal: bool,
+) -> Result<Outcome, tools::CliError> {
+    require_existing(target)?;
+    let (secret_id, field_id) = env_file_ids(&target.load_env_file()?)?;
+    let field = format!("{}/{}", secret_id, field_id);
+
+    let mut secret = store.get(secret_id.clone(), None).await?;
+    let mut leases = tools::Leases::load(&secret)?;
+    let owner = tools::lease_owner();
+
+    let Some(lease) = leases.live(&field_id).cloned() else {
+        println!("{} is not locked.", style(&field).cyan());
+        return Ok(Outcome::Done);
+    };
+
+    if lease.owner != owner {
+        if !steal {
+            return Err(tools::CliError::LeaseHeldError(format!(
+                "{} is locked by {}. Use `--steal` to release it anyway.",
+                field,
+                lease.describe()
+            )));
+        }
+        leases.steal(&field_id, &owner);
+        eprintln!(
+            "{} {} released the lease of {} held by {}",
+            style("Warning:").yellow().bold(),
+            owner,
+            style(&field).cyan(),
+            lease.owner
+        );
+    } else {
+        leases.release(&field_id);
+    }
+
+    store
+        .update_fields(secret_id, &mut secret, vec![leases.change(&field_id)?])
+        .await?;
+
+    println!("Unlocked {}", style(&field).cyan());
+
+    Ok(Outcome::Done)
+}
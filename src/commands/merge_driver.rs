@@ -0,0 +1,48 @@
+use anyhow::Result;
+use console::style;
+use std::path::Path;
+
+use super::Outcome;
+use crate::tools;
+
+pub fn install(backend: &tools::Backend) -> Result<Outcome, tools::CliError> {
+    let tools::Backend::Git(options) = backend else {
+        return Err(tools::CliError::ManifestError(
+            "the merge driver is only used by the git backend".to_string(),
+        ));
+    };
+
+    let changed = tools::GitFile::new(options).install_merge_driver()?;
+    let changed: Vec<String> = changed
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    println!(
+        "Installed the merge driver for {}. Commit {} so everyone merges the file field by field.",
+        style(options.path.to_string_lossy()).cyan(),
+        style(changed.join(" and ")).cyan()
+    );
+
+    Ok(Outcome::Done)
+}
+
+pub fn run(base: &Path, ours: &Path, theirs: &Path) -> Result<Outcome, tools::CliError> {
+    let conflicts = tools::merge_secrets_files(base, ours, theirs)?;
+    if conflicts.is_empty() {
+        return Ok(Outcome::Done);
+    }
+
+    // git leaves the file conflicted when the driver fails
+    for conflict in &conflicts {
+        eprintln!(
+            "{} {} was changed on both sides, keeping our value",
+            style("Conflict:").red().bold(),
+            style(conflict).cyan()
+        );
+    }
+    Err(tools::CliError::FieldConflictError(format!(
+        "{} value(s) were changed on both sides",
+        conflicts.len()
+    )))
+}
@@ -0,0 +1,419 @@
+use anyhow::Result;
+use console::style;
+use dialoguer::{FuzzySelect, Input, Select};
+
+use crate::tools;
+
+pub mod bump;
+pub mod diff;
+pub mod field;
+pub mod history;
+pub mod list;
+pub mod lock;
+pub mod merge_driver;
+pub mod pull;
+pub mod push;
+pub mod reset;
+pub mod rollback;
+pub mod secret;
+pub mod status;
+pub mod sync;
+pub mod watch;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Done,
+    ChangesPending,
+}
+
+impl Outcome {
+    pub fn and(self, other: Outcome) -> Outcome {
+        if self == Outcome::ChangesPending || other == Outcome::ChangesPending {
+            Outcome::ChangesPending
+        } else {
+            Outcome::Done
+        }
+    }
+}
+
+fn plan_outcome(plan: &tools::Plan) -> Outcome {
+    if plan.has_changes() {
+        Outcome::ChangesPending
+    } else {
+        Outcome::Done
+    }
+}
+
+async fn load_env_info(
+    store: &dyn tools::SecretStore,
+    filepath: &str,
+    env_file: &mut tools::EnvFile,
+    dry_run: bool,
+) -> Result<tools::Secret, tools::CliError> {
+    // get the secret ID from the local file
+    if env_file.secret_id.is_none() {
+        let remote_secrets = store.list(&tools::SecretFilter::default()).await?;
+        if remote_secrets.is_empty() && dry_run {
+            return Err(tools::CliError::NotFoundError(
+                "No remote secrets were found.".to_string(),
+            ));
+        }
+
+        // type to search by name or description
+        let mut items: Vec<String> = remote_secrets
+            .iter()
+            .map(|info| {
+                format!(
+                    "{:<32} {:<22} {}",
+                    info.name,
+                    info.last_changed.as_deref().unwrap_or("-"),
+                    info.description.as_deref().unwrap_or_default()
+                )
+            })
+            .collect();
+        if !dry_run {
+            items.push(NEW_SECRET_ITEM.to_string());
+        }
+        let selection = FuzzySelect::new()
+            .with_prompt(format!(
+                "No local file found at {}. Please select a secret ID to download:",
+                style(filepath).cyan()
+            ))
+            .items(&items)
+            .default(0)
+            .interact()
+            .expect("Failed to select a secret ID");
+
+        let secret_id = match remote_secrets.get(selection) {
+            Some(info) => info.name.clone(),
+            None => create_secret(store).await?,
+        };
+        env_file.secret_id = Some(secret_id);
+    }
+
+    let secret_id = env_file.secret_id.clone().unwrap();
+
+    // load the remote secret from the store
+    let mut secret = store.get(secret_id.clone(), None).await?;
+
+    // get the field ID from the local file
+    if env_file.field_id.is_none() {
+        let mut remote_fields = secret.list_fields();
+        remote_fields.sort();
+        if remote_fields.is_empty() && dry_run {
+            return Err(tools::CliError::AwsSecretsFormatError(format!(
+                "Secret {} has no fields.",
+                secret_id
+            )));
+        }
+
+        let mut items = remote_fields.clone();
+        if !dry_run {
+            items.push(NEW_FIELD_ITEM.to_string());
+        }
+        let selection = Select::new()
+            .with_prompt(format!(
+                "Please select a field ID to load from secret {}:",
+                style(secret_id.clone()).cyan()
+            ))
+            .items(&items)
+            .default(0)
+            .interact()
+            .expect("Failed to select a field ID");
+
+        let field_id = match remote_fields.get(selection) {
+            Some(field_id) => field_id.clone(),
+            None => {
+                let field_id = prompt_name("Name of the new field", &remote_fields);
+                // the field starts out empty, and is only created remotely by the first write
+                secret.data.insert(field_id.clone(), String::new());
+                field_id
+            }
+        };
+        env_file.field_id = Some(field_id);
+    }
+
+    if env_file.version.is_none() {
+        // if local version is not initialized, rewrite the local file with headers
+        env_file.version = Some(0);
+        if !dry_run {
+            env_file.write()?;
+        }
+    }
+
+    Ok(secret)
+}
+
+const NEW_SECRET_ITEM: &str = "create new secret...";
+const NEW_FIELD_ITEM: &str = "new field...";
+
+async fn create_secret(store: &dyn tools::SecretStore) -> Result<String, tools::CliError> {
+    let name = prompt_name("Name of the new secret", &[]);
+    let description: String = Input::new()
+        .with_prompt("Description (optional)")
+        .allow_empty(true)
+        .interact_text()
+        .expect("Failed to read a description");
+
+    store
+        .create(&tools::NewSecret {
+            name: name.clone(),
+            description: (!description.is_empty()).then_some(description),
+            tags: Default::default(),
+            kms_key_id: None,
+        })
+        .await?;
+    println!("Created remote secret {}", style(&name).cyan());
+
+    Ok(name)
+}
+
+fn prompt_name(prompt: &str, taken: &[String]) -> String {
+    Input::<String>::new()
+        .with_prompt(prompt)
+        .validate_with(|name: &String| {
+            if name.trim().is_empty() {
+                Err("The name cannot be empty")
+            } else if taken.contains(name) || tools::is_lease_field(name) {
+                Err("The name is already taken")
+            } else {
+                Ok(())
+            }
+        })
+        .interact_text()
+        .expect("Failed to read a name")
+}
+
+fn env_file_ids(env_file: &tools::EnvFile) -> Result<(String, String), tools::CliError> {
+    let secret_id = env_file.secret_id.clone().ok_or_else(|| {
+        tools::CliError::InvalidEnvFileError(
+            "The secret file does not contain a secret ID.".to_string(),
+        )
+    })?;
+    let field_id = env_file.field_id.clone().ok_or_else(|| {
+        tools::CliError::InvalidEnvFileError(
+            "The secret file does not contain a field ID.".to_string(),
+        )
+    })?;
+
+    Ok((secret_id, field_id))
+}
+
+fn load_remote_env_file(
+    secret: &tools::Secret,
+    encryption: &tools::Encryption,
+    secret_id: &str,
+    field_id: &str,
+) -> Result<tools::EnvFile, tools::CliError> {
+    let decrypted_remote_secret = encryption.decrypt(secret.load_field(field_id.to_string())?)?;
+
+    let mut remote_env_file = tools::EnvFile::new_remote(decrypted_remote_secret)?;
+    // the location of the field wins over the headers inside it, which go stale when it moves
+    remote_env_file.secret_id = Some(secret_id.to_string());
+    remote_env_file.field_id = Some(field_id.to_string());
+
+    Ok(remote_env_file)
+}
+
+fn detect_changes(
+    state: &tools::SyncState,
+    encryption: &tools::Encryption,
+    local_env_file: &tools::EnvFile,
+    remote_env_file: &tools::EnvFile,
+) -> Result<(bool, bool), tools::CliError> {
+    let local_version = local_env_file.version.unwrap_or(0);
+    let remote_version = remote_env_file.version.unwrap_or(0);
+
+    Ok(match state.synced_body(encryption)? {
+        Some(body) => (
+            local_env_file.body() != body,
+            remote_env_file.body() != body,
+        ),
+        // without a sync record, fall back to comparing the versions
+        None => (
+            local_version > remote_version,
+            local_version < remote_version,
+        ),
+    })
+}
+
+fn backup_local_file(
+    target: &tools::Target,
+    backups: tools::Backups,
+) -> Result<(), tools::CliError> {
+    let encryption = backups.encrypt.then_some(&target.encryption);
+    if let Some(backup_path) = tools::backup_file(&target.path(), backups.count, encryption)? {
+        println!(
+            "Saved a backup of {} to {}",
+            style(&target.filepath).cyan(),
+            style(backup_path.to_string_lossy()).cyan()
+        );
+    }
+    Ok(())
+}
+
+fn plan_backup(plan: &mut tools::Plan, target: &tools::Target, backups: tools::Backups) {
+    if target.path().exists() && backups.count > 0 {
+        plan.writes
+            .push(format!("backup file {}.bak.1", target.filepath));
+    }
+}
+
+fn require_existing(target: &tools::Target) -> Result<(), tools::CliError> {
+    if !target.path().exists() {
+        return Err(tools::CliError::InvalidEnvFileError(format!(
+            "The file '{}' does not exist.",
+            target.filepath
+        )));
+    }
+    Ok(())
+}
+
+async fn check_lease(
+    store: &dyn tools::SecretStore,
+    secret: &mut tools::Secret,
+    secret_id: &str,
+    field_id: &str,
+    steal: bool,
+    dry_run: bool,
+) -> Result<(), tools::CliError> {
+    let mut leases = tools::Leases::load(secret)?;
+    let owner = tools::lease_owner();
+    let Some(lease) = leases.held_by_other(field_id, &owner).cloned() else {
+        return Ok(());
+    };
+
+    let field = format!("{}/{}", secret_id, field_id);
+    if dry_run {
+        println!(
+            "{} {} is locked by {}.",
+            style("Warning:").yellow().bold(),
+            style(&field).cyan(),
+            lease.describe()
+        );
+        return Ok(());
+    }
+    if !steal {
+        return Err(tools::CliError::LeaseHeldError(format!(
+            "{} is locked by {}. Use `--steal` to override the lease.",
+            field,
+            lease.describe()
+        )));
+    }
+
+    // record the override in the secret, so the lease holder can see it
+    leases.steal(field_id, &owner);
+    store
+        .update_fields(
+            secret_id.to_string(),
+            secret,
+            vec![leases.change(field_id)?],
+        )
+        .await?;
+    eprintln!(
+        "{} {} stole the lease of {} from {}",
+        style("Warning:").yellow().bold(),
+        owner,
+        style(&field).cyan(),
+        lease.owner
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detect_changes;
+    use crate::tools::{Encryption, EnvFile, SyncState};
+    use std::path::Path;
+
+    const SYNCED: &str = "#do-not-edit--secrets-version 1\nA=1\n";
+
+    fn synced_state(encryption: &Encryption) -> SyncState {
+        let mut state = SyncState::load(Path::new("missing/.env")).unwrap();
+        state.record_sync(encryption, SYNCED.to_string()).unwrap();
+        state
+    }
+
+    fn env_file(content: &str) -> EnvFile {
+        EnvFile::new_remote(content.to_string()).unwrap()
+    }
+
+    #[test]
+    fn unchanged_content_is_detected_through_version_bumps() {
+        let encryption = Encryption::new("hunter2".to_string());
+        let state = synced_state(&encryption);
+
+        let changes = detect_changes(
+            &state,
+            &encryption,
+            &env_file(SYNCED),
+            &env_file("#do-not-edit--secrets-version 3\nA=1\n"),
+        );
+
+        assert_eq!(changes.unwrap(), (false, false));
+    }
+
+    #[test]
+    fn local_changes_are_detected() {
+        let encryption = Encryption::new("hunter2".to_string());
+        let state = synced_state(&encryption);
+
+        let changes = detect_changes(&state, &encryption, &env_file("A=2\n"), &env_file(SYNCED));
+
+        assert_eq!(changes.unwrap(), (true, false));
+    }
+
+    #[test]
+    fn remote_changes_are_detected() {
+        let encryption = Encryption::none();
+        let state = synced_state(&encryption);
+
+        let changes = detect_changes(&state, &encryption, &env_file(SYNCED), &env_file("A=2\n"));
+
+        assert_eq!(changes.unwrap(), (false, true));
+    }
+
+    #[test]
+    fn changes_on_both_sides_are_detected() {
+        let encryption = Encryption::none();
+        let state = synced_state(&encryption);
+
+        let changes = detect_changes(
+            &state,
+            &encryption,
+            &env_file("A=local\n"),
+            &env_file("A=remote\n"),
+        );
+
+        assert_eq!(changes.unwrap(), (true, true));
+    }
+
+    #[test]
+    fn versions_are_compared_without_a_sync_record() {
+        let state = SyncState::load(Path::new("missing/.env")).unwrap();
+
+        let changes = detect_changes(
+            &state,
+            &Encryption::none(),
+            &env_file("#do-not-edit--secrets-version 2\nA=2\n"),
+            &env_file(SYNCED),
+        );
+
+        assert_eq!(changes.unwrap(), (true, false));
+    }
+
+    #[test]
+    fn a_wrong_password_fails_instead_of_reporting_changes() {
+        let state = synced_state(&Encryption::new("hunter2".to_string()));
+
+        let changes = detect_changes(
+            &state,
+            &Encryption::new("wrong".to_string()),
+            &env_file(SYNCED),
+            &env_file(SYNCED),
+        );
+
+        assert!(changes.is_err());
+    }
+}
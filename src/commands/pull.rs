@@ -0,0 +1,86 @@
+use anyhow::Result;
+use console::style;
+
+use super::{
+    Outcome, backup_local_file, detect_changes, env_file_ids, load_env_info, load_remote_env_file,
+    plan_backup, plan_outcome,
+};
+use crate::tools;
+
+pub async fn run(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    force: bool,
+    dry_run: bool,
+    backups: tools::Backups,
+) -> Result<Outcome, tools::CliError> {
+    let encryption = &target.encryption;
+    let filepath = target.filepath.as_str();
+    let path = target.path();
+    let _lock = tools::FileLock::acquire(&path)?;
+
+    // load the local secret file
+    let mut local_env_file = target.load_env_file()?;
+    let secret = load_env_info(store, filepath, &mut local_env_file, dry_run).await?;
+    let (secret_id, field_id) = env_file_ids(&local_env_file)?;
+
+    let mut remote_env_file = load_remote_env_file(&secret, encryption, &secret_id, &field_id)?;
+
+    let mut state = tools::SyncState::load(&path)?;
+    let (local_edited, _) = detect_changes(&state, encryption, &local_env_file, &remote_env_file)?;
+    let local_version = local_env_file.version.unwrap_or(0);
+    let remote_version = remote_env_file.version.unwrap_or(0);
+    let unchanged = local_env_file.body() == remote_env_file.body();
+    let needs_force = !unchanged && (local_edited || local_version > remote_version);
+
+    if dry_run {
+        let direction = if unchanged {
+            tools::Direction::UpToDate
+        } else {
+            tools::Direction::Pull
+        };
+        let mut plan = tools::Plan::new(direction, local_version, remote_version);
+        plan.old_content = local_env_file.body();
+        plan.new_content = remote_env_file.body();
+        if plan.has_changes() {
+            plan_backup(&mut plan, target, backups);
+            plan.writes.push(format!("local file {}", filepath));
+        }
+        plan.display();
+        if needs_force && !force {
+            println!("The pull would require `--force`.");
+        }
+        return Ok(plan_outcome(&plan));
+    }
+
+    println!(
+        "Pulling remote secret {} (version {}) into {} (version {})",
+        style(format!("{}/{}", secret_id, field_id)).cyan(),
+        style(remote_version).cyan(),
+        style(filepath).magenta(),
+        style(local_version).cyan()
+    );
+    tools::display_key_changes(&local_env_file.body(), &remote_env_file.body());
+
+    if needs_force && !force {
+        return Err(tools::CliError::ForceRequiredError(
+            "The local secret file has unsynced or newer content that would be discarded. Use `--force` to pull anyway.".to_string(),
+        ));
+    }
+
+    if !unchanged {
+        backup_local_file(target, backups)?;
+    }
+    remote_env_file.filepath = Some(path.clone());
+    remote_env_file.version = Some(remote_version);
+    remote_env_file.write()?;
+    state.record_sync(encryption, remote_env_file.content.clone())?;
+    state.save()?;
+
+    println!(
+        "The local secret file has been updated to version {}",
+        style(remote_version).cyan()
+    );
+
+    Ok(Outcome::Done)
+}
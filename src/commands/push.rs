@@ -0,0 +1,103 @@
+use anyhow::Result;
+use console::style;
+
+use super::{
+    Outcome, check_lease, detect_changes, env_file_ids, load_env_info, load_remote_env_file,
+    plan_outcome,
+};
+use crate::tools;
+
+pub async fn run(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    force: bool,
+    steal: bool,
+    dry_run: bool,
+) -> Result<Outcome, tools::CliError> {
+    let encryption = &target.encryption;
+    let filepath = target.filepath.as_str();
+    let path = target.path();
+    let _lock = tools::FileLock::acquire(&path)?;
+
+    // load the local secret file
+    let mut local_env_file = target.load_env_file()?;
+    let mut secret = load_env_info(store, filepath, &mut local_env_file, dry_run).await?;
+    let (secret_id, field_id) = env_file_ids(&local_env_file)?;
+
+    let remote_env_file = load_remote_env_file(&secret, encryption, &secret_id, &field_id)?;
+
+    let mut state = tools::SyncState::load(&path)?;
+    let (_, remote_changed) =
+        detect_changes(&state, encryption, &local_env_file, &remote_env_file)?;
+    let local_version = local_env_file.version.unwrap_or(0);
+    let remote_version = remote_env_file.version.unwrap_or(0);
+    let new_version = local_version.max(remote_version + 1);
+    let unchanged = local_env_file.body() == remote_env_file.body();
+    let needs_force = !unchanged && (remote_changed || remote_version > local_version);
+
+    if dry_run {
+        let mut plan = if unchanged {
+            tools::Plan::new(tools::Direction::UpToDate, remote_version, remote_version)
+        } else {
+            tools::Plan::new(tools::Direction::Push, remote_version, new_version)
+        };
+        plan.old_content = remote_env_file.body();
+        plan.new_content = local_env_file.body();
+        if plan.has_changes() {
+            if new_version != local_version {
+                plan.writes.push(format!("local file {}", filepath));
+            }
+            plan.writes
+                .push(format!("remote field {}/{}", secret_id, field_id));
+        }
+        if plan.has_changes() {
+            check_lease(store, &mut secret, &secret_id, &field_id, steal, true).await?;
+        }
+        plan.display();
+        if needs_force && !force {
+            println!("The push would require `--force`.");
+        }
+        return Ok(plan_outcome(&plan));
+    }
+
+    println!(
+        "Pushing {} (version {}) to remote secret {} (version {})",
+        style(filepath).magenta(),
+        style(new_version).cyan(),
+        style(format!("{}/{}", secret_id, field_id)).cyan(),
+        style(remote_version).cyan()
+    );
+    tools::display_key_changes(&remote_env_file.body(), &local_env_file.body());
+
+    if unchanged {
+        state.record_sync(encryption, local_env_file.content.clone())?;
+        state.save()?;
+        println!("The remote secret is already up to date!");
+        return Ok(Outcome::Done);
+    }
+
+    if needs_force && !force {
+        return Err(tools::CliError::ForceRequiredError(
+            "The remote secret has changes or a newer version that would be discarded. Use `--force` to push anyway.".to_string(),
+        ));
+    }
+
+    check_lease(store, &mut secret, &secret_id, &field_id, steal, false).await?;
+
+    local_env_file.version = Some(new_version);
+    local_env_file.write()?;
+
+    let encrypted_content = encryption.encrypt(local_env_file.content.clone())?;
+    store
+        .update_field(secret_id, &mut secret, field_id, encrypted_content)
+        .await?;
+    state.record_sync(encryption, local_env_file.content.clone())?;
+    state.save()?;
+
+    println!(
+        "The remote secret has been updated with the local secret file version {}",
+        style(new_version).cyan()
+    );
+
+    Ok(Outcome::Done)
+}
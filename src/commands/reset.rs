@@ -0,0 +1,84 @@
+use anyhow::Result;
+use console::style;
+use std::io::IsTerminal;
+
+use super::{
+    Outcome, backup_local_file, env_file_ids, load_env_info, load_remote_env_file, plan_backup,
+    plan_outcome,
+};
+use crate::tools;
+
+pub async fn run(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    dry_run: bool,
+    mut backups: tools::Backups,
+) -> Result<Outcome, tools::CliError> {
+    let encryption = &target.encryption;
+    let filepath = target.filepath.as_str();
+    let path = target.path();
+    let _lock = tools::FileLock::acquire(&path)?;
+
+    // load the local secret file
+    let mut local_env_file = target.load_env_file()?;
+
+    let secret = load_env_info(store, filepath, &mut local_env_file, dry_run).await?;
+    let (secret_id, field_id) = env_file_ids(&local_env_file)?;
+
+    // load the remote secret from the store
+    let mut remote_env_file = load_remote_env_file(&secret, encryption, &secret_id, &field_id)?;
+    remote_env_file.version = remote_env_file.version.or(Some(1));
+    remote_env_file.filepath = Some(path.clone());
+
+    if dry_run {
+        let direction = if local_env_file.body() == remote_env_file.body() {
+            tools::Direction::UpToDate
+        } else {
+            tools::Direction::Pull
+        };
+        let mut plan = tools::Plan::new(
+            direction,
+            local_env_file.version.unwrap_or(0),
+            remote_env_file.version.unwrap_or(0),
+        );
+        plan.old_content = local_env_file.body();
+        plan.new_content = remote_env_file.body();
+        if plan.has_changes() {
+            plan_backup(&mut plan, target, backups);
+            plan.writes.push(format!("local file {}", filepath));
+        }
+        plan.display();
+        return Ok(plan_outcome(&plan));
+    }
+
+    // in a terminal, the backup can still be declined like before backups were numbered
+    if path.exists()
+        && backups.count > 0
+        && std::io::stdin().is_terminal()
+        && !dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "The file {} already exists. Do you want to make a backup?",
+                style(filepath).cyan()
+            ))
+            .default(true)
+            .interact()
+            .expect("Failed to confirm backup")
+    {
+        backups.count = 0;
+    }
+    backup_local_file(target, backups)?;
+
+    remote_env_file.write()?;
+
+    let mut state = tools::SyncState::load(&path)?;
+    state.record_sync(encryption, remote_env_file.content.clone())?;
+    state.save()?;
+
+    println!(
+        "Local secret file {} has been reset by remote secret {}",
+        style(filepath).magenta(),
+        style(format!("{}/{}", secret_id, field_id)).cyan()
+    );
+
+    Ok(Outcome::Done)
+}
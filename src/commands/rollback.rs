@@ -0,0 +1,101 @@
+use anyhow::Result;
+use console::style;
+
+use super::{
+    Outcome, check_lease, env_file_ids, load_remote_env_file, plan_outcome, require_existing,
+};
+use crate::tools;
+
+pub async fn run(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    version_id: String,
+    steal: bool,
+    yes: bool,
+    dry_run: bool,
+) -> Result<Outcome, tools::CliError> {
+    let encryption = &target.encryption;
+    let filepath = target.filepath.as_str();
+    require_existing(target)?;
+
+    let env_file = target.load_env_file()?;
+    let (secret_id, field_id) = env_file_ids(&env_file)?;
+
+    // load the current and the past remote secret
+    let mut secret = store.get(secret_id.clone(), None).await?;
+    let remote_env_file = load_remote_env_file(&secret, encryption, &secret_id, &field_id)?;
+    let past_secret = store
+        .get(secret_id.clone(), Some(version_id.clone()))
+        .await?;
+    let mut past_env_file = load_remote_env_file(&past_secret, encryption, &secret_id, &field_id)?;
+
+    // restore the past content on top of the current version
+    let remote_version = remote_env_file.version.unwrap_or(0);
+    let new_version = remote_version + 1;
+    past_env_file.version = Some(new_version);
+    past_env_file.write()?;
+    let unchanged = remote_env_file.body() == past_env_file.body();
+
+    if dry_run {
+        let mut plan = if unchanged {
+            tools::Plan::new(tools::Direction::UpToDate, remote_version, remote_version)
+        } else {
+            tools::Plan::new(tools::Direction::Push, remote_version, new_version)
+        };
+        plan.old_content = remote_env_file.body();
+        plan.new_content = past_env_file.body();
+        if plan.has_changes() {
+            check_lease(store, &mut secret, &secret_id, &field_id, steal, true).await?;
+            plan.writes
+                .push(format!("remote field {}/{}", secret_id, field_id));
+        }
+        plan.display();
+        return Ok(plan_outcome(&plan));
+    }
+
+    println!(
+        "Restoring remote secret {} from version {}",
+        style(format!("{}/{}", secret_id, field_id)).cyan(),
+        style(&version_id).cyan()
+    );
+    tools::display_key_changes(&remote_env_file.body(), &past_env_file.body());
+
+    if unchanged {
+        println!("The remote secret already has the content of this version!");
+        return Ok(Outcome::Done);
+    }
+
+    if !yes
+        && !dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "Publish this content as version {}?",
+                style(new_version).cyan()
+            ))
+            .default(false)
+            .interact()
+            .map_err(|e| {
+                tools::CliError::AbortedError(format!(
+                    "Cannot confirm the rollback: {}. Use `--yes` to roll back without a prompt.",
+                    e
+                ))
+            })?
+    {
+        return Err(tools::CliError::AbortedError(
+            "Rollback cancelled. No changes were written.".to_string(),
+        ));
+    }
+
+    check_lease(store, &mut secret, &secret_id, &field_id, steal, false).await?;
+    let encrypted_content = encryption.encrypt(past_env_file.content.clone())?;
+    store
+        .update_field(secret_id, &mut secret, field_id, encrypted_content)
+        .await?;
+
+    println!(
+        "The remote secret has been rolled back as version {}. Run `tc-secrets pull -f {}` to update the local file.",
+        style(new_version).cyan(),
+        filepath
+    );
+
+    Ok(Outcome::Done)
+}
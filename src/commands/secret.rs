@@ -0,0 +1,131 @@
+use anyhow::Result;
+use console::style;
+use std::collections::BTreeMap;
+
+use super::Outcome;
+use crate::tools;
+
+// the longest window AWS Secrets Manager allows
+const DEFAULT_RECOVERY_DAYS: i64 = 30;
+
+pub async fn create(
+    store: &dyn tools::SecretStore,
+    name: String,
+    description: Option<String>,
+    tags: &[String],
+    kms_key_id: Option<String>,
+) -> Result<Outcome, tools::CliError> {
+    // a tag without a value gets an empty one
+    let tags: BTreeMap<String, String> = tags
+        .iter()
+        .map(|tag| match tag.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (tag.clone(), String::new()),
+        })
+        .collect();
+
+    store
+        .create(&tools::NewSecret {
+            name: name.clone(),
+            description,
+            tags,
+            kms_key_id,
+        })
+        .await?;
+
+    println!(
+        "Created remote secret {}. Run `tc-secrets sync -f <file>` to add a field to it.",
+        style(&name).cyan()
+    );
+
+    Ok(Outcome::Done)
+}
+
+pub async fn delete(
+    store: &dyn tools::SecretStore,
+    secret_id: String,
+    recovery_days: Option<i64>,
+    yes: bool,
+) -> Result<Outcome, tools::CliError> {
+    let secret = store.get(secret_id.clone(), None).await?;
+    let mut fields = secret.list_fields();
+    fields.sort();
+
+    println!(
+        "Remote secret {} has the fields: {}",
+        style(&secret_id).cyan(),
+        if fields.is_empty() {
+            "-".to_string()
+        } else {
+            fields.join(", ")
+        }
+    );
+
+    let window = store.has_recovery_window();
+    if !window && recovery_days.is_some() {
+        eprintln!(
+            "{} this backend deletes the secret right away, `--recovery-days` is ignored",
+            style("Warning:").yellow().bold()
+        );
+    }
+    let recovery_days = recovery_days.unwrap_or(DEFAULT_RECOVERY_DAYS);
+    let prompt = if window {
+        format!(
+            "Delete {} after a recovery window of {} days?",
+            style(&secret_id).cyan(),
+            recovery_days
+        )
+    } else {
+        format!(
+            "Delete {} now? It can be brought back with `tc-secrets restore`.",
+            style(&secret_id).cyan()
+        )
+    };
+
+    if !yes
+        && !dialoguer::Confirm::new()
+            .with_prompt(prompt)
+            .default(false)
+            .interact()
+            .expect("Failed to confirm deletion")
+    {
+        return Err(tools::CliError::AbortedError(
+            "Deletion cancelled. The secret was not changed.".to_string(),
+        ));
+    }
+
+    let deletion_date = store.delete(secret_id.clone(), recovery_days).await?;
+
+    if !window {
+        println!(
+            "Remote secret {} has been deleted. Run `tc-secrets restore {}` to bring it back.",
+            style(&secret_id).cyan(),
+            secret_id
+        );
+        return Ok(Outcome::Done);
+    }
+    println!(
+        "Remote secret {} will be deleted at {}. Run `tc-secrets restore {}` to keep it.",
+        style(&secret_id).cyan(),
+        deletion_date
+            .as_deref()
+            .unwrap_or("the end of the recovery window"),
+        secret_id
+    );
+
+    Ok(Outcome::Done)
+}
+
+pub async fn restore(
+    store: &dyn tools::SecretStore,
+    secret_id: String,
+) -> Result<Outcome, tools::CliError> {
+    store.restore(secret_id.clone()).await?;
+
+    println!(
+        "Remote secret {} has been restored",
+        style(&secret_id).cyan()
+    );
+
+    Ok(Outcome::Done)
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use console::style;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::{Outcome, detect_changes, env_file_ids, load_remote_env_file};
+use crate::tools;
+
+#[derive(Serialize)]
+struct FileStatus {
+    file: String,
+    secret_id: Option<String>,
+    field_id: Option<String>,
+    state: &'static str,
+    local_version: Option<u32>,
+    remote_version: Option<u32>,
+    local_changes: bool,
+    remote_newer: bool,
+    last_sync: Option<String>,
+    lease: Option<tools::Lease>,
+    steals: Vec<tools::Steal>,
+    error: Option<String>,
+}
+
+impl FileStatus {
+    fn new(target: &tools::Target) -> Self {
+        FileStatus {
+            file: target.filepath.clone(),
+            secret_id: target.secret_id.clone(),
+            field_id: target.field_id.clone(),
+            state: "unknown",
+            local_version: None,
+            remote_version: None,
+            local_changes: false,
+            remote_newer: false,
+            last_sync: None,
+            lease: None,
+            steals: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn in_sync(&self) -> bool {
+        self.state == "synced"
+    }
+}
+
+pub async fn run(
+    targets: &[(&dyn tools::SecretStore, &tools::Target)],
+    store_indexes: &[usize],
+    json: bool,
+) -> Result<Outcome, tools::CliError> {
+    // load every secret only once per store, files often share a secret
+    let mut secrets = HashMap::new();
+
+    let mut statuses = Vec::new();
+    let mut first_error = None;
+    for (&(store, target), &store_index) in targets.iter().zip(store_indexes) {
+        let mut status = FileStatus::new(target);
+        let cache = secrets.entry(store_index).or_default();
+        if let Err(e) = check_file(store, target, cache, &mut status).await {
+            status.state = "error";
+            status.error = Some(e.to_string());
+            first_error.get_or_insert(e);
+        }
+        statuses.push(status);
+    }
+
+    if json {
+        let content =
+            serde_json::to_string_pretty(&statuses).expect("Failed to serialize the status");
+        println!("{}", content);
+    } else {
+        display(&statuses);
+    }
+
+    // a file that could not be checked exits with the code of its error, not as out of sync
+    if let Some(e) = first_error {
+        return Err(e);
+    }
+    if statuses.iter().all(FileStatus::in_sync) {
+        Ok(Outcome::Done)
+    } else {
+        Ok(Outcome::ChangesPending)
+    }
+}
+
+async fn check_file(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    secrets: &mut HashMap<String, tools::Secret>,
+    status: &mut FileStatus,
+) -> Result<(), tools::CliError> {
+    let path = target.path();
+    let state = tools::SyncState::load(&path)?;
+    status.last_sync = state.synced_at().map(|s| s.to_string());
+
+    if !path.exists() {
+        status.state = "missing";
+        return Ok(());
+    }
+
+    let local_env_file = target.load_env_file()?;
+    let (secret_id, field_id) = env_file_ids(&local_env_file)?;
+    status.secret_id = Some(secret_id.clone());
+    status.field_id = Some(field_id.clone());
+    status.local_version = local_env_file.version;
+
+    if !secrets.contains_key(&secret_id) {
+        let secret = store.get(secret_id.clone(), None).await?;
+        secrets.insert(secret_id.clone(), secret);
+    }
+    let secret = &secrets[&secret_id];
+
+    let leases = tools::Leases::load(secret)?;
+    status.lease = leases.live(&field_id).cloned();
+    status.steals = leases.steals(&field_id).cloned().collect();
+
+    if !secret.list_fields().contains(&field_id) {
+        status.state = "not pushed";
+        status.local_changes = true;
+        return Ok(());
+    }
+
+    let remote_env_file = load_remote_env_file(secret, &target.encryption, &secret_id, &field_id)?;
+    status.remote_version = remote_env_file.version;
+
+    let (local_changed, remote_changed) = detect_changes(
+        &state,
+        &target.encryption,
+        &local_env_file,
+        &remote_env_file,
+    )?;
+    status.local_changes = local_changed;
+    status.remote_newer = remote_changed;
+    status.state = match (local_changed, remote_changed) {
+        (false, false) if local_env_file.body() == remote_env_file.body() => "synced",
+        (false, false) => "diverged",
+        (true, false) => "local changes",
+        (false, true) => "remote newer",
+        (true, true) => "both changed",
+    };
+
+    Ok(())
+}
+
+fn display(statuses: &[FileStatus]) {
+    for status in statuses {
+        let state = match status.state {
+            "synced" => style(status.state).green(),
+            "error" | "both changed" | "diverged" => style(status.state).red(),
+            _ => style(status.state).yellow(),
+        };
+        let version = |version: Option<u32>| {
+            version
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+
+        println!("{} {}", style(&status.file).magenta().bold(), state);
+        println!(
+            "  {:<13}{}",
+            "Remote",
+            style(format!(
+                "{}/{}",
+                status.secret_id.as_deref().unwrap_or("?"),
+                status.field_id.as_deref().unwrap_or("?")
+            ))
+            .cyan()
+        );
+        println!(
+            "  {:<13}local {}, remote {}",
+            "Version",
+            style(version(status.local_version)).cyan(),
+            style(version(status.remote_version)).cyan()
+        );
+        println!(
+            "  {:<13}{}",
+            "Last sync",
+            status.last_sync.as_deref().unwrap_or("never")
+        );
+        if let Some(lease) = &status.lease {
+            println!("  {:<13}{}", "Locked by", style(lease.describe()).yellow());
+        }
+        for steal in &status.steals {
+            println!(
+                "  {:<13}{} from {} at {}",
+                "Lease stolen",
+                steal.owner,
+                steal.stolen_from,
+                tools::format_time(steal.stolen_at)
+            );
+        }
+        if let Some(error) = &status.error {
+            println!("  {:<13}{}", "Error", style(error).red());
+        }
+    }
+
+    let out_of_sync = statuses.iter().filter(|s| !s.in_sync()).count();
+    if out_of_sync == 0 {
+        println!("{}", style("Everything is in sync.").green());
+    } else {
+        println!(
+            "{}",
+            style(format!(
+                "{} of {} files out of sync.",
+                out_of_sync,
+                statuses.len()
+            ))
+            .yellow()
+        );
+    }
+}
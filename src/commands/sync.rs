@@ -0,0 +1,244 @@
+use anyhow::Result;
+use console::style;
+
+use super::{
+    Outcome, backup_local_file, check_lease, detect_changes, env_file_ids, load_env_info,
+    load_remote_env_file, plan_backup,
+};
+use crate::tools;
+
+pub async fn run(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    dry_run: bool,
+    steal: bool,
+    backups: tools::Backups,
+) -> Result<Outcome, tools::CliError> {
+    let direction = sync_file(store, target, dry_run, true, steal, backups).await?;
+
+    if dry_run && direction != tools::Direction::UpToDate {
+        return Ok(Outcome::ChangesPending);
+    }
+    Ok(Outcome::Done)
+}
+
+pub async fn sync_file(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    dry_run: bool,
+    interactive: bool,
+    steal: bool,
+    backups: tools::Backups,
+) -> Result<tools::Direction, tools::CliError> {
+    let encryption = &target.encryption;
+    let filepath = target.filepath.as_str();
+    let path = target.path();
+    let _lock = tools::FileLock::acquire(&path)?;
+
+    // load the local secret file
+    let mut local_env_file = target.load_env_file()?;
+    let mut secret = load_env_info(store, filepath, &mut local_env_file, dry_run).await?;
+
+    let (secret_id, field_id) = env_file_ids(&local_env_file)?;
+
+    println!(
+        "Synchronizing {} with remote secret {}",
+        style(filepath).magenta(),
+        style(format!("{}/{}", secret_id, field_id)).cyan()
+    );
+
+    // load the remote secret from the store
+    let mut remote_env_file = load_remote_env_file(&secret, encryption, &secret_id, &field_id)?;
+
+    // compare the local and remote content with the last synced content
+    let mut state = tools::SyncState::load(&path)?;
+    let local_version = local_env_file.version.unwrap_or(0);
+    let remote_version = remote_env_file.version.unwrap_or(0);
+
+    let (local_edited, remote_changed) =
+        detect_changes(&state, encryption, &local_env_file, &remote_env_file)?;
+
+    let direction = if local_env_file.body() == remote_env_file.body() {
+        tools::Direction::UpToDate
+    } else if local_edited && remote_changed {
+        tools::Direction::Merge
+    } else if local_edited {
+        tools::Direction::Push
+    } else if remote_changed {
+        tools::Direction::Pull
+    } else {
+        tools::Direction::Diverged
+    };
+
+    let base_env_file = match direction {
+        tools::Direction::Merge => Some(
+            state
+                .base(encryption)?
+                .map(tools::EnvFile::new_remote)
+                .transpose()?
+                .ok_or_else(|| {
+                    tools::CliError::StateFileError(
+                        "The last synced content is missing.".to_string(),
+                    )
+                })?,
+        ),
+        _ => None,
+    };
+
+    if dry_run {
+        let remote_field = format!("remote field {}/{}", secret_id, field_id);
+        let local_file = format!("local file {}", filepath);
+
+        let mut plan = match direction {
+            tools::Direction::Pull => {
+                let mut plan = tools::Plan::new(direction, local_version, remote_version);
+                plan_backup(&mut plan, target, backups);
+                plan.writes.push(local_file);
+                plan
+            }
+            tools::Direction::Push => {
+                let new_version = local_version.max(remote_version + 1);
+                let mut plan = tools::Plan::new(direction, remote_version, new_version);
+                if new_version != local_version {
+                    plan.writes.push(local_file);
+                }
+                plan.writes.push(remote_field);
+                plan
+            }
+            tools::Direction::Merge => {
+                let new_version = local_version.max(remote_version) + 1;
+                let mut plan = tools::Plan::new(direction, local_version, new_version);
+                plan_backup(&mut plan, target, backups);
+                plan.writes.push(local_file);
+                plan.writes.push(remote_field);
+                plan
+            }
+            _ => tools::Plan::new(direction, local_version, local_version),
+        };
+
+        match direction {
+            tools::Direction::Push => {
+                plan.old_content = remote_env_file.body();
+                plan.new_content = local_env_file.body();
+            }
+            tools::Direction::Merge => {
+                let base_env_file = base_env_file.as_ref().unwrap();
+                let merge = tools::merge(
+                    &base_env_file.body(),
+                    &local_env_file.body(),
+                    &remote_env_file.body(),
+                );
+                plan.old_content = local_env_file.body();
+                plan.new_content = merge.content;
+                plan.conflicts = merge.conflicts.into_iter().map(|c| c.key).collect();
+            }
+            _ => {
+                plan.old_content = local_env_file.body();
+                plan.new_content = remote_env_file.body();
+            }
+        }
+
+        // only writing the remote field is affected by someone else's lease
+        if matches!(direction, tools::Direction::Push | tools::Direction::Merge) {
+            check_lease(store, &mut secret, &secret_id, &field_id, steal, true).await?;
+        }
+        plan.display();
+        return Ok(direction);
+    }
+
+    match direction {
+        tools::Direction::UpToDate => {
+            if remote_version > local_version {
+                local_env_file.version = Some(remote_version);
+                local_env_file.write()?;
+            }
+            state.record_sync(encryption, local_env_file.content.clone())?;
+
+            println!("The local secret file is up to date!");
+        }
+        tools::Direction::Merge => {
+            println!(
+                "The local secret file has unsynced edits and the remote secret changed since the last sync."
+            );
+            // refuse before asking to resolve any conflicts
+            check_lease(store, &mut secret, &secret_id, &field_id, steal, false).await?;
+
+            let base_env_file = base_env_file.unwrap();
+            let merge = tools::merge(
+                &base_env_file.content,
+                &local_env_file.content,
+                &remote_env_file.content,
+            );
+
+            // without a terminal, leave both sides untouched when keys conflict
+            if !interactive && !merge.conflicts.is_empty() {
+                let keys: Vec<String> = merge.conflicts.into_iter().map(|c| c.key).collect();
+                println!(
+                    "Conflicting changes on {}. Run `tc-secrets sync -f {}` to resolve them.",
+                    style(keys.join(", ")).yellow(),
+                    filepath
+                );
+                return Ok(direction);
+            }
+            local_env_file.content = tools::resolve_conflicts(merge)?;
+
+            let merged_version = local_version.max(remote_version) + 1;
+            local_env_file.version = Some(merged_version);
+            backup_local_file(target, backups)?;
+            local_env_file.write()?;
+
+            let encrypted_content = encryption.encrypt(local_env_file.content.clone())?;
+            store
+                .update_field(secret_id, &mut secret, field_id, encrypted_content)
+                .await?;
+            state.record_sync(encryption, local_env_file.content.clone())?;
+
+            println!(
+                "The merged secret file has been saved locally and remotely as version {}",
+                style(merged_version).cyan()
+            );
+        }
+        tools::Direction::Push => {
+            check_lease(store, &mut secret, &secret_id, &field_id, steal, false).await?;
+
+            // bump the version automatically when the local edits were not bumped
+            let new_version = local_version.max(remote_version + 1);
+            if new_version != local_version {
+                local_env_file.version = Some(new_version);
+                local_env_file.write()?;
+            }
+
+            let encrypted_content = encryption.encrypt(local_env_file.content.clone())?;
+            store
+                .update_field(secret_id, &mut secret, field_id, encrypted_content)
+                .await?;
+            state.record_sync(encryption, local_env_file.content.clone())?;
+
+            println!(
+                "The remote secret has been updated with the local secret file version {}",
+                style(new_version).cyan()
+            );
+        }
+        tools::Direction::Pull => {
+            remote_env_file.filepath = Some(path.clone());
+            remote_env_file.version = Some(remote_version);
+            backup_local_file(target, backups)?;
+            remote_env_file.write()?;
+            state.record_sync(encryption, remote_env_file.content.clone())?;
+
+            println!(
+                "The local secret file is outdated. Updated to version {}",
+                style(remote_version).cyan()
+            );
+        }
+        tools::Direction::Diverged => {
+            println!(
+                "The local secret file has same version but different content. Please run `tc-secrets diff` to see the differences."
+            );
+        }
+    }
+
+    state.save()?;
+
+    Ok(direction)
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use console::style;
+
+use super::{Outcome, env_file_ids, sync::sync_file};
+use crate::tools;
+
+pub async fn run(
+    targets: &[(&dyn tools::SecretStore, &tools::Target)],
+    interval: u64,
+    debounce: u64,
+) -> Result<Outcome, tools::CliError> {
+    // watch all targets concurrently, stopping them together on Ctrl-C
+    let results = futures::future::join_all(
+        targets
+            .iter()
+            .map(|&(store, target)| watch_file(store, target, interval, debounce)),
+    )
+    .await;
+    for result in results {
+        result?;
+    }
+
+    Ok(Outcome::Done)
+}
+
+async fn watch_file(
+    store: &dyn tools::SecretStore,
+    target: &tools::Target,
+    interval: u64,
+    debounce: u64,
+) -> Result<(), tools::CliError> {
+    let filepath = target.filepath.as_str();
+    let path = target.path();
+    let debounce = std::time::Duration::from_millis(debounce);
+
+    let mut watcher = tools::FileWatcher::new(&path)?;
+    sync_file(
+        store,
+        target,
+        false,
+        false,
+        false,
+        tools::Backups::default(),
+    )
+    .await?;
+
+    let (secret_id, _) = env_file_ids(&target.load_env_file()?)?;
+    let mut remote_version_id = store.describe(secret_id.clone()).await?.version_id;
+    watcher.drain();
+
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(interval));
+    ticker.tick().await;
+
+    println!(
+        "Watching {} for local edits and remote changes every {}s. Press Ctrl-C to stop.",
+        style(filepath).magenta(),
+        interval
+    );
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = watcher.changed(debounce) => {
+                println!("\nLocal edit detected in {}", style(filepath).magenta());
+            }
+            _ = ticker.tick() => {
+                // only the version is needed to notice a remote change
+                match store.describe(secret_id.clone()).await {
+                    Ok(info) if info.version_id == remote_version_id => continue,
+                    Ok(_) => println!(
+                        "\nRemote change detected in {}",
+                        style(&secret_id).cyan()
+                    ),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        // keep watching when a single sync fails, e.g. on network errors
+        if let Err(e) = sync_file(
+            store,
+            target,
+            false,
+            false,
+            false,
+            tools::Backups::default(),
+        )
+        .await
+        {
+            eprintln!("{}", e);
+        }
+        if let Ok(info) = store.describe(secret_id.clone()).await {
+            remote_version_id = info.version_id;
+        }
+
+        // ignore the events caused by our own writes
+        tokio::time::sleep(debounce).await;
+        watcher.drain();
+    }
+
+    Ok(())
+}
@@ -1,10 +1,14 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use console::style;
-use dialoguer::Select;
+use std::io::IsTerminal;
+use std::path::PathBuf;
 
+mod commands;
 mod tools;
 
+use commands::Outcome;
+
 #[derive(Parser)]
 #[command(
     name = "tc-secrets",
@@ -14,317 +18,730 @@ mod tools;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    #[arg(
+        help = "Fix insecure permissions of managed files",
+        long,
+        global = true
+    )]
+    fix: bool,
+    #[arg(
+        help = "Refuse to write secrets into files or directories with insecure permissions",
+        long,
+        global = true
+    )]
+    strict: bool,
+    #[arg(
+        help = "Secret storage backend, overrides the manifest [default: aws]",
+        long,
+        global = true,
+        value_parser = ["aws", "ssm", "s3", "vault", "git", "sqlite", "memory"]
+    )]
+    backend: Option<String>,
+    #[arg(
+        help = "Custom AWS endpoint, e.g. for LocalStack, overrides the manifest and AWS_ENDPOINT_URL",
+        long,
+        global = true
+    )]
+    endpoint_url: Option<String>,
+    #[arg(
+        help = "AWS profile to use instead of the default credential chain",
+        long,
+        global = true
+    )]
+    profile: Option<String>,
+    #[arg(help = "AWS region of the secrets", long, global = true)]
+    region: Option<String>,
+    #[arg(
+        help = "IAM role to assume, overrides the manifest",
+        long,
+        global = true
+    )]
+    role_arn: Option<String>,
+    #[arg(help = "External ID required by the role", long, global = true)]
+    external_id: Option<String>,
+    #[arg(
+        help = "ARN of the MFA device required by the role, prompts for a code",
+        long,
+        global = true
+    )]
+    mfa_serial: Option<String>,
+    #[arg(
+        help = "Attempts per AWS or Vault request, throttled and transient failures are retried with jittered backoff [default: 5]",
+        long,
+        global = true
+    )]
+    max_attempts: Option<u32>,
+    #[arg(
+        help = "Seconds to wait for each attempt of an AWS or Vault request [default: 30]",
+        long,
+        global = true
+    )]
+    timeout: Option<u64>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    #[command(about = "Authenticate with AWS Secrets Manager")]
+    #[command(
+        about = "Configure AWS credentials with `aws configure` and check them, or check the Vault token"
+    )]
     Auth,
+    #[command(about = "List the remote secrets and their fields")]
+    List {
+        #[arg(help = "Only list secrets whose name starts with the prefix", long)]
+        prefix: Option<String>,
+        #[arg(
+            help = "Only list secrets with the tag, given as KEY or KEY=VALUE",
+            long = "tag",
+            value_name = "TAG"
+        )]
+        tags: Vec<String>,
+        #[arg(help = "Print the secrets as JSON", long)]
+        json: bool,
+    },
+    #[command(about = "Create a new remote secret without fields")]
+    Create {
+        #[arg(help = "Name of the secret")]
+        secret_id: String,
+        #[arg(help = "Description of the secret", short, long)]
+        description: Option<String>,
+        #[arg(
+            help = "Tag of the secret, given as KEY or KEY=VALUE",
+            long = "tag",
+            value_name = "TAG"
+        )]
+        tags: Vec<String>,
+        #[arg(
+            help = "KMS key to encrypt the secret with [default: the AWS managed key]",
+            long
+        )]
+        kms_key_id: Option<String>,
+    },
+    #[command(about = "Schedule the deletion of a remote secret")]
+    Delete {
+        #[arg(help = "Name of the secret")]
+        secret_id: String,
+        #[arg(
+            help = "Days until the secret is deleted for good, it can be restored until then [default: 30]",
+            long,
+            value_parser = clap::value_parser!(i64).range(7..=30)
+        )]
+        recovery_days: Option<i64>,
+        #[arg(help = "Delete without asking for confirmation", short, long)]
+        yes: bool,
+    },
+    #[command(about = "Cancel the scheduled deletion of a remote secret")]
+    Restore {
+        #[arg(help = "Name of the secret")]
+        secret_id: String,
+    },
+    #[command(about = "Manage the fields of remote secrets")]
+    Field {
+        #[command(subcommand)]
+        command: FieldCommands,
+    },
+    #[command(about = "Merge the secrets file of the git backend field by field")]
+    MergeDriver {
+        #[command(subcommand)]
+        command: MergeDriverCommands,
+    },
     #[command(about = "Display differences between local and remote secret files")]
     Diff {
-        #[arg(help = "Path to the local secret file", short, long)]
-        filepath: String,
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
         #[arg(
-            help = "Optional password for decrypting the secret file",
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
             short,
-            long,
-            default_value = "secret"
+            long
         )]
-        password: String,
+        password: Option<String>,
+        #[arg(help = "Remote version ID to compare with", long)]
+        remote_version: Option<String>,
     },
     #[command(about = "Increment the version of the local secret file")]
     Bump {
-        #[arg(help = "Path to the local secret file", short, long)]
-        filepath: String,
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
     },
     #[command(about = "Reset the local secret file by the remote secret")]
     Reset {
-        #[arg(help = "Path to the local secret file", short, long)]
-        filepath: String,
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
         #[arg(
-            help = "Optional password for decrypting the secret file",
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
             short,
+            long
+        )]
+        password: Option<String>,
+        #[arg(help = "Show what would change without writing anything", long)]
+        dry_run: bool,
+        #[arg(
+            help = "Number of numbered backups to keep, 0 disables backups",
             long,
-            default_value = "secret"
+            default_value_t = 3
         )]
-        password: String,
+        backups: usize,
+        #[arg(help = "Encrypt the backups with the password", long)]
+        encrypt_backups: bool,
     },
     #[command(about = "Synchronize local secret file with the remote secret")]
     Sync {
-        #[arg(help = "Path to the local secret file", short, long)]
-        filepath: String,
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
+            short,
+            long
+        )]
+        password: Option<String>,
+        #[arg(help = "Show what would change without writing anything", long)]
+        dry_run: bool,
+        #[arg(
+            help = "Override a lease on the remote field held by someone else",
+            long
+        )]
+        steal: bool,
+        #[arg(
+            help = "Number of numbered backups to keep, 0 disables backups",
+            long,
+            default_value_t = 3
+        )]
+        backups: usize,
+        #[arg(help = "Encrypt the backups with the password", long)]
+        encrypt_backups: bool,
+    },
+    #[command(about = "Download the remote secret into the local secret file")]
+    Pull {
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
+            short,
+            long
+        )]
+        password: Option<String>,
+        #[arg(help = "Discard unsynced or newer local content", long)]
+        force: bool,
+        #[arg(help = "Show what would change without writing anything", long)]
+        dry_run: bool,
+        #[arg(
+            help = "Number of numbered backups to keep, 0 disables backups",
+            long,
+            default_value_t = 3
+        )]
+        backups: usize,
+        #[arg(help = "Encrypt the backups with the password", long)]
+        encrypt_backups: bool,
+    },
+    #[command(about = "Upload the local secret file to the remote secret")]
+    Push {
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
+            short,
+            long
+        )]
+        password: Option<String>,
+        #[arg(help = "Discard unsynced or newer remote content", long)]
+        force: bool,
         #[arg(
-            help = "Optional password for decrypting the secret file",
+            help = "Override a lease on the remote field held by someone else",
+            long
+        )]
+        steal: bool,
+        #[arg(help = "Show what would change without writing anything", long)]
+        dry_run: bool,
+    },
+    #[command(about = "List the remote versions of the secret file")]
+    History {
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
             short,
+            long
+        )]
+        password: Option<String>,
+        #[arg(
+            help = "Number of the newest versions to read",
             long,
-            default_value = "secret"
+            default_value_t = 20,
+            value_parser = clap::value_parser!(u16).range(1..)
         )]
-        password: String,
+        limit: u16,
+    },
+    #[command(about = "Restore a past remote version of the secret file as a new version")]
+    Rollback {
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
+            short,
+            long
+        )]
+        password: Option<String>,
+        #[arg(help = "Remote version ID to restore", long)]
+        version_id: String,
+        #[arg(help = "Show what would change without writing anything", long)]
+        dry_run: bool,
+        #[arg(
+            help = "Override a lease on the remote field held by someone else",
+            long
+        )]
+        steal: bool,
+        #[arg(help = "Roll back without asking for confirmation", short, long)]
+        yes: bool,
+    },
+    #[command(about = "Take an advisory lease on the remote field before editing it")]
+    Lock {
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(help = "Why the field is locked", short, long)]
+        reason: Option<String>,
+        #[arg(
+            help = "Minutes until the lease expires",
+            long,
+            default_value_t = 60,
+            value_parser = clap::value_parser!(i64).range(1..)
+        )]
+        minutes: i64,
+        #[arg(help = "Take over a lease held by someone else", long)]
+        steal: bool,
+    },
+    #[command(about = "Release the lease on the remote field")]
+    Unlock {
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(help = "Release a lease held by someone else", long)]
+        steal: bool,
+    },
+    #[command(about = "Show the sync state of every managed secret file")]
+    Status {
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
+            short,
+            long
+        )]
+        password: Option<String>,
+        #[arg(help = "Print the status as JSON", long)]
+        json: bool,
+    },
+    #[command(about = "Watch the local secret file and the remote secret and keep them in sync")]
+    Watch {
+        #[arg(help = "Path or manifest name of the local secret file", short, long)]
+        filepath: Option<String>,
+        #[arg(
+            help = "Password for decrypting the secret file, overrides the manifest [default: secret]",
+            short,
+            long
+        )]
+        password: Option<String>,
+        #[arg(
+            help = "Seconds between checks of the remote secret",
+            long,
+            default_value_t = 30,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        interval: u64,
+        #[arg(
+            help = "Milliseconds to wait for local edits to settle",
+            long,
+            default_value_t = 500
+        )]
+        debounce: u64,
     },
 }
 
-#[tokio::main]
-async fn main() {
-    let cli = Cli::parse();
+#[derive(Subcommand)]
+enum FieldCommands {
+    #[command(about = "List the fields of a remote secret")]
+    Ls {
+        #[arg(help = "Name of the secret")]
+        secret_id: String,
+    },
+    #[command(about = "Remove a field from a remote secret")]
+    Rm {
+        #[arg(help = "Field to remove, given as SECRET/FIELD")]
+        field: String,
+        #[arg(help = "Remove without asking for confirmation", short, long)]
+        yes: bool,
+    },
+    #[command(about = "Rename a field or move it to another secret")]
+    Mv {
+        #[arg(help = "Field to move, given as SECRET/FIELD")]
+        from: String,
+        #[arg(help = "New location of the field, given as SECRET/FIELD")]
+        to: String,
+        #[arg(help = "Overwrite an existing field at the new location", long)]
+        force: bool,
+    },
+    #[command(about = "Copy a field within a secret or to another secret")]
+    Cp {
+        #[arg(help = "Field to copy, given as SECRET/FIELD")]
+        from: String,
+        #[arg(help = "Location of the copy, given as SECRET/FIELD")]
+        to: String,
+        #[arg(help = "Overwrite an existing field at the new location", long)]
+        force: bool,
+    },
+}
 
-    if let Err(e) = run(cli).await {
-        eprintln!("{}", e);
-        std::process::exit(1);
-    }
+#[derive(Subcommand)]
+enum MergeDriverCommands {
+    #[command(about = "Register the merge driver in the git repository of the secrets file")]
+    Install,
+    #[command(about = "Merge two versions of the secrets file, called by git")]
+    Run {
+        #[arg(help = "Common ancestor of both versions")]
+        base: PathBuf,
+        #[arg(help = "Our version, overwritten with the result")]
+        ours: PathBuf,
+        #[arg(help = "Their version")]
+        theirs: PathBuf,
+    },
 }
 
-async fn run(cli: Cli) -> Result<(), tools::CliError> {
-    let mut aws_client = tools::AWS::new().await?;
+impl Commands {
+    // commands that write secrets into the local files
+    fn writes_local_files(&self) -> bool {
+        matches!(
+            self,
+            Commands::Bump { .. }
+                | Commands::Reset { .. }
+                | Commands::Sync { .. }
+                | Commands::Pull { .. }
+                | Commands::Push { .. }
+                | Commands::Watch { .. }
+        )
+    }
 
-    match cli.command {
-        Commands::Auth => {
-            // only reauthenticate if the client was created from cache
-            if aws_client.from_cache {
-                aws_client.reauthenticate().await?;
-            }
+    // commands whose output is parsed by scripts
+    fn prints_json(&self) -> bool {
+        matches!(
+            self,
+            Commands::List { json: true, .. } | Commands::Status { json: true, .. }
+        )
+    }
+
+    fn password(&self) -> Option<String> {
+        match self {
+            Commands::Auth
+            | Commands::List { .. }
+            | Commands::Create { .. }
+            | Commands::Delete { .. }
+            | Commands::Restore { .. }
+            | Commands::Field { .. }
+            | Commands::MergeDriver { .. }
+            | Commands::Bump { .. }
+            | Commands::Lock { .. }
+            | Commands::Unlock { .. } => None,
+            Commands::Diff { password, .. }
+            | Commands::Reset { password, .. }
+            | Commands::Sync { password, .. }
+            | Commands::Pull { password, .. }
+            | Commands::Push { password, .. }
+            | Commands::History { password, .. }
+            | Commands::Rollback { password, .. }
+            | Commands::Status { password, .. }
+            | Commands::Watch { password, .. } => password.clone(),
         }
-        Commands::Diff { filepath, password } => {
-            let encryption = tools::Encryption::new(password);
+    }
 
-            // check if the file exists
-            let path = std::path::PathBuf::from(filepath.clone());
-            if !path.exists() {
-                return Err(tools::CliError::InvalidEnvFileError(format!(
-                    "The file '{}' does not exist.",
-                    filepath
-                )));
+    fn backups(&self) -> tools::Backups {
+        match self {
+            Commands::Reset {
+                backups,
+                encrypt_backups,
+                ..
             }
+            | Commands::Sync {
+                backups,
+                encrypt_backups,
+                ..
+            }
+            | Commands::Pull {
+                backups,
+                encrypt_backups,
+                ..
+            } => tools::Backups {
+                count: *backups,
+                encrypt: *encrypt_backups,
+            },
+            _ => tools::Backups::default(),
+        }
+    }
 
-            // load the local secret file
-            let env_file = tools::EnvFile::new_local(path)?;
-
-            let secret_id = env_file.secret_id.ok_or_else(|| {
-                tools::CliError::InvalidEnvFileError(
-                    "The secret file does not contain a secret ID.".to_string(),
-                )
-            })?;
-            let field_id = env_file.field_id.ok_or_else(|| {
-                tools::CliError::InvalidEnvFileError(
-                    "The secret file does not contain a field ID.".to_string(),
-                )
-            })?;
+    fn filepath(&self) -> Option<&str> {
+        match self {
+            Commands::Auth
+            | Commands::List { .. }
+            | Commands::Create { .. }
+            | Commands::Delete { .. }
+            | Commands::Restore { .. }
+            | Commands::Field { .. }
+            | Commands::MergeDriver { .. } => None,
+            Commands::Diff { filepath, .. }
+            | Commands::Bump { filepath }
+            | Commands::Reset { filepath, .. }
+            | Commands::Sync { filepath, .. }
+            | Commands::Pull { filepath, .. }
+            | Commands::Push { filepath, .. }
+            | Commands::History { filepath, .. }
+            | Commands::Rollback { filepath, .. }
+            | Commands::Lock { filepath, .. }
+            | Commands::Unlock { filepath, .. }
+            | Commands::Status { filepath, .. }
+            | Commands::Watch { filepath, .. } => filepath.as_deref(),
+        }
+    }
+}
 
-            // load the remote secret from AWS Secrets Manager
-            let aws_secret =
-                tools::AWSSecret::new(aws_client.load_secret(secret_id.clone()).await?)?;
+// clap exits with 2 on usage errors, and errors use the codes below 10
+const EXIT_CHANGES_PENDING: i32 = 10;
 
-            // decrypt the remote secret
-            let decrypted_remote_secret =
-                encryption.decrypt(aws_secret.load_field(field_id.clone())?)?;
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
 
-            // display diff
-            println!(
-                "Comparing local file {} with remote secret {}",
-                style(filepath).magenta(),
-                style(format!("{}/{}", secret_id, field_id)).cyan()
-            );
-            tools::display_diff(decrypted_remote_secret, env_file.content);
+    match run(cli).await {
+        Ok(Outcome::Done) => {}
+        Ok(Outcome::ChangesPending) => std::process::exit(EXIT_CHANGES_PENDING),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(e.exit_code());
         }
-        Commands::Bump { filepath } => {
-            let path = std::path::PathBuf::from(filepath.clone());
+    }
+}
 
-            if !path.exists() {
-                return Err(tools::CliError::InvalidEnvFileError(format!(
-                    "The file '{}' does not exist.",
-                    filepath
-                )));
+async fn run(cli: Cli) -> Result<Outcome, tools::CliError> {
+    let manifest = tools::Manifest::load()?;
+
+    // the command line takes precedence over the manifest, but keeps the options of its
+    // [backend] table when both name the same type
+    let cli_backend = cli
+        .backend
+        .as_deref()
+        .map(tools::Backend::from_name)
+        .transpose()?
+        .map(|backend| match &manifest {
+            Some(manifest) if manifest.backend.same_type(&backend) => manifest.backend.clone(),
+            Some(manifest) => backend.with_root(manifest.root()),
+            None => backend,
+        });
+    let backend = match &cli_backend {
+        Some(backend) => backend.clone(),
+        None => manifest
+            .as_ref()
+            .map(|manifest| manifest.backend.clone())
+            .unwrap_or_default(),
+    };
+    let aws_options = tools::AwsOptions {
+        profile: cli.profile.clone(),
+        region: cli.region.clone(),
+        endpoint_url: cli.endpoint_url.clone(),
+        role_arn: cli.role_arn.clone(),
+        external_id: cli.external_id.clone(),
+        mfa_serial: cli.mfa_serial.clone(),
+        max_attempts: cli.max_attempts,
+        timeout: cli.timeout,
+    };
+
+    if let Commands::MergeDriver { command } = cli.command {
+        return match command {
+            MergeDriverCommands::Install => commands::merge_driver::install(&backend),
+            MergeDriverCommands::Run { base, ours, theirs } => {
+                commands::merge_driver::run(&base, &ours, &theirs)
             }
+        };
+    }
 
-            // load the local secret file
-            let mut env_file = tools::EnvFile::new_local(path)?;
-
-            // increment the version
-            let new_version = env_file.version.unwrap_or(0) + 1;
-            env_file.version = Some(new_version);
+    // suggesting similar names lists every secret, which only helps someone reading the error
+    let suggest = std::io::stderr().is_terminal() && !cli.command.prints_json();
 
-            // write the updated file
-            env_file.write()?;
+    if let Commands::Auth = cli.command {
+        let options = match backend.with_aws_options(aws_options.clone()) {
+            // Vault tokens come from `vault login`, so only check them
+            tools::Backend::Vault(options) => {
+                tools::Vault::check(&options).await?;
+                return Ok(Outcome::Done);
+            }
+            tools::Backend::Aws(options) => options,
+            tools::Backend::Ssm(options) => options.aws,
+            tools::Backend::S3(options) => options.aws,
+            _ => aws_options,
+        };
+
+        // credentials are only configured interactively when explicitly asked for
+        tools::AWS::configure(&options).await?;
+        return Ok(Outcome::Done);
+    }
 
-            println!(
-                "Bumped the version of the secret file to {}",
-                style(new_version).cyan()
-            );
+    // these commands work on remote secrets instead of local files
+    match cli.command {
+        Commands::List { prefix, tags, json } => {
+            let store = backend.with_aws_options(aws_options).open(suggest).await?;
+            let filter = tools::SecretFilter::new(prefix, &tags);
+            return commands::list::run(store.as_ref(), &filter, json).await;
         }
-        Commands::Reset { filepath, password } => {
-            let encryption = tools::Encryption::new(password);
-            let path = std::path::PathBuf::from(filepath.clone());
-
-            // load the local secret file
-            let mut local_env_file = tools::EnvFile::new_local(path.clone())?;
-
-            let aws_secret = load_env_info(&aws_client, &filepath, &mut local_env_file).await?;
-
-            let secret_id = local_env_file.secret_id.ok_or_else(|| {
-                tools::CliError::InvalidEnvFileError(
-                    "The secret file does not contain a secret ID.".to_string(),
-                )
-            })?;
-            let field_id = local_env_file.field_id.ok_or_else(|| {
-                tools::CliError::InvalidEnvFileError(
-                    "The secret file does not contain a field ID.".to_string(),
-                )
-            })?;
-
-            // load the remote secret from AWS Secrets Manager
-            let decrypted_remote_secret =
-                encryption.decrypt(aws_secret.load_field(field_id.clone())?)?;
-
-            let mut remote_env_file = tools::EnvFile::new_remote(decrypted_remote_secret)?;
-            remote_env_file.secret_id = remote_env_file.secret_id.or(Some(secret_id.clone()));
-            remote_env_file.field_id = remote_env_file.field_id.or(Some(field_id.clone()));
-            remote_env_file.version = remote_env_file.version.or(Some(1));
-            remote_env_file.filepath = Some(path.clone());
-
-            if path.exists() {
-                if dialoguer::Confirm::new()
-                    .with_prompt(format!(
-                        "The file {} already exists. Do you want to make a `.bak` backup?",
-                        style(filepath.clone()).cyan()
-                    ))
-                    .default(true)
-                    .interact()
-                    .expect("Failed to confirm backup")
-                {
-                    // create a backup of the local file
-                    let backup_path = path.with_extension("bak");
-                    std::fs::copy(&path, &backup_path).map_err(|e| tools::CliError::IoError(e))?;
-                } else {
-                    println!(
-                        "Overwriting file {} with remote secret",
-                        style(filepath.clone()).cyan()
-                    );
+        Commands::Create {
+            secret_id,
+            description,
+            tags,
+            kms_key_id,
+        } => {
+            let store = backend.with_aws_options(aws_options).open(suggest).await?;
+            return commands::secret::create(
+                store.as_ref(),
+                secret_id,
+                description,
+                &tags,
+                kms_key_id,
+            )
+            .await;
+        }
+        Commands::Delete {
+            secret_id,
+            recovery_days,
+            yes,
+        } => {
+            let store = backend.with_aws_options(aws_options).open(suggest).await?;
+            return commands::secret::delete(store.as_ref(), secret_id, recovery_days, yes).await;
+        }
+        Commands::Restore { secret_id } => {
+            let store = backend.with_aws_options(aws_options).open(suggest).await?;
+            return commands::secret::restore(store.as_ref(), secret_id).await;
+        }
+        Commands::Field { command } => {
+            let store = backend.with_aws_options(aws_options).open(suggest).await?;
+            let store = store.as_ref();
+            let manifest = manifest.as_ref();
+            return match command {
+                FieldCommands::Ls { secret_id } => commands::field::ls(store, secret_id).await,
+                FieldCommands::Rm { field, yes } => {
+                    commands::field::rm(store, manifest, field, yes).await
                 }
-            }
-
-            remote_env_file.write()?;
-
-            println!(
-                "Local secret file has been reset by remote secret {}",
-                style(format!("{}/{}", secret_id, field_id)).cyan()
-            );
+                FieldCommands::Mv { from, to, force } => {
+                    commands::field::transfer(store, manifest, from, to, force, false).await
+                }
+                FieldCommands::Cp { from, to, force } => {
+                    commands::field::transfer(store, manifest, from, to, force, true).await
+                }
+            };
         }
-        Commands::Sync { filepath, password } => {
-            let encryption = tools::Encryption::new(password);
-            let path = std::path::PathBuf::from(filepath.clone());
-
-            // load the local secret file
-            let mut local_env_file = tools::EnvFile::new_local(path.clone())?;
-            let mut aws_secret = load_env_info(&aws_client, &filepath, &mut local_env_file).await?;
-
-            let secret_id = local_env_file.secret_id.ok_or_else(|| {
-                tools::CliError::InvalidEnvFileError(
-                    "The secret file does not contain a secret ID.".to_string(),
-                )
-            })?;
-            let field_id = local_env_file.field_id.ok_or_else(|| {
-                tools::CliError::InvalidEnvFileError(
-                    "The secret file does not contain a field ID.".to_string(),
-                )
-            })?;
-
-            println!(
-                "Synchronizing with remote secret {}",
-                style(format!("{}/{}", secret_id, field_id)).cyan()
-            );
-
-            // load the remote secret from AWS Secrets Manager
-            let decrypted_remote_secret =
-                encryption.decrypt(aws_secret.load_field(field_id.clone())?)?;
-
-            let mut remote_env_file = tools::EnvFile::new_remote(decrypted_remote_secret)?;
-            remote_env_file.secret_id = remote_env_file.secret_id.or(Some(secret_id.clone()));
-            remote_env_file.field_id = remote_env_file.field_id.or(Some(field_id.clone()));
-
-            // compare the local and remote versions
-            let local_version = local_env_file.version.clone().unwrap_or(0);
-            let remote_version = remote_env_file.version.clone().unwrap_or(0);
-
-            if local_version < remote_version {
-                remote_env_file.filepath = Some(path.clone());
-                remote_env_file.write()?;
+        _ => {}
+    }
 
-                println!(
-                    "The local secret file is outdated. Updated to version {}",
-                    style(remote_version).cyan()
-                );
-            } else if local_version > remote_version {
-                let encrypted_content = encryption.encrypt(local_env_file.content.clone())?;
-                aws_secret.put_field(field_id.clone(), encrypted_content.clone())?;
-                aws_client
-                    .put_secret(secret_id, aws_secret.to_string()?)
-                    .await?;
+    // resolve the files to work on from the arguments and the manifest
+    let targets = tools::Manifest::targets(
+        manifest.as_ref(),
+        cli.command.filepath(),
+        cli.command.password(),
+    )?;
+    // read-only commands only warn about insecure permissions, even in strict mode
+    let strict = cli.strict && cli.command.writes_local_files();
+    for target in &targets {
+        tools::check_permissions(&target.path(), cli.fix, strict)?;
+    }
 
-                println!(
-                    "The remote secret has been updated with the local secret file version {}",
-                    style(local_version).cyan()
-                );
-            } else if local_env_file.content != remote_env_file.content {
-                println!(
-                    "The local secret file has same version but different content. Please run `tc-secrets diff` to see the differences."
-                );
-            } else {
-                println!("The local secret file is up to date!");
-            }
+    // open one store per backend, files may use their own backend or assume different roles
+    let mut stores: Vec<(tools::Backend, Box<dyn tools::SecretStore>)> = Vec::new();
+    let mut store_indexes = Vec::new();
+    for target in &targets {
+        let backend = match (&cli_backend, &target.backend) {
+            (None, Some(file_backend)) => file_backend.clone(),
+            _ => backend.clone(),
         }
+        .with_aws_options(target.aws.clone())
+        .with_aws_options(aws_options.clone());
+        let idx = match stores.iter().position(|(opened, _)| *opened == backend) {
+            Some(idx) => idx,
+            None => {
+                let store = backend.open(suggest).await?;
+                stores.push((backend, store));
+                stores.len() - 1
+            }
+        };
+        store_indexes.push(idx);
     }
+    let jobs: Vec<(&dyn tools::SecretStore, &tools::Target)> = store_indexes
+        .iter()
+        .zip(&targets)
+        .map(|(&idx, target)| (stores[idx].1.as_ref(), target))
+        .collect();
 
-    Ok(())
-}
-
-async fn load_env_info(
-    aws_client: &tools::AWS,
-    filepath: &str,
-    env_file: &mut tools::EnvFile,
-) -> Result<tools::AWSSecret, tools::CliError> {
-    // get the secret ID from the local file
-    if env_file.secret_id.is_none() {
-        let remote_secrets = aws_client.list_secrets().await?;
-
-        let selection = Select::new()
-            .with_prompt(format!(
-                "No local file found at {}. Please select a secret ID to download:",
-                style(filepath).cyan()
-            ))
-            .items(&remote_secrets)
-            .default(0)
-            .interact()
-            .expect("Failed to select a secret ID");
-
-        env_file.secret_id = Some(remote_secrets[selection].clone());
+    // these commands work on all targets at once
+    match cli.command {
+        Commands::Status { json, .. } => {
+            return commands::status::run(&jobs, &store_indexes, json).await;
+        }
+        Commands::Watch {
+            interval, debounce, ..
+        } => {
+            return commands::watch::run(&jobs, interval, debounce).await;
+        }
+        _ => {}
     }
 
-    let secret_id = env_file.secret_id.clone().unwrap();
-
-    // create a new AWSSecret instance
-    let aws_secret = tools::AWSSecret::new(aws_client.load_secret(secret_id.clone()).await?)?;
-
-    // get the field ID from the local file
-    if env_file.field_id.is_none() {
-        let remote_fields = aws_secret.list_fields();
-
-        let selection = Select::new()
-            .with_prompt(format!(
-                "Please select a field ID to load from secret {}:",
-                style(secret_id.clone()).cyan()
-            ))
-            .items(&remote_fields)
-            .default(0)
-            .interact()
-            .expect("Failed to select a field ID");
-
-        env_file.field_id = Some(remote_fields[selection].clone());
-    }
+    let mut outcome = Outcome::Done;
+    for &(store, target) in &jobs {
+        if targets.len() > 1 {
+            println!("{}", style(format!("==> {}", target.filepath)).bold());
+        }
 
-    if env_file.version.is_none() {
-        // if local version is not initialized, rewrite the local file with headers
-        env_file.version = Some(0);
-        env_file.write()?;
+        let result = match &cli.command {
+            Commands::Diff { remote_version, .. } => {
+                commands::diff::run(store, target, remote_version.clone()).await?
+            }
+            Commands::Bump { .. } => commands::bump::run(target)?,
+            Commands::Reset { dry_run, .. } => {
+                commands::reset::run(store, target, *dry_run, cli.command.backups()).await?
+            }
+            Commands::Sync { dry_run, steal, .. } => {
+                commands::sync::run(store, target, *dry_run, *steal, cli.command.backups()).await?
+            }
+            Commands::Pull { force, dry_run, .. } => {
+                commands::pull::run(store, target, *force, *dry_run, cli.command.backups()).await?
+            }
+            Commands::Push {
+                force,
+                steal,
+                dry_run,
+                ..
+            } => commands::push::run(store, target, *force, *steal, *dry_run).await?,
+            Commands::History { limit, .. } => {
+                commands::history::run(store, target, usize::from(*limit)).await?
+            }
+            Commands::Rollback {
+                version_id,
+                dry_run,
+                steal,
+                yes,
+                ..
+            } => {
+                commands::rollback::run(store, target, version_id.clone(), *steal, *yes, *dry_run)
+                    .await?
+            }
+            Commands::Lock {
+                reason,
+                minutes,
+                steal,
+                ..
+            } => commands::lock::lock(store, target, reason.clone(), *minutes, *steal).await?,
+            Commands::Unlock { steal, .. } => commands::lock::unlock(store, target, *steal).await?,
+            Commands::Auth
+            | Commands::List { .. }
+            | Commands::Create { .. }
+            | Commands::Delete { .. }
+            | Commands::Restore { .. }
+            | Commands::Field { .. }
+            | Commands::MergeDriver { .. }
+            | Commands::Status { .. }
+            | Commands::Watch { .. } => unreachable!(),
+        };
+        outcome = outcome.and(result);
     }
 
-    Ok(aws_secret)
+    Ok(outcome)
 }
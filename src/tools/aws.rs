@@ -1,62 +1,403 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use anyhow::Result;
+use async_trait::async_trait;
+use aws_config::Region;
 use aws_config::SdkConfig;
+use aws_config::default_provider::region::DefaultRegionChain;
 use aws_config::meta::region::RegionProviderChain;
-use aws_config::profile::ProfileFileCredentialsProvider;
+use aws_config::retry::RetryConfig;
+use aws_config::timeout::TimeoutConfig;
 use aws_sdk_secretsmanager::Client;
+use aws_sdk_secretsmanager::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_secretsmanager::primitives::DateTimeFormat;
+use aws_sdk_secretsmanager::types::{Filter, FilterNameStringType, Tag};
 use aws_sdk_sts::Client as StsClient;
+use aws_sdk_sts::config::{Credentials, ProvideCredentials, SharedCredentialsProvider};
+use aws_sdk_sts::error::DisplayErrorContext;
 use console::style;
-use serde_json;
+use dialoguer::Input;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use super::error::CliError;
+use super::lease::lease_owner;
+use super::session::{Session, session_key};
+use super::store::{NewSecret, Secret, SecretFilter, SecretInfo, SecretStore, SecretVersion};
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct AWS {
     client: Client,
-    pub from_cache: bool,
+    // versions written by this process, to tell them apart from concurrent writes
+    written: Mutex<HashSet<String>>,
 }
 
-const PROFILE_NAME: &str = "tc-secrets-cli-profile";
+#[derive(Deserialize, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AwsOptions {
+    pub profile: Option<String>,
+    pub region: Option<String>,
+    pub endpoint_url: Option<String>,
+    // role to assume with the credentials of the profile
+    pub role_arn: Option<String>,
+    pub external_id: Option<String>,
+    pub mfa_serial: Option<String>,
+    // attempts per request, including retries of throttled and transient failures
+    pub max_attempts: Option<u32>,
+    // seconds to wait for each attempt
+    pub timeout: Option<u64>,
+}
+
+impl AwsOptions {
+    pub fn or(self, other: AwsOptions) -> AwsOptions {
+        // the external id and MFA device belong to the role they were given with
+        let (role_arn, external_id, mfa_serial) = match self.role_arn {
+            Some(role_arn) => (Some(role_arn), self.external_id, self.mfa_serial),
+            None => (
+                other.role_arn,
+                self.external_id.or(other.external_id),
+                self.mfa_serial.or(other.mfa_serial),
+            ),
+        };
+
+        AwsOptions {
+            profile: self.profile.or(other.profile),
+            region: self.region.or(other.region),
+            endpoint_url: self.endpoint_url.or(other.endpoint_url),
+            role_arn,
+            external_id,
+            mfa_serial,
+            max_attempts: self.max_attempts.or(other.max_attempts),
+            timeout: self.timeout.or(other.timeout),
+        }
+    }
+}
+
+// profile written by `auth` in earlier versions
+const LEGACY_PROFILE_NAME: &str = "tc-secrets-cli-profile";
+const DEFAULT_REGION: &str = "us-east-1";
+const CURRENT_STAGE: &str = "AWSCURRENT";
+const PREVIOUS_STAGE: &str = "AWSPREVIOUS";
+const SESSION_DURATION_SECS: i32 = 3600;
+const AUTH_HINT: &str = "Run `tc-secrets auth` to configure credentials.";
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const CONNECT_TIMEOUT_SECS: u64 = 5;
 
 impl AWS {
-    pub async fn new() -> Result<Self, CliError> {
-        let provider = ProfileFileCredentialsProvider::builder()
-            .profile_name(PROFILE_NAME)
-            .build();
-
-        let config: aws_config::SdkConfig =
-            aws_config::defaults(aws_config::BehaviorVersion::latest())
-                .credentials_provider(provider)
-                .region(RegionProviderChain::default_provider().or_else("us-east-1"))
-                .load()
-                .await;
-
-        let (client, from_cache) = match Self::report_account_info(&config).await {
-            Ok(()) => (Client::new(&config), true),
-            Err(_e) => {
-                println!("AWS credentials expired or invalid. Authenticating...");
-                (Self::authenticate().await?, false)
+    pub async fn new(options: &AwsOptions) -> Result<Self, CliError> {
+        let config = Self::sdk_config(options).await?;
+
+        Ok(AWS {
+            client: Client::new(&config),
+            written: Mutex::default(),
+        })
+    }
+
+    // checked credentials for clients of any AWS service
+    pub async fn sdk_config(options: &AwsOptions) -> Result<SdkConfig, CliError> {
+        let error = match Self::connect(options).await {
+            Ok(config) => return Ok(config),
+            // other credentials won't help when AWS can't be reached
+            Err(
+                e @ (CliError::NetworkError(_)
+                | CliError::TimeoutError(_)
+                | CliError::ThrottlingError(_)),
+            ) => return Err(e),
+            Err(e) => e,
+        };
+
+        // fall back to the profile of earlier versions unless a profile was chosen
+        if options.profile.is_none() && std::env::var_os("AWS_PROFILE").is_none() {
+            let legacy = AwsOptions {
+                profile: Some(LEGACY_PROFILE_NAME.to_string()),
+                ..options.clone()
+            };
+            if let Ok(config) = Self::connect(&legacy).await {
+                return Ok(config);
+            }
+        }
+
+        let message = match error {
+            CliError::AwsAuthError(message) => message,
+            error => error.to_string(),
+        };
+        Err(CliError::AwsAuthError(if message.ends_with(AUTH_HINT) {
+            message
+        } else {
+            format!("{}. {}", message, AUTH_HINT)
+        }))
+    }
+
+    pub async fn configure(options: &AwsOptions) -> Result<Self, CliError> {
+        // interactively store credentials in the shared AWS config files
+        let mut command = std::process::Command::new("aws");
+        command.arg("configure");
+        if let Some(profile) = &options.profile {
+            command.args(["--profile", profile]);
+        }
+        let configure_status = command
+            .status()
+            .map_err(|e| CliError::AwsAuthError(e.to_string()))?;
+
+        if !configure_status.success() {
+            return Err(CliError::AwsAuthError(
+                "Failed to configure AWS profile.".to_string(),
+            ));
+        }
+
+        let config = Self::connect(options).await?;
+
+        Ok(AWS {
+            client: Client::new(&config),
+            written: Mutex::default(),
+        })
+    }
+
+    async fn connect(options: &AwsOptions) -> Result<SdkConfig, CliError> {
+        let mut config = Self::load_config(options).await;
+        if let Some(role_arn) = &options.role_arn {
+            config = Self::assume_role(config, options, role_arn).await?;
+        }
+
+        Self::report_account_info(&config).await?;
+        Ok(config)
+    }
+
+    async fn assume_role(
+        config: SdkConfig,
+        options: &AwsOptions,
+        role_arn: &str,
+    ) -> Result<SdkConfig, CliError> {
+        // reuse the session of an earlier command so MFA is only asked for once per session
+        let key = session_key(&[
+            options.profile.as_deref(),
+            Some(role_arn),
+            options.external_id.as_deref(),
+            options.mfa_serial.as_deref(),
+        ]);
+        let session = match Session::load(&key) {
+            Some(session) => session,
+            None => {
+                let session = Self::start_session(&config, options, role_arn).await?;
+                session.save(&key)?;
+                session
             }
         };
-        Ok(AWS { client, from_cache })
+
+        let credentials = Credentials::new(
+            session.access_key_id,
+            session.secret_access_key,
+            Some(session.session_token),
+            Some(UNIX_EPOCH + Duration::from_secs(session.expires_at.max(0) as u64)),
+            "tc-secrets-session",
+        );
+
+        Ok(config
+            .into_builder()
+            .credentials_provider(SharedCredentialsProvider::new(credentials))
+            .build())
     }
 
-    pub async fn reauthenticate(&mut self) -> Result<(), CliError> {
-        let client = Self::authenticate().await?;
-        self.client = client;
+    async fn start_session(
+        config: &SdkConfig,
+        options: &AwsOptions,
+        role_arn: &str,
+    ) -> Result<Session, CliError> {
+        // make sure there are credentials to assume the role with before asking for a code
+        let provider = config
+            .credentials_provider()
+            .ok_or_else(|| CliError::AwsAuthError("no credentials were found".to_string()))?;
+        provider
+            .provide_credentials()
+            .await
+            .map_err(|e| CliError::AwsAuthError(e.to_string()))?;
 
+        let token_code = match &options.mfa_serial {
+            Some(mfa_serial) => Some(
+                Input::<String>::new()
+                    .with_prompt(format!("MFA code for {}", mfa_serial))
+                    .interact_text()
+                    .map_err(|e| {
+                        CliError::AwsAuthError(format!("cannot read the MFA code: {}", e))
+                    })?,
+            ),
+            None => None,
+        };
+
+        let resp = StsClient::new(config)
+            .assume_role()
+            .role_arn(role_arn)
+            .role_session_name(session_name())
+            .set_external_id(options.external_id.clone())
+            .set_serial_number(options.mfa_serial.clone())
+            .set_token_code(token_code)
+            .duration_seconds(SESSION_DURATION_SECS)
+            .send()
+            .await
+            .map_err(|e| {
+                CliError::AwsAuthError(format!(
+                    "cannot assume role {}: {}",
+                    role_arn,
+                    DisplayErrorContext(e)
+                ))
+            })?;
+
+        let credentials = resp.credentials().ok_or_else(|| {
+            CliError::AwsAuthError(format!("no credentials returned for role {}", role_arn))
+        })?;
+
+        Ok(Session {
+            access_key_id: credentials.access_key_id().to_string(),
+            secret_access_key: credentials.secret_access_key().to_string(),
+            session_token: credentials.session_token().to_string(),
+            expires_at: credentials.expiration().secs(),
+        })
+    }
+
+    async fn version_stages(
+        &self,
+        secret_id: &str,
+    ) -> Result<HashMap<String, Vec<String>>, CliError> {
+        let resp = self
+            .client
+            .describe_secret()
+            .secret_id(secret_id)
+            .send()
+            .await
+            .map_err(|e| secret_error(e, secret_id))?;
+
+        Ok(resp.version_ids_to_stages().cloned().unwrap_or_default())
+    }
+
+    async fn load_config(options: &AwsOptions) -> SdkConfig {
+        // the standard credential chain: env vars, profiles, SSO, web identity and instance roles
+        let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+        let mut region_chain = DefaultRegionChain::builder();
+        if let Some(profile) = &options.profile {
+            loader = loader.profile_name(profile);
+            region_chain = region_chain.profile_name(profile);
+        }
+
+        loader = match &options.region {
+            Some(region) => loader.region(Region::new(region.clone())),
+            None => loader.region(
+                RegionProviderChain::first_try(region_chain.build()).or_else(DEFAULT_REGION),
+            ),
+        };
+
+        // the standard retry mode backs off exponentially with full jitter
+        let timeout = options.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS);
+        loader = loader
+            .retry_config(
+                RetryConfig::standard()
+                    .with_max_attempts(options.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1)),
+            )
+            .timeout_config(
+                TimeoutConfig::builder()
+                    .connect_timeout(Duration::from_secs(timeout.min(CONNECT_TIMEOUT_SECS)))
+                    .operation_attempt_timeout(Duration::from_secs(timeout))
+                    .build(),
+            );
+
+        // the endpoint applies to every client created from the config, e.g. STS.
+        // Without one, the SDK still honors AWS_ENDPOINT_URL.
+        if let Some(endpoint_url) = &options.endpoint_url {
+            loader = loader.endpoint_url(endpoint_url);
+        }
+
+        loader.load().await
+    }
+
+    async fn report_account_info(config: &SdkConfig) -> Result<(), CliError> {
+        let client = StsClient::new(config);
+        let resp = client
+            .get_caller_identity()
+            .send()
+            .await
+            .map_err(|e| sdk_error(e, CliError::AwsAuthError))?;
+
+        // on stderr, so the output of commands like `list --json` stays parseable
+        eprintln!(
+            "AWS Account ID: {}\nAWS User ID: {}\n",
+            style(resp.account().unwrap_or("Unknown")).cyan(),
+            style(resp.user_id().unwrap_or("Unknown")).cyan()
+        );
         Ok(())
     }
+}
+
+#[async_trait]
+impl SecretStore for AWS {
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError> {
+        // narrow down the secrets on the server, the tag filters match keys and values separately
+        let mut filters = Vec::new();
+        if let Some(prefix) = &filter.name_prefix {
+            filters.push(server_filter(FilterNameStringType::Name, prefix));
+        }
+        for (key, value) in &filter.tags {
+            filters.push(server_filter(FilterNameStringType::TagKey, key));
+            if let Some(value) = value {
+                filters.push(server_filter(FilterNameStringType::TagValue, value));
+            }
+        }
+        let filters = (!filters.is_empty()).then_some(filters);
+
+        let mut secrets = Vec::new();
+        let mut next_token = None;
+
+        // page through all secrets
+        loop {
+            let resp = self
+                .client
+                .list_secrets()
+                .set_filters(filters.clone())
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(|e| sdk_error(e, CliError::AwsSecretsManagerError))?;
+
+            secrets.extend(resp.secret_list().iter().filter_map(|s| {
+                Some(SecretInfo {
+                    name: s.name()?.to_string(),
+                    description: s.description().map(String::from),
+                    last_changed: s
+                        .last_changed_date()
+                        .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok()),
+                    version_id: current_version(s.secret_versions_to_stages(), CURRENT_STAGE),
+                    tags: tag_map(s.tags()),
+                })
+            }));
+
+            next_token = resp.next_token().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        // pair tag keys with their values
+        secrets.retain(|info| filter.matches(info));
+        secrets.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(secrets)
+    }
 
-    pub async fn load_secret(&self, secret_id: String) -> Result<String, CliError> {
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError> {
         // send the request to get the secret value
         let resp = self
             .client
             .get_secret_value()
             .secret_id(secret_id.clone())
+            .set_version_id(version_id.clone())
             .send()
             .await
-            .map_err(|e| CliError::AwsSecretsManagerError(e.to_string()))?;
+            .map_err(|e| match version_id {
+                // AWS tells whether the secret or only the version is missing
+                Some(_) => sdk_error(e, CliError::AwsSecretsManagerError),
+                None => secret_error(e, &secret_id),
+            })?;
 
         // get the secret value
         let secret_value = resp.secret_string().ok_or_else(|| {
@@ -66,121 +407,337 @@ impl AWS {
             ))
         })?;
 
-        Ok(secret_value.to_string())
+        Secret::new(
+            secret_value.to_string(),
+            resp.version_id().map(String::from),
+        )
     }
 
-    pub async fn put_secret(
+    async fn put(
         &self,
         secret_id: String,
         secret_value: String,
-    ) -> Result<(), CliError> {
-        // send the request to put the secret value
-        self.client
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError> {
+        // make sure nobody has written the secret since we read it
+        if let Some(expected) = &expected_version {
+            let stages = self.version_stages(&secret_id).await?;
+            if current_version(Some(&stages), CURRENT_STAGE).as_ref() != Some(expected) {
+                return Err(CliError::RemoteConflictError(format!(
+                    "secret {} changed since it was read",
+                    secret_id
+                )));
+            }
+        }
+
+        // the token becomes the id of our version, so it's known even when the response is lost
+        let token = request_token(&secret_id);
+        let resp = self
+            .client
             .put_secret_value()
-            .secret_id(secret_id)
+            .secret_id(secret_id.clone())
             .secret_string(secret_value)
+            .client_request_token(&token)
             .send()
             .await
-            .map_err(|e| CliError::AwsSecretsManagerError(e.to_string()))?;
+            .map_err(|e| secret_error(e, &secret_id))?;
+        let version_id = resp.version_id().unwrap_or(&token).to_string();
+        self.written.lock().unwrap().insert(version_id.clone());
+
+        // Secrets Manager has no conditional writes, so verify that our version directly
+        // replaced the one we read. Otherwise another write slipped in between the check and
+        // the put, and the caller retries on top of the base returned by `rebase`.
+        if let Some(expected) = &expected_version {
+            let stages = self.version_stages(&secret_id).await?;
+            let current = current_version(Some(&stages), CURRENT_STAGE);
+            let previous = current_version(Some(&stages), PREVIOUS_STAGE);
+            if current.as_ref() != Some(&version_id) || previous.as_ref() != Some(expected) {
+                return Err(CliError::RemoteConflictError(format!(
+                    "secret {} was written concurrently",
+                    secret_id
+                )));
+            }
+        }
 
-        Ok(())
+        Ok(Some(version_id))
     }
 
-    pub async fn list_secrets(&self) -> Result<Vec<String>, CliError> {
-        // send the request to list secrets
+    async fn rebase(&self, secret_id: String) -> Result<Secret, CliError> {
+        let stages = self.version_stages(&secret_id).await?;
+        let current = current_version(Some(&stages), CURRENT_STAGE);
+        let ours = current
+            .as_ref()
+            .is_some_and(|current| self.written.lock().unwrap().contains(current));
+
+        match current_version(Some(&stages), PREVIOUS_STAGE) {
+            // our version replaced a write that landed after the check, so that write holds
+            // the latest fields, while the next put has to go on top of our version
+            Some(replaced) if ours => {
+                let mut base = self.get(secret_id, Some(replaced)).await?;
+                base.version_id = current;
+                Ok(base)
+            }
+            _ => self.get(secret_id, None).await,
+        }
+    }
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError> {
         let resp = self
             .client
-            .list_secrets()
+            .describe_secret()
+            .secret_id(secret_id.clone())
             .send()
             .await
-            .map_err(|e| CliError::AwsSecretsManagerError(e.to_string()))?;
+            .map_err(|e| secret_error(e, &secret_id))?;
+
+        Ok(SecretInfo {
+            name: resp.name().unwrap_or(&secret_id).to_string(),
+            description: resp.description().map(String::from),
+            last_changed: resp
+                .last_changed_date()
+                .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok()),
+            version_id: current_version(resp.version_ids_to_stages(), CURRENT_STAGE),
+            tags: tag_map(resp.tags()),
+        })
+    }
 
-        // extract the secret names from the response
-        let secrets = resp
-            .secret_list()
-            .iter()
-            .filter_map(|s| s.name().map(String::from))
-            .collect();
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError> {
+        let mut versions = Vec::new();
+        let mut next_token = None;
+
+        // page through all versions, including the deprecated ones without a stage
+        loop {
+            let resp = self
+                .client
+                .list_secret_version_ids()
+                .secret_id(secret_id.clone())
+                .include_deprecated(true)
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(|e| secret_error(e, &secret_id))?;
+
+            versions.extend(resp.versions().iter().filter_map(|v| {
+                Some(SecretVersion {
+                    version_id: v.version_id()?.to_string(),
+                    created_date: v
+                        .created_date()
+                        .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok()),
+                    stages: v.version_stages().to_vec(),
+                })
+            }));
+
+            next_token = resp.next_token().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
 
-        Ok(secrets)
-    }
+        // newest first
+        versions.sort_by(|a, b| b.created_date.cmp(&a.created_date));
 
-    async fn authenticate() -> Result<Client, CliError> {
-        // configure the profile
-        let configure_status = std::process::Command::new("aws")
-            .args(&["configure", "--profile", PROFILE_NAME])
-            .status()
-            .map_err(|e| CliError::AwsAuthError(e.to_string()))?;
+        Ok(versions)
+    }
 
-        if !configure_status.success() {
-            return Err(CliError::AwsAuthError(
-                "Failed to configure AWS profile.".to_string(),
-            ));
-        }
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError> {
+        let tags = new_secret
+            .tags
+            .iter()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect::<Vec<_>>();
 
-        // create the credentials provider
-        let provider = ProfileFileCredentialsProvider::builder()
-            .profile_name(PROFILE_NAME)
-            .build();
+        let resp = self
+            .client
+            .create_secret()
+            .name(new_secret.name.clone())
+            .set_description(new_secret.description.clone())
+            .set_kms_key_id(new_secret.kms_key_id.clone())
+            .set_tags((!tags.is_empty()).then_some(tags))
+            .secret_string("{}")
+            .send()
+            .await
+            .map_err(|e| sdk_error(e, CliError::AwsSecretsManagerError))?;
 
-        let config: aws_config::SdkConfig =
-            aws_config::defaults(aws_config::BehaviorVersion::latest())
-                .credentials_provider(provider)
-                .region(RegionProviderChain::default_provider().or_else("us-east-1"))
-                .load()
-                .await;
+        Ok(resp.version_id().map(String::from))
+    }
 
-        Self::report_account_info(&config).await?;
+    async fn delete(
+        &self,
+        secret_id: String,
+        recovery_days: i64,
+    ) -> Result<Option<String>, CliError> {
+        let resp = self
+            .client
+            .delete_secret()
+            .secret_id(&secret_id)
+            .recovery_window_in_days(recovery_days)
+            .send()
+            .await
+            .map_err(|e| secret_error(e, &secret_id))?;
 
-        Ok(Client::new(&config))
+        Ok(resp
+            .deletion_date()
+            .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok()))
     }
 
-    async fn report_account_info(config: &SdkConfig) -> Result<(), CliError> {
-        let client = StsClient::new(config);
-        let resp = client
-            .get_caller_identity()
+    async fn restore(&self, secret_id: String) -> Result<(), CliError> {
+        self.client
+            .restore_secret()
+            .secret_id(&secret_id)
             .send()
             .await
-            .map_err(|e| CliError::AwsAuthError(e.to_string()))?;
+            .map_err(|e| secret_error(e, &secret_id))?;
 
-        println!(
-            "AWS Account ID: {}\nAWS User ID: {}\n",
-            style(resp.account().unwrap_or("Unknown")).cyan(),
-            style(resp.user_id().unwrap_or("Unknown")).cyan()
-        );
         Ok(())
     }
 }
 
-pub struct AWSSecret {
-    pub data: HashMap<String, String>,
-}
+// turns SDK errors into typed errors with a hint, `service` wraps all other errors
+pub fn sdk_error<E, R>(error: SdkError<E, R>, service: fn(String) -> CliError) -> CliError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    match &error {
+        SdkError::TimeoutError(_) => return timeout_error(&error),
+        SdkError::DispatchFailure(failure) if failure.is_timeout() => return timeout_error(&error),
+        SdkError::DispatchFailure(failure) if failure.is_io() => {
+            return CliError::NetworkError(format!(
+                "{}. Check the connection and the endpoint",
+                error_chain(&error)
+            ));
+        }
+        _ => {}
+    }
 
-impl AWSSecret {
-    pub fn new(secret: String) -> Result<Self, CliError> {
-        let data: HashMap<String, String> = serde_json::from_str(&secret)
-            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?;
+    let (Some(code), Some(message)) = (error.code(), error.message()) else {
+        return service(error_chain(&error));
+    };
+    match code {
+        "AccessDeniedException" | "AccessDenied" => CliError::AccessDeniedError(format!(
+            "{}. Check the IAM permissions of the credentials",
+            message
+        )),
+        "UnrecognizedClientException"
+        | "InvalidClientTokenId"
+        | "InvalidAccessKeyId"
+        | "InvalidSignatureException"
+        | "SignatureDoesNotMatch"
+        | "ExpiredTokenException"
+        | "ExpiredToken" => CliError::AwsAuthError(format!("{}. {}", message, AUTH_HINT)),
+        "ThrottlingException" | "Throttling" | "TooManyRequestsException" | "SlowDown" => {
+            CliError::ThrottlingError(format!(
+                "{}. Gave up after retrying, try again later or raise --max-attempts",
+                message
+            ))
+        }
+        code if is_kms_error(code) => CliError::KmsError(format!(
+            "{}. Check the KMS key and the permissions to use it",
+            message
+        )),
+        code => service(format!("{}: {}", code, message)),
+    }
+}
 
-        Ok(AWSSecret { data })
+// like sdk_error, but a missing secret is a NotFoundError for the secret
+fn secret_error<E, R>(error: SdkError<E, R>, secret_id: &str) -> CliError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    match error.code() {
+        Some("ResourceNotFoundException") => CliError::NotFoundError(secret_id.to_string()),
+        _ => sdk_error(error, CliError::AwsSecretsManagerError),
     }
+}
+
+// Secrets Manager reports its own codes, the other services the ones of KMS
+fn is_kms_error(code: &str) -> bool {
+    matches!(
+        code,
+        "DecryptionFailure" | "EncryptionFailure" | "InvalidKeyId"
+    ) || code.starts_with("KMS")
+}
 
-    pub fn list_fields(&self) -> Vec<String> {
-        self.data.keys().cloned().collect()
+// the message of the error and of everything that caused it, without debug output
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut messages = vec![error.to_string()];
+    let mut source = error.source();
+    while let Some(error) = source {
+        messages.push(error.to_string());
+        source = error.source();
     }
+    messages.join(": ")
+}
 
-    pub fn load_field(&self, field_id: String) -> Result<String, CliError> {
-        self.data.get(field_id.as_str()).cloned().ok_or_else(|| {
-            CliError::AwsSecretsFormatError(format!("Field '{}' not found", field_id))
+fn timeout_error<E, R>(error: &SdkError<E, R>) -> CliError
+where
+    E: std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    CliError::TimeoutError(format!(
+        "{}. Raise --timeout to wait longer",
+        error_chain(error)
+    ))
+}
+
+fn server_filter(key: FilterNameStringType, value: &str) -> Filter {
+    Filter::builder().key(key).values(value).build()
+}
+
+fn tag_map(tags: &[Tag]) -> BTreeMap<String, String> {
+    tags.iter()
+        .filter_map(|tag| {
+            Some((
+                tag.key()?.to_string(),
+                tag.value().unwrap_or_default().to_string(),
+            ))
         })
-    }
+        .collect()
+}
 
-    pub fn put_field(&mut self, field_id: String, value: String) -> Result<(), CliError> {
-        self.data.insert(field_id, value);
-        Ok(())
-    }
+fn session_name() -> String {
+    // session names show up in CloudTrail and only allow a few special characters
+    format!("tc-secrets-{}", lease_owner())
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "+=,.@-_".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(64)
+        .collect()
+}
 
-    pub fn to_string(&self) -> Result<String, CliError> {
-        serde_json::to_string(&self.data)
-            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))
-    }
+// a unique ClientRequestToken, formatted like the UUIDs the SDK generates
+fn request_token(secret_id: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let digest = Sha256::digest(format!("{}:{}:{}", secret_id, std::process::id(), nanos));
+    let hex: String = digest
+        .iter()
+        .take(16)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn current_version(versions: Option<&HashMap<String, Vec<String>>>, stage: &str) -> Option<String> {
+    versions.and_then(|versions| {
+        versions
+            .iter()
+            .find(|(_, stages)| stages.iter().any(|s| s == stage))
+            .map(|(version_id, _)| version_id.clone())
+    })
 }
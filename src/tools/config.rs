@@ -1,7 +1,8 @@
 use anyhow::Result;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use super::error::CliError;
+use super::files::{STATE_DIR, write_atomic};
 
 pub struct EnvFile {
     pub filepath: Option<PathBuf>,
@@ -15,10 +16,14 @@ const SECRETS_VERSION_HEADER: &str = "#do-not-edit--secrets-version";
 const SECRETS_ID_HEADER: &str = "#do-not-edit--secrets-id";
 const SECRETS_FIELD_ID_HEADER: &str = "#do-not-edit--secrets-field-id";
 
+// directories that don't contain secret files of the project
+const SKIPPED_DIRS: [&str; 4] = [".git", "target", "node_modules", STATE_DIR];
+const MAX_ENV_FILE_SIZE: u64 = 1024 * 1024;
+
 impl EnvFile {
     pub fn new_local(filepath: PathBuf) -> Result<Self, CliError> {
         let mut env_file = if filepath.exists() {
-            let content = std::fs::read_to_string(&filepath).map_err(|e| CliError::IoError(e))?;
+            let content = std::fs::read_to_string(&filepath).map_err(CliError::IoError)?;
 
             EnvFile {
                 filepath: Some(filepath),
@@ -54,6 +59,54 @@ impl EnvFile {
         Ok(env_file)
     }
 
+    pub fn find_linked(root: &Path, secret_id: &str, field_id: &str) -> Vec<PathBuf> {
+        // local files below the root whose headers point at the field
+        let mut linked = Vec::new();
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                let path = entry.path();
+                if file_type.is_dir() {
+                    if !SKIPPED_DIRS.contains(&entry.file_name().to_string_lossy().as_ref()) {
+                        dirs.push(path);
+                    }
+                } else if file_type.is_file()
+                    && entry
+                        .metadata()
+                        .is_ok_and(|metadata| metadata.len() <= MAX_ENV_FILE_SIZE)
+                    && let Ok(env_file) = EnvFile::new_local(path.clone())
+                    && env_file.secret_id.as_deref() == Some(secret_id)
+                    && env_file.field_id.as_deref() == Some(field_id)
+                {
+                    linked.push(path);
+                }
+            }
+        }
+
+        linked.sort();
+        linked
+    }
+
+    pub fn body(&self) -> String {
+        // content without the headers managed by this tool
+        self.content
+            .lines()
+            .filter(|line| {
+                !line.starts_with(SECRETS_VERSION_HEADER)
+                    && !line.starts_with(SECRETS_ID_HEADER)
+                    && !line.starts_with(SECRETS_FIELD_ID_HEADER)
+            })
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
     fn parse(&mut self) -> Result<(), CliError> {
         // parse the header to extract version, secret_id and field_id
         for line in self.content.lines() {
@@ -149,7 +202,7 @@ impl EnvFile {
         self.content = lines.join("\n");
 
         if let Some(path) = self.filepath.clone() {
-            std::fs::write(&path, &self.content).map_err(|e| CliError::IoError(e))?;
+            write_atomic(&path, &self.content)?;
         }
 
         Ok(())
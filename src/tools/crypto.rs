@@ -1,25 +1,38 @@
 use anyhow::Result;
 use magic_crypt::{MagicCrypt256, MagicCryptTrait, new_magic_crypt};
+use sha2::{Digest, Sha256};
 
 use super::error::CliError;
 
+#[derive(Clone)]
 pub struct Encryption {
-    mcrypt: MagicCrypt256,
+    mcrypt: Option<MagicCrypt256>,
 }
 
 impl Encryption {
     pub fn new(password: String) -> Self {
         let mcrypt = new_magic_crypt!(password, 256);
-        Encryption { mcrypt }
+        Encryption {
+            mcrypt: Some(mcrypt),
+        }
+    }
+
+    pub fn none() -> Self {
+        // store the content as plain text
+        Encryption { mcrypt: None }
     }
 
     pub fn encrypt(&self, data: String) -> Result<String, CliError> {
+        let Some(mcrypt) = &self.mcrypt else {
+            return Ok(data);
+        };
+
         if data.is_empty() {
             return Ok("".to_string());
         }
 
         // encrypt content
-        let encrypted_content = self.mcrypt.encrypt_str_to_base64(&data);
+        let encrypted_content = mcrypt.encrypt_str_to_base64(&data);
 
         if encrypted_content.is_empty() {
             Err(CliError::EncryptionError(
@@ -31,13 +44,16 @@ impl Encryption {
     }
 
     pub fn decrypt(&self, data: String) -> Result<String, CliError> {
+        let Some(mcrypt) = &self.mcrypt else {
+            return Ok(data);
+        };
+
         if data.is_empty() {
             return Ok("".to_string());
         }
 
         // decrypt content
-        let content = self
-            .mcrypt
+        let content = mcrypt
             .decrypt_base64_to_string(data)
             .map_err(|e| CliError::DecryptionError(e.to_string()))?;
 
@@ -50,3 +66,11 @@ impl Encryption {
         }
     }
 }
+
+// hex digest used to derive stable names and cache keys, not to protect anything
+pub fn hash_content(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
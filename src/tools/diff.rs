@@ -2,6 +2,8 @@ use console::{Style, style};
 use similar::{ChangeTag, TextDiff};
 use std::fmt;
 
+use super::dotenv::parse_entries;
+
 struct Line(Option<usize>);
 
 impl fmt::Display for Line {
@@ -52,3 +54,34 @@ pub fn display_diff(current_content: String, compare_with: String) {
         println!("No differences found.");
     }
 }
+
+pub fn display_key_changes(current_content: &str, compare_with: &str) -> bool {
+    let current = parse_entries(current_content);
+    let compare = parse_entries(compare_with);
+    let mut different = false;
+
+    for (key, value) in &compare {
+        match current.iter().find(|(k, _)| k == key) {
+            None => {
+                different = true;
+                println!("  {} {}", style("+").green().bold(), style(key).green());
+            }
+            Some((_, current_value)) if current_value != value => {
+                different = true;
+                println!("  {} {}", style("~").yellow().bold(), style(key).yellow());
+            }
+            _ => {}
+        }
+    }
+    for (key, _) in &current {
+        if !compare.iter().any(|(k, _)| k == key) {
+            different = true;
+            println!("  {} {}", style("-").red().bold(), style(key).red());
+        }
+    }
+
+    if !different {
+        println!("No key changes.");
+    }
+    different
+}
@@ -0,0 +1,103 @@
+const EXPORT_PREFIX: &str = "export ";
+
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return None;
+    }
+
+    let trimmed = trimmed.strip_prefix(EXPORT_PREFIX).unwrap_or(trimmed);
+    let (key, value) = trimmed.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() {
+        return None;
+    }
+
+    Some((key.to_string(), value.to_string()))
+}
+
+pub fn parse_entries(content: &str) -> Vec<(String, String)> {
+    // later definitions of the same key win, matching how dotenv loaders behave
+    let mut entries: Vec<(String, String)> = Vec::new();
+    for (key, value) in content.lines().filter_map(parse_line) {
+        if let Some(entry) = entries.iter_mut().find(|(k, _)| *k == key) {
+            entry.1 = value;
+        } else {
+            entries.push((key, value));
+        }
+    }
+    entries
+}
+
+pub fn get_entry(content: &str, key: &str) -> Option<String> {
+    parse_entries(content)
+        .into_iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v)
+}
+
+pub fn set_entry(content: &str, key: &str, value: Option<&str>) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut found = false;
+
+    for line in content.lines() {
+        match parse_line(line) {
+            Some((k, _)) if k == key => {
+                // keep the first definition in place as it was written, e.g. with `export `,
+                // and drop duplicates
+                if let Some(value) = value
+                    && !found
+                {
+                    let (prefix, _) = line.split_once('=').unwrap();
+                    lines.push(format!("{}={}", prefix, value));
+                }
+                found = true;
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    if !found && let Some(value) = value {
+        lines.push(format!("{}={}", key, value));
+    }
+
+    lines.join("\n")
+}
+
+pub fn mask_value(value: Option<&str>) -> String {
+    match value {
+        None => "<unset>".to_string(),
+        Some(value) => format!("{} ({} chars)", "*".repeat(8), value.chars().count()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_entry, set_entry};
+
+    #[test]
+    fn values_are_replaced_in_place() {
+        let content = "# db\nexport HOST=old\nPORT=1";
+
+        assert_eq!(
+            set_entry(content, "HOST", Some("new")),
+            "# db\nexport HOST=new\nPORT=1"
+        );
+    }
+
+    #[test]
+    fn duplicates_are_dropped_and_missing_keys_appended() {
+        let content = "A=1\nB=2\nA=3";
+
+        assert_eq!(set_entry(content, "A", Some("4")), "A=4\nB=2");
+        assert_eq!(set_entry(content, "C", Some("5")), "A=1\nB=2\nA=3\nC=5");
+        // the last definition wins when reading, like dotenv loaders
+        assert_eq!(get_entry(content, "A").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn keys_without_a_value_are_removed() {
+        assert_eq!(set_entry("A=1\n# keep\nB=2\nA=3", "A", None), "# keep\nB=2");
+        assert_eq!(set_entry("A=1", "B", None), "A=1");
+    }
+}
@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum CliError {
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
@@ -10,10 +11,76 @@ pub enum CliError {
     DecryptionError(String),
     #[error("AWS Authentication Error: {0}")]
     AwsAuthError(String),
+    #[error("Access Denied: {0}")]
+    AccessDeniedError(String),
+    #[error("Secret not found: {0}")]
+    NotFoundError(String),
+    #[error("Throttled: {0}")]
+    ThrottlingError(String),
+    #[error("Timed out: {0}")]
+    TimeoutError(String),
+    #[error("Network Error: {0}")]
+    NetworkError(String),
+    #[error("KMS Error: {0}")]
+    KmsError(String),
     #[error("AWS Secrets Manager Error: {0}")]
     AwsSecretsManagerError(String),
+    #[error("AWS Parameter Store Error: {0}")]
+    AwsParameterStoreError(String),
+    #[error("AWS S3 Error: {0}")]
+    AwsS3Error(String),
+    #[error("Vault Error: {0}")]
+    VaultError(String),
+    #[error("Git File Error: {0}")]
+    GitFileError(String),
+    #[error("SQLite Error: {0}")]
+    SqliteError(String),
     #[error("AWS Secrets JSON Format Error: {0}")]
     AwsSecretsFormatError(String),
     #[error("Failed to parse the secrets file: {0}")]
     InvalidEnvFileError(String),
+    #[error("Remote Conflict Error: {0}")]
+    RemoteConflictError(String),
+    #[error("Field Conflict Error: {0}")]
+    FieldConflictError(String),
+    #[error("Refusing to overwrite: {0}")]
+    ForceRequiredError(String),
+    #[error("Refusing to write secrets into insecure locations: {0}")]
+    InsecurePermissionsError(String),
+    #[error("Failed to watch the secret file: {0}")]
+    WatchError(String),
+    #[error("Failed to read the sync state: {0}")]
+    StateFileError(String),
+    #[error("Aborted: {0}")]
+    AbortedError(String),
+    #[error("Invalid manifest: {0}")]
+    ManifestError(String),
+    #[error("Lease Error: {0}")]
+    LeaseHeldError(String),
+    #[error("Invalid field: {0}")]
+    InvalidFieldError(String),
+}
+
+impl CliError {
+    // scripts can tell classes of errors apart, 2 is taken by clap's usage errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::AwsAuthError(_) | CliError::AccessDeniedError(_) => 3,
+            CliError::NotFoundError(_) => 4,
+            CliError::RemoteConflictError(_)
+            | CliError::FieldConflictError(_)
+            | CliError::ForceRequiredError(_)
+            | CliError::LeaseHeldError(_) => 5,
+            CliError::ThrottlingError(_) => 6,
+            CliError::TimeoutError(_) | CliError::NetworkError(_) => 7,
+            CliError::KmsError(_) | CliError::EncryptionError(_) | CliError::DecryptionError(_) => {
+                8
+            }
+            CliError::ManifestError(_)
+            | CliError::InvalidEnvFileError(_)
+            | CliError::InvalidFieldError(_)
+            | CliError::AwsSecretsFormatError(_) => 9,
+            _ => 1,
+        }
+    }
 }
@@ -0,0 +1,236 @@
+use anyhow::Result;
+use console::style;
+use std::collections::hash_map::RandomState;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::crypto::Encryption;
+use super::error::CliError;
+
+pub const STATE_DIR: &str = ".tc-secrets";
+
+pub fn state_file_path(filepath: &Path, extension: &str) -> Result<PathBuf, CliError> {
+    let file_name = filepath.file_name().ok_or_else(|| {
+        CliError::InvalidEnvFileError(format!(
+            "'{}' is not a file path.",
+            filepath.to_string_lossy()
+        ))
+    })?;
+
+    let parent = filepath.parent().unwrap_or_else(|| Path::new(""));
+    Ok(parent
+        .join(STATE_DIR)
+        .join(format!("{}.{}", file_name.to_string_lossy(), extension)))
+}
+
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), CliError> {
+    let file_name = path.file_name().ok_or_else(|| {
+        CliError::InvalidEnvFileError(format!("'{}' is not a file path.", path.to_string_lossy()))
+    })?;
+    // the random suffix keeps threads and leftovers of crashed processes from colliding
+    let temp_path = path.with_file_name(format!(
+        ".{}.tmp-{}-{:016x}",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        RandomState::new().build_hasher().finish()
+    ));
+
+    // a replaced file keeps its permissions, tightening them is left to `--fix`,
+    // and new files are only readable by the owner
+    let result = (|| {
+        let mut file = create_private(&temp_path)?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result.map_err(CliError::IoError)
+}
+
+#[cfg(unix)]
+pub fn create_private(path: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    // new files are only readable by the owner
+    OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+pub fn create_private(path: &Path) -> std::io::Result<File> {
+    File::create_new(path)
+}
+
+#[cfg(unix)]
+pub fn create_private_dir(path: &Path) -> Result<(), CliError> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn create_private_dir(path: &Path) -> Result<(), CliError> {
+    std::fs::create_dir_all(path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn check_permissions(filepath: &Path, fix: bool, strict: bool) -> Result<(), CliError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let parent = match filepath.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    // neither the file nor its directory may be accessible by group or others
+    let mut insecure = Vec::new();
+    for (path, mask, secure_mode) in [(filepath, 0o077, 0o600), (parent, 0o077, 0)] {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            continue;
+        };
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & mask == 0 {
+            continue;
+        }
+
+        let new_mode = if secure_mode != 0 {
+            secure_mode
+        } else {
+            mode & !mask
+        };
+        if fix {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(new_mode))?;
+            println!(
+                "Fixed permissions of {} from {:o} to {:o}",
+                style(path.to_string_lossy()).cyan(),
+                mode,
+                new_mode
+            );
+        } else {
+            eprintln!(
+                "{} {} has insecure permissions {:o}. Run with `--fix` to change them to {:o}.",
+                style("Warning:").yellow().bold(),
+                style(path.to_string_lossy()).cyan(),
+                mode,
+                new_mode
+            );
+            insecure.push(path.to_string_lossy().to_string());
+        }
+    }
+
+    if strict && !insecure.is_empty() {
+        return Err(CliError::InsecurePermissionsError(insecure.join(", ")));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn check_permissions(_filepath: &Path, _fix: bool, _strict: bool) -> Result<(), CliError> {
+    Ok(())
+}
+
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    pub fn acquire(filepath: &Path) -> Result<Self, CliError> {
+        let path = state_file_path(filepath, "lock")?;
+        if let Some(parent) = path.parent() {
+            create_private_dir(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                println!(
+                    "Waiting for another tc-secrets process to release {}...",
+                    style(filepath.to_string_lossy()).cyan()
+                );
+                file.lock()?;
+            }
+            Err(TryLockError::Error(e)) => return Err(CliError::IoError(e)),
+        }
+
+        // the lock is released when the file is closed
+        Ok(FileLock { _file: file })
+    }
+}
+
+// numbered backups taken before a command replaces the content of a local file
+#[derive(Clone, Copy)]
+pub struct Backups {
+    pub count: usize,
+    pub encrypt: bool,
+}
+
+impl Default for Backups {
+    fn default() -> Self {
+        Backups {
+            count: 3,
+            encrypt: false,
+        }
+    }
+}
+
+pub fn backup_file(
+    filepath: &Path,
+    count: usize,
+    encryption: Option<&Encryption>,
+) -> Result<Option<PathBuf>, CliError> {
+    if count == 0 || !filepath.exists() {
+        return Ok(None);
+    }
+
+    let backup_path = |idx: usize| {
+        let mut name = filepath.as_os_str().to_owned();
+        name.push(format!(".bak.{}", idx));
+        PathBuf::from(name)
+    };
+
+    // rotate the existing backups, dropping the oldest one
+    let oldest = backup_path(count);
+    if oldest.exists() {
+        std::fs::remove_file(&oldest)?;
+    }
+    for idx in (1..count).rev() {
+        let path = backup_path(idx);
+        if path.exists() {
+            std::fs::rename(&path, backup_path(idx + 1))?;
+        }
+    }
+
+    let content = std::fs::read_to_string(filepath)?;
+    let content = match encryption {
+        Some(encryption) => encryption.encrypt(content)?,
+        None => content,
+    };
+
+    let path = backup_path(1);
+    write_atomic(&path, &content)?;
+
+    Ok(Some(path))
+}
@@ -0,0 +1,565 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_smithy_types::date_time::{DateTime, Format};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::error::CliError;
+use super::files::{FileLock, STATE_DIR, write_atomic};
+use super::store::{NewSecret, Secret, SecretFilter, SecretInfo, SecretStore, SecretVersion};
+
+const DEFAULT_PATH: &str = "secrets.enc.json";
+const MERGE_DRIVER: &str = "tc-secrets";
+const MERGE_DRIVER_COMMAND: &str = "tc-secrets merge-driver run %O %A %B";
+const CURRENT_STAGE: &str = "current";
+const PREVIOUS_STAGE: &str = "previous";
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GitOptions {
+    // relative to the directory of the manifest
+    #[serde(default = "default_path")]
+    pub path: PathBuf,
+}
+
+impl Default for GitOptions {
+    fn default() -> Self {
+        GitOptions {
+            path: default_path(),
+        }
+    }
+}
+
+fn default_path() -> PathBuf {
+    PathBuf::from(DEFAULT_PATH)
+}
+
+// sorted maps keep the file stable, so diffs only show what changed
+#[derive(Serialize, Deserialize, Default)]
+struct SecretsFile {
+    #[serde(default)]
+    secrets: BTreeMap<String, FileSecret>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+struct FileSecret {
+    version: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    updated: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deletion_date: Option<String>,
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+impl SecretsFile {
+    fn parse(content: &str) -> Result<Self, CliError> {
+        if content.trim().is_empty() {
+            return Ok(SecretsFile::default());
+        }
+        serde_json::from_str(content).map_err(|e| CliError::GitFileError(e.to_string()))
+    }
+
+    fn read(path: &Path) -> Result<Self, CliError> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SecretsFile::default()),
+            Err(e) => Err(CliError::IoError(e)),
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<(), CliError> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| CliError::GitFileError(e.to_string()))?;
+        write_atomic(path, &format!("{}\n", content))
+    }
+}
+
+pub struct GitFile {
+    path: PathBuf,
+}
+
+impl GitFile {
+    pub fn new(options: &GitOptions) -> Self {
+        GitFile {
+            path: options.path.clone(),
+        }
+    }
+
+    fn not_found(secret_id: &str) -> CliError {
+        CliError::NotFoundError(secret_id.to_string())
+    }
+
+    // secrets scheduled for deletion can only be restored
+    fn find<'a>(
+        file: &'a mut SecretsFile,
+        secret_id: &str,
+    ) -> Result<&'a mut FileSecret, CliError> {
+        let secret = file
+            .secrets
+            .get_mut(secret_id)
+            .ok_or_else(|| Self::not_found(secret_id))?;
+        if secret.deletion_date.is_some() {
+            return Err(CliError::GitFileError(format!(
+                "secret {} is scheduled for deletion",
+                secret_id
+            )));
+        }
+        Ok(secret)
+    }
+
+    // changes the file while holding its lock, so concurrent commands can't lose writes
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&mut SecretsFile) -> Result<T, CliError>,
+    ) -> Result<T, CliError> {
+        let _lock = FileLock::acquire(&self.path)?;
+        let mut file = SecretsFile::read(&self.path)?;
+        let result = change(&mut file)?;
+
+        // deletions take effect on the next write after the recovery window
+        let now = now().unwrap_or_default();
+        file.secrets
+            .retain(|_, secret| secret.deletion_date.as_ref().is_none_or(|date| *date > now));
+
+        file.write(&self.path)?;
+        Ok(result)
+    }
+
+    fn git(&self, args: &[&str]) -> Option<String> {
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    // every committed state of the file, newest first, empty outside of a git repository
+    fn commits(&self) -> Vec<SecretsFile> {
+        let Some(name) = self.path.file_name().map(|name| name.to_string_lossy()) else {
+            return Vec::new();
+        };
+        let Some(log) = self.git(&["log", "--format=%H", "--", &name]) else {
+            return Vec::new();
+        };
+
+        log.lines()
+            .filter_map(|commit| self.git(&["show", &format!("{}:./{}", commit, name)]))
+            .filter_map(|content| SecretsFile::parse(&content).ok())
+            .collect()
+    }
+
+    // the working copy first, then every earlier version found in the git history
+    fn versions(&self, secret_id: &str) -> Result<Vec<FileSecret>, CliError> {
+        let mut file = SecretsFile::read(&self.path)?;
+        let current = Self::find(&mut file, secret_id)?.clone();
+
+        let mut seen = BTreeSet::from([current.version]);
+        let mut versions = vec![current];
+        for mut file in self.commits() {
+            if let Some(secret) = file.secrets.remove(secret_id)
+                && seen.insert(secret.version)
+            {
+                versions.push(secret);
+            }
+        }
+
+        versions.sort_by_key(|secret| std::cmp::Reverse(secret.version));
+        Ok(versions)
+    }
+
+    // registers the field-level merge driver for the secrets file in the git repository and
+    // returns the files to commit
+    pub fn install_merge_driver(&self) -> Result<Vec<PathBuf>, CliError> {
+        let toplevel = self
+            .git(&["rev-parse", "--show-toplevel"])
+            .map(|toplevel| PathBuf::from(toplevel.trim()))
+            .ok_or_else(|| {
+                CliError::GitFileError(format!(
+                    "{} is not inside a git repository",
+                    self.path.to_string_lossy()
+                ))
+            })?;
+
+        // the command is looked up on the PATH, so the config survives reinstalls and upgrades
+        for (key, value) in [
+            ("name", "tc-secrets field-level merge"),
+            ("driver", MERGE_DRIVER_COMMAND),
+        ] {
+            self.git(&["config", &format!("merge.{}.{}", MERGE_DRIVER, key), value])
+                .ok_or_else(|| {
+                    CliError::GitFileError("failed to configure the merge driver".to_string())
+                })?;
+        }
+
+        // the file may not exist yet, so resolve its directory instead
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+            _ => std::env::current_dir()?,
+        };
+        let relative = dir
+            .strip_prefix(toplevel.canonicalize()?)
+            .map_err(|e| CliError::GitFileError(e.to_string()))?
+            .join(self.path.file_name().unwrap_or_default());
+        let pattern = format!(
+            "/{} merge={}",
+            relative.to_string_lossy().replace('\\', "/"),
+            MERGE_DRIVER
+        );
+
+        let attributes_path = toplevel.join(".gitattributes");
+        let attributes = std::fs::read_to_string(&attributes_path).unwrap_or_default();
+        if !attributes.lines().any(|line| line.trim() == pattern) {
+            let mut attributes = attributes;
+            if !attributes.is_empty() && !attributes.ends_with('\n') {
+                attributes.push('\n');
+            }
+            attributes.push_str(&pattern);
+            attributes.push('\n');
+            std::fs::write(&attributes_path, attributes)?;
+        }
+        let mut changed = vec![attributes_path];
+
+        // the sync snapshots next to the local files must not be committed with the secrets
+        let snapshot = format!("{}/snapshot", STATE_DIR);
+        if self.git(&["check-ignore", "-q", &snapshot]).is_none() {
+            let ignore_path = toplevel.join(".gitignore");
+            let mut ignore = std::fs::read_to_string(&ignore_path).unwrap_or_default();
+            if !ignore.is_empty() && !ignore.ends_with('\n') {
+                ignore.push('\n');
+            }
+            ignore.push_str(&format!("{}/\n", STATE_DIR));
+            std::fs::write(&ignore_path, ignore)?;
+            changed.push(ignore_path);
+        }
+
+        Ok(changed)
+    }
+}
+
+#[async_trait]
+impl SecretStore for GitFile {
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError> {
+        let file = SecretsFile::read(&self.path)?;
+
+        Ok(file
+            .secrets
+            .into_iter()
+            .filter(|(_, secret)| secret.deletion_date.is_none())
+            .map(|(name, secret)| info(name, secret))
+            .filter(|info| filter.matches(info))
+            .collect())
+    }
+
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError> {
+        let secret = match &version_id {
+            Some(version_id) => self
+                .versions(&secret_id)?
+                .into_iter()
+                .find(|secret| &secret.version.to_string() == version_id)
+                .ok_or_else(|| {
+                    CliError::GitFileError(format!(
+                        "version {} of secret {} not found",
+                        version_id, secret_id
+                    ))
+                })?,
+            None => {
+                let mut file = SecretsFile::read(&self.path)?;
+                Self::find(&mut file, &secret_id)?.clone()
+            }
+        };
+
+        Ok(Secret {
+            data: secret.fields.into_iter().collect(),
+            version_id: Some(secret.version.to_string()),
+        })
+    }
+
+    async fn put(
+        &self,
+        secret_id: String,
+        secret_value: String,
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError> {
+        let fields: BTreeMap<String, String> = serde_json::from_str(&secret_value)
+            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?;
+
+        self.update(|file| {
+            let secret = Self::find(file, &secret_id)?;
+
+            // the file lock makes the check and the write a single step
+            if let Some(expected) = &expected_version
+                && &secret.version.to_string() != expected
+            {
+                return Err(CliError::RemoteConflictError(format!(
+                    "secret {} changed since it was read",
+                    secret_id
+                )));
+            }
+
+            secret.version += 1;
+            secret.updated = now();
+            secret.fields = fields;
+            Ok(Some(secret.version.to_string()))
+        })
+    }
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError> {
+        let mut file = SecretsFile::read(&self.path)?;
+        let secret = Self::find(&mut file, &secret_id)?.clone();
+        Ok(info(secret_id, secret))
+    }
+
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError> {
+        Ok(self
+            .versions(&secret_id)?
+            .into_iter()
+            .enumerate()
+            .map(|(idx, secret)| SecretVersion {
+                version_id: secret.version.to_string(),
+                created_date: secret.updated,
+                stages: match idx {
+                    0 => vec![CURRENT_STAGE.to_string()],
+                    1 => vec![PREVIOUS_STAGE.to_string()],
+                    _ => Vec::new(),
+                },
+            })
+            .collect())
+    }
+
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError> {
+        if new_secret.kms_key_id.is_some() {
+            return Err(CliError::GitFileError(
+                "fields are encrypted locally, --kms-key-id is not supported".to_string(),
+            ));
+        }
+
+        self.update(|file| {
+            if file.secrets.contains_key(&new_secret.name) {
+                return Err(CliError::GitFileError(format!(
+                    "secret {} already exists",
+                    new_secret.name
+                )));
+            }
+
+            file.secrets.insert(
+                new_secret.name.clone(),
+                FileSecret {
+                    version: 1,
+                    updated: now(),
+                    description: new_secret.description.clone(),
+                    tags: new_secret.tags.clone(),
+                    ..FileSecret::default()
+                },
+            );
+            Ok(Some("1".to_string()))
+        })
+    }
+
+    async fn delete(
+        &self,
+        secret_id: String,
+        recovery_days: i64,
+    ) -> Result<Option<String>, CliError> {
+        self.update(|file| {
+            let secret = Self::find(file, &secret_id)?;
+
+            let now = DateTime::from(std::time::SystemTime::now()).secs();
+            secret.deletion_date = DateTime::from_secs(now + recovery_days * 24 * 60 * 60)
+                .fmt(Format::DateTime)
+                .ok();
+            Ok(secret.deletion_date.clone())
+        })
+    }
+
+    async fn restore(&self, secret_id: String) -> Result<(), CliError> {
+        self.update(|file| {
+            let secret = file
+                .secrets
+                .get_mut(&secret_id)
+                .ok_or_else(|| Self::not_found(&secret_id))?;
+            secret.deletion_date = None;
+            Ok(())
+        })
+    }
+}
+
+// merges both sides of a git merge field by field and writes the result over ours,
+// returns the values changed differently on both sides, which keep our value
+pub fn merge_secrets_files(
+    base: &Path,
+    ours: &Path,
+    theirs: &Path,
+) -> Result<Vec<String>, CliError> {
+    let base_file = SecretsFile::read(base)?;
+    let ours_file = SecretsFile::read(ours)?;
+    let theirs_file = SecretsFile::read(theirs)?;
+
+    let names: BTreeSet<&String> = [&base_file, &ours_file, &theirs_file]
+        .iter()
+        .flat_map(|file| file.secrets.keys())
+        .collect();
+
+    let mut conflicts = Vec::new();
+    let mut merged = SecretsFile::default();
+    for name in names {
+        let secret = merge_secret(
+            name,
+            base_file.secrets.get(name),
+            ours_file.secrets.get(name),
+            theirs_file.secrets.get(name),
+            &mut conflicts,
+        );
+        if let Some(secret) = secret {
+            merged.secrets.insert(name.clone(), secret);
+        }
+    }
+
+    merged.write(ours)?;
+    Ok(conflicts)
+}
+
+fn merge_secret(
+    name: &str,
+    base: Option<&FileSecret>,
+    ours: Option<&FileSecret>,
+    theirs: Option<&FileSecret>,
+    conflicts: &mut Vec<String>,
+) -> Option<FileSecret> {
+    let (Some(ours_secret), Some(theirs_secret)) = (ours, theirs) else {
+        // a secret removed on one side and changed on the other is kept
+        return match merge_value(base, ours, theirs) {
+            Some(secret) => secret.cloned(),
+            None => {
+                conflicts.push(name.to_string());
+                ours.or(theirs).cloned()
+            }
+        };
+    };
+    if let Some(secret) = merge_value(base, ours, theirs) {
+        return secret.cloned();
+    }
+
+    let mut merged = FileSecret {
+        // a version neither side has seen, so pending writes of both are detected
+        version: ours_secret.version.max(theirs_secret.version) + 1,
+        updated: ours_secret
+            .updated
+            .clone()
+            .max(theirs_secret.updated.clone()),
+        ..FileSecret::default()
+    };
+    merged.description = merge_or_keep(
+        &format!("{} (description)", name),
+        base.map(|secret| &secret.description),
+        Some(&ours_secret.description),
+        Some(&theirs_secret.description),
+        conflicts,
+    )
+    .flatten();
+    merged.deletion_date = merge_or_keep(
+        &format!("{} (deletion date)", name),
+        base.map(|secret| &secret.deletion_date),
+        Some(&ours_secret.deletion_date),
+        Some(&theirs_secret.deletion_date),
+        conflicts,
+    )
+    .flatten();
+
+    let tag_keys: BTreeSet<&String> = [base, ours, theirs]
+        .into_iter()
+        .flatten()
+        .flat_map(|secret| secret.tags.keys())
+        .collect();
+    for key in tag_keys {
+        if let Some(value) = merge_or_keep(
+            &format!("{} (tag {})", name, key),
+            base.and_then(|secret| secret.tags.get(key)),
+            ours_secret.tags.get(key),
+            theirs_secret.tags.get(key),
+            conflicts,
+        ) {
+            merged.tags.insert(key.clone(), value);
+        }
+    }
+
+    let field_ids: BTreeSet<&String> = [base, ours, theirs]
+        .into_iter()
+        .flatten()
+        .flat_map(|secret| secret.fields.keys())
+        .collect();
+    for field_id in field_ids {
+        if let Some(value) = merge_or_keep(
+            &format!("{}/{}", name, field_id),
+            base.and_then(|secret| secret.fields.get(field_id)),
+            ours_secret.fields.get(field_id),
+            theirs_secret.fields.get(field_id),
+            conflicts,
+        ) {
+            merged.fields.insert(field_id.clone(), value);
+        }
+    }
+
+    Some(merged)
+}
+
+// three-way merge of a single value, None when both sides changed it differently
+fn merge_value<'a, T: PartialEq>(
+    base: Option<&'a T>,
+    ours: Option<&'a T>,
+    theirs: Option<&'a T>,
+) -> Option<Option<&'a T>> {
+    if ours == theirs || theirs == base {
+        Some(ours)
+    } else if ours == base {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+fn merge_or_keep<T: PartialEq + Clone>(
+    label: &str,
+    base: Option<&T>,
+    ours: Option<&T>,
+    theirs: Option<&T>,
+    conflicts: &mut Vec<String>,
+) -> Option<T> {
+    match merge_value(base, ours, theirs) {
+        Some(value) => value.cloned(),
+        None => {
+            conflicts.push(label.to_string());
+            ours.cloned()
+        }
+    }
+}
+
+fn info(name: String, secret: FileSecret) -> SecretInfo {
+    SecretInfo {
+        name,
+        description: secret.description,
+        last_changed: secret.updated,
+        version_id: Some(secret.version.to_string()),
+        tags: secret.tags,
+    }
+}
+
+fn now() -> Option<String> {
+    DateTime::from(std::time::SystemTime::now())
+        .fmt(Format::DateTime)
+        .ok()
+}
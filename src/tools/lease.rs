@@ -0,0 +1,233 @@
+use anyhow::Result;
+use aws_smithy_types::date_time::{DateTime, Format};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::error::CliError;
+use super::store::Secret;
+
+// reserved entries of the secret JSON hold the lease of each field, so that locking different
+// fields at the same time changes different entries and doesn't conflict
+const LEASE_FIELD_PREFIX: &str = "tc-secrets:lease:";
+const MAX_STEALS: usize = 20;
+
+pub fn lease_field(field_id: &str) -> String {
+    format!("{}{}", LEASE_FIELD_PREFIX, field_id)
+}
+
+pub fn is_lease_field(key: &str) -> bool {
+    key.starts_with(LEASE_FIELD_PREFIX)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Lease {
+    pub owner: String,
+    pub reason: Option<String>,
+    pub expires_at: i64,
+}
+
+impl Lease {
+    pub fn describe(&self) -> String {
+        let mut description = format!("{} until {}", self.owner, format_time(self.expires_at));
+        if let Some(reason) = &self.reason {
+            description.push_str(&format!(" ({})", reason));
+        }
+        description
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Steal {
+    pub field_id: String,
+    pub owner: String,
+    pub stolen_from: String,
+    pub stolen_at: i64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct FieldLease {
+    #[serde(default)]
+    lease: Option<Lease>,
+    // the most recent overrides of someone else's lease
+    #[serde(default)]
+    steals: Vec<Steal>,
+}
+
+#[derive(Default)]
+pub struct Leases {
+    fields: HashMap<String, FieldLease>,
+}
+
+impl Leases {
+    pub fn load(secret: &Secret) -> Result<Self, CliError> {
+        let mut fields = HashMap::new();
+        for (key, content) in &secret.data {
+            let Some(field_id) = key.strip_prefix(LEASE_FIELD_PREFIX) else {
+                continue;
+            };
+            let field_lease = serde_json::from_str(content)
+                .map_err(|e| CliError::AwsSecretsFormatError(format!("{}: {}", key, e)))?;
+            fields.insert(field_id.to_string(), field_lease);
+        }
+
+        Ok(Leases { fields })
+    }
+
+    // the change of the reserved entry of the field, which is removed once it holds nothing
+    pub fn change(&self, field_id: &str) -> Result<(String, Option<String>), CliError> {
+        let value = match self.fields.get(field_id) {
+            Some(field_lease) if field_lease.lease.is_some() || !field_lease.steals.is_empty() => {
+                Some(
+                    serde_json::to_string(field_lease)
+                        .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?,
+                )
+            }
+            _ => None,
+        };
+
+        Ok((lease_field(field_id), value))
+    }
+
+    pub fn live(&self, field_id: &str) -> Option<&Lease> {
+        self.fields
+            .get(field_id)
+            .and_then(|field_lease| field_lease.lease.as_ref())
+            .filter(|lease| lease.expires_at > now())
+    }
+
+    pub fn held_by_other(&self, field_id: &str, owner: &str) -> Option<&Lease> {
+        self.live(field_id).filter(|lease| lease.owner != owner)
+    }
+
+    pub fn acquire(&mut self, field_id: &str, owner: &str, reason: Option<String>, seconds: i64) {
+        self.fields.entry(field_id.to_string()).or_default().lease = Some(Lease {
+            owner: owner.to_string(),
+            reason,
+            expires_at: now() + seconds,
+        });
+    }
+
+    pub fn release(&mut self, field_id: &str) -> Option<Lease> {
+        self.fields.get_mut(field_id)?.lease.take()
+    }
+
+    pub fn steal(&mut self, field_id: &str, owner: &str) -> Option<Lease> {
+        let lease = self.release(field_id)?;
+
+        let steals = &mut self.fields.entry(field_id.to_string()).or_default().steals;
+        steals.push(Steal {
+            field_id: field_id.to_string(),
+            owner: owner.to_string(),
+            stolen_from: lease.owner.clone(),
+            stolen_at: now(),
+        });
+        if steals.len() > MAX_STEALS {
+            steals.remove(0);
+        }
+
+        Some(lease)
+    }
+
+    pub fn steals(&self, field_id: &str) -> impl Iterator<Item = &Steal> {
+        self.fields
+            .get(field_id)
+            .into_iter()
+            .flat_map(|field_lease| &field_lease.steals)
+    }
+}
+
+pub fn lease_owner() -> String {
+    ["TC_SECRETS_OWNER", "USER", "USERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|owner| !owner.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+pub fn format_time(seconds: i64) -> String {
+    DateTime::from_secs(seconds)
+        .fmt(Format::DateTime)
+        .unwrap_or_else(|_| seconds.to_string())
+}
+
+fn now() -> i64 {
+    DateTime::from(std::time::SystemTime::now()).secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Leases, is_lease_field, lease_field};
+    use crate::tools::store::Secret;
+    use std::collections::HashMap;
+
+    fn secret_with(leases: &Leases, field_ids: &[&str]) -> Secret {
+        let mut data = HashMap::from([("dev".to_string(), "A=1".to_string())]);
+        for field_id in field_ids {
+            if let (key, Some(value)) = leases.change(field_id).unwrap() {
+                data.insert(key, value);
+            }
+        }
+        Secret {
+            data,
+            version_id: None,
+        }
+    }
+
+    #[test]
+    fn acquired_leases_are_held_until_released() {
+        let mut leases = Leases::default();
+        leases.acquire("dev", "alice", Some("rotation".to_string()), 60);
+
+        let mut leases = Leases::load(&secret_with(&leases, &["dev"])).unwrap();
+        assert_eq!(leases.live("dev").unwrap().owner, "alice");
+        assert!(leases.held_by_other("dev", "bob").is_some());
+        assert!(leases.held_by_other("dev", "alice").is_none());
+        assert!(leases.live("prod").is_none());
+
+        assert_eq!(leases.release("dev").unwrap().owner, "alice");
+        assert!(leases.live("dev").is_none());
+        assert_eq!(leases.change("dev").unwrap(), (lease_field("dev"), None));
+    }
+
+    #[test]
+    fn expired_leases_are_not_held() {
+        let mut leases = Leases::default();
+        leases.acquire("dev", "alice", None, -1);
+
+        assert!(leases.live("dev").is_none());
+        assert!(leases.held_by_other("dev", "bob").is_none());
+    }
+
+    #[test]
+    fn steals_are_recorded_for_the_field() {
+        let mut leases = Leases::default();
+        leases.acquire("dev", "alice", None, 60);
+
+        assert_eq!(leases.steal("dev", "bob").unwrap().owner, "alice");
+        assert!(leases.steal("prod", "bob").is_none());
+
+        let leases = Leases::load(&secret_with(&leases, &["dev"])).unwrap();
+        assert!(leases.live("dev").is_none());
+        let steals: Vec<_> = leases.steals("dev").collect();
+        assert_eq!(steals.len(), 1);
+        assert_eq!(steals[0].stolen_from, "alice");
+        assert_eq!(leases.steals("prod").count(), 0);
+    }
+
+    #[test]
+    fn each_field_has_its_own_entry() {
+        let mut leases = Leases::default();
+        leases.acquire("dev", "alice", None, 60);
+        leases.acquire("prod", "bob", None, 60);
+
+        let secret = secret_with(&leases, &["dev", "prod"]);
+        assert!(secret.data.contains_key("tc-secrets:lease:dev"));
+        assert!(secret.data.contains_key("tc-secrets:lease:prod"));
+        assert_eq!(secret.list_fields(), vec!["dev".to_string()]);
+        assert!(is_lease_field("tc-secrets:lease:dev"));
+        assert!(!is_lease_field("dev"));
+
+        let leases = Leases::load(&secret).unwrap();
+        assert_eq!(leases.live("dev").unwrap().owner, "alice");
+        assert_eq!(leases.live("prod").unwrap().owner, "bob");
+    }
+}
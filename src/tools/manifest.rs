@@ -0,0 +1,229 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use super::aws::AwsOptions;
+use super::config::EnvFile;
+use super::crypto::Encryption;
+use super::error::CliError;
+use super::store::Backend;
+
+pub const MANIFEST_FILE: &str = ".tc-secrets.toml";
+const DEFAULT_PASSWORD: &str = "secret";
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Dotenv,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EncryptionMode {
+    #[default]
+    Password,
+    None,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManagedFile {
+    pub name: String,
+    pub path: PathBuf,
+    pub secret_id: String,
+    pub field_id: String,
+    #[serde(default)]
+    pub format: Format,
+    #[serde(default)]
+    pub encryption: EncryptionMode,
+    // password sources, tried in this order
+    pub password_env: Option<String>,
+    pub password_file: Option<PathBuf>,
+    // backend of this file instead of the one of the manifest
+    pub backend: Option<Backend>,
+    // role to assume for this file, e.g. for secrets in another account
+    pub role_arn: Option<String>,
+    pub external_id: Option<String>,
+    pub mfa_serial: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(skip)]
+    root: PathBuf,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub files: Vec<ManagedFile>,
+}
+
+impl Manifest {
+    pub fn load() -> Result<Option<Self>, CliError> {
+        // look for the manifest in the current directory and its parents
+        let cwd = std::env::current_dir()?;
+        let Some(root) = cwd
+            .ancestors()
+            .find(|dir| dir.join(MANIFEST_FILE).is_file())
+        else {
+            return Ok(None);
+        };
+
+        let path = root.join(MANIFEST_FILE);
+        let content = std::fs::read_to_string(&path)?;
+        let mut manifest: Manifest = toml::from_str(&content)
+            .map_err(|e| CliError::ManifestError(format!("{}: {}", path.to_string_lossy(), e)))?;
+
+        // keep paths short when the manifest is in the current directory
+        manifest.root = root.strip_prefix(&cwd).unwrap_or(root).to_path_buf();
+        manifest.backend = manifest.backend.with_root(&manifest.root);
+        for file in &mut manifest.files {
+            file.backend = file
+                .backend
+                .take()
+                .map(|backend| backend.with_root(&manifest.root));
+        }
+
+        for (idx, file) in manifest.files.iter().enumerate() {
+            if manifest.files[..idx].iter().any(|f| f.name == file.name) {
+                return Err(CliError::ManifestError(format!(
+                    "duplicate file name '{}'",
+                    file.name
+                )));
+            }
+        }
+
+        Ok(Some(manifest))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path_of(&self, file: &ManagedFile) -> PathBuf {
+        self.root.join(&file.path)
+    }
+
+    pub fn find(&self, name_or_path: &str) -> Option<&ManagedFile> {
+        let path = Path::new(name_or_path);
+        self.files.iter().find(|file| {
+            if file.name == name_or_path {
+                return true;
+            }
+
+            let file_path = self.path_of(file);
+            file_path == path
+                || matches!(
+                    (file_path.canonicalize(), path.canonicalize()),
+                    (Ok(a), Ok(b)) if a == b
+                )
+        })
+    }
+
+    pub fn targets(
+        manifest: Option<&Manifest>,
+        filepath: Option<&str>,
+        password: Option<String>,
+    ) -> Result<Vec<Target>, CliError> {
+        match (manifest, filepath) {
+            (Some(manifest), Some(filepath)) => match manifest.find(filepath) {
+                Some(file) => Ok(vec![manifest.target(file, password)?]),
+                None => Ok(vec![Target::from_path(filepath, password)]),
+            },
+            (None, Some(filepath)) => Ok(vec![Target::from_path(filepath, password)]),
+            (Some(manifest), None) if !manifest.files.is_empty() => manifest
+                .files
+                .iter()
+                .map(|file| manifest.target(file, password.clone()))
+                .collect(),
+            _ => Err(CliError::ManifestError(format!(
+                "No file path was given and no files are listed in {}.",
+                MANIFEST_FILE
+            ))),
+        }
+    }
+
+    fn target(&self, file: &ManagedFile, password: Option<String>) -> Result<Target, CliError> {
+        let encryption = match file.encryption {
+            EncryptionMode::None => Encryption::none(),
+            EncryptionMode::Password => {
+                let password = match (password, &file.password_env, &file.password_file) {
+                    (Some(password), _, _) => password,
+                    (None, Some(var), _) => std::env::var(var).map_err(|_| {
+                        CliError::ManifestError(format!(
+                            "the password variable {} for '{}' is not set",
+                            var, file.name
+                        ))
+                    })?,
+                    (None, None, Some(path)) => std::fs::read_to_string(self.root.join(path))?
+                        .trim_end()
+                        .to_string(),
+                    (None, None, None) => DEFAULT_PASSWORD.to_string(),
+                };
+                Encryption::new(password)
+            }
+        };
+
+        Ok(Target {
+            filepath: self.path_of(file).to_string_lossy().to_string(),
+            secret_id: Some(file.secret_id.clone()),
+            field_id: Some(file.field_id.clone()),
+            format: file.format,
+            encryption,
+            backend: file.backend.clone(),
+            aws: AwsOptions {
+                role_arn: file.role_arn.clone(),
+                external_id: file.external_id.clone(),
+                mfa_serial: file.mfa_serial.clone(),
+                ..AwsOptions::default()
+            },
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Target {
+    pub filepath: String,
+    pub secret_id: Option<String>,
+    pub field_id: Option<String>,
+    pub format: Format,
+    pub encryption: Encryption,
+    pub backend: Option<Backend>,
+    // overrides of the backend options for this file
+    pub aws: AwsOptions,
+}
+
+impl Target {
+    fn from_path(filepath: &str, password: Option<String>) -> Self {
+        Target {
+            filepath: filepath.to_string(),
+            secret_id: None,
+            field_id: None,
+            format: Format::default(),
+            encryption: Encryption::new(password.unwrap_or_else(|| DEFAULT_PASSWORD.to_string())),
+            backend: None,
+            aws: AwsOptions::default(),
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(&self.filepath)
+    }
+
+    pub fn load_env_file(&self) -> Result<EnvFile, CliError> {
+        let mut env_file = match self.format {
+            Format::Dotenv => EnvFile::new_local(self.path())?,
+        };
+
+        // the manifest takes precedence over the headers in the file
+        if self.secret_id.is_some() {
+            env_file.secret_id = self.secret_id.clone();
+        }
+        if self.field_id.is_some() {
+            env_file.field_id = self.field_id.clone();
+        }
+
+        Ok(env_file)
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_smithy_types::date_time::{DateTime, Format};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use super::error::CliError;
+use super::store::{NewSecret, Secret, SecretFilter, SecretInfo, SecretStore, SecretVersion};
+
+const CURRENT_STAGE: &str = "current";
+const PREVIOUS_STAGE: &str = "previous";
+
+struct StoredVersion {
+    version_id: String,
+    created_date: Option<String>,
+    value: String,
+}
+
+#[derive(Default)]
+struct StoredSecret {
+    // oldest first
+    versions: Vec<StoredVersion>,
+    description: Option<String>,
+    tags: BTreeMap<String, String>,
+    deletion_date: Option<String>,
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    secrets: Mutex<HashMap<String, StoredSecret>>,
+}
+
+impl MemoryStore {
+    pub fn with_secrets(
+        secrets: &HashMap<String, HashMap<String, String>>,
+    ) -> Result<Self, CliError> {
+        let store = MemoryStore::default();
+        {
+            let mut stored = store.secrets.lock().unwrap();
+            for (secret_id, fields) in secrets {
+                let value = serde_json::to_string(fields)
+                    .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?;
+                stored.insert(
+                    secret_id.clone(),
+                    StoredSecret {
+                        versions: vec![StoredVersion {
+                            version_id: "v1".to_string(),
+                            created_date: None,
+                            value,
+                        }],
+                        ..StoredSecret::default()
+                    },
+                );
+            }
+        }
+        Ok(store)
+    }
+
+    fn not_found(secret_id: &str) -> CliError {
+        CliError::NotFoundError(secret_id.to_string())
+    }
+
+    // secrets scheduled for deletion can only be restored
+    fn find<'a>(
+        secrets: &'a mut HashMap<String, StoredSecret>,
+        secret_id: &str,
+    ) -> Result<&'a mut StoredSecret, CliError> {
+        let secret = secrets
+            .get_mut(secret_id)
+            .ok_or_else(|| Self::not_found(secret_id))?;
+        if secret.deletion_date.is_some() {
+            return Err(CliError::AwsSecretsManagerError(format!(
+                "secret {} is scheduled for deletion",
+                secret_id
+            )));
+        }
+        Ok(secret)
+    }
+}
+
+#[async_trait]
+impl SecretStore for MemoryStore {
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError> {
+        let mut names: Vec<String> = self
+            .secrets
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, secret)| secret.deletion_date.is_none())
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+
+        let mut secrets = Vec::new();
+        for name in names {
+            let info = self.describe(name).await?;
+            if filter.matches(&info) {
+                secrets.push(info);
+            }
+        }
+        Ok(secrets)
+    }
+
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let versions = &Self::find(&mut secrets, &secret_id)?.versions;
+
+        let version = match &version_id {
+            Some(version_id) => versions.iter().find(|v| &v.version_id == version_id),
+            None => versions.last(),
+        }
+        .ok_or_else(|| {
+            CliError::AwsSecretsManagerError(format!(
+                "version {} of secret {} not found",
+                version_id.unwrap_or_default(),
+                secret_id
+            ))
+        })?;
+
+        Secret::new(version.value.clone(), Some(version.version_id.clone()))
+    }
+
+    async fn put(
+        &self,
+        secret_id: String,
+        secret_value: String,
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let versions = &mut Self::find(&mut secrets, &secret_id)?.versions;
+
+        // the lock makes the check and the write a single step
+        let current = versions.last().map(|v| &v.version_id);
+        if expected_version.is_some() && current != expected_version.as_ref() {
+            return Err(CliError::RemoteConflictError(format!(
+                "secret {} changed since it was read",
+                secret_id
+            )));
+        }
+
+        let version_id = format!("v{}", versions.len() + 1);
+        versions.push(StoredVersion {
+            version_id: version_id.clone(),
+            created_date: now(),
+            value: secret_value,
+        });
+
+        Ok(Some(version_id))
+    }
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let secret = Self::find(&mut secrets, &secret_id)?;
+        let latest = secret.versions.last();
+
+        Ok(SecretInfo {
+            name: secret_id,
+            description: secret.description.clone(),
+            last_changed: latest.and_then(|v| v.created_date.clone()),
+            version_id: latest.map(|v| v.version_id.clone()),
+            tags: secret.tags.clone(),
+        })
+    }
+
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let versions = &Self::find(&mut secrets, &secret_id)?.versions;
+
+        Ok(versions
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(idx, version)| SecretVersion {
+                version_id: version.version_id.clone(),
+                created_date: version.created_date.clone(),
+                stages: match idx {
+                    0 => vec![CURRENT_STAGE.to_string()],
+                    1 => vec![PREVIOUS_STAGE.to_string()],
+                    _ => Vec::new(),
+                },
+            })
+            .collect())
+    }
+
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        if secrets.contains_key(&new_secret.name) {
+            return Err(CliError::AwsSecretsManagerError(format!(
+                "secret {} already exists",
+                new_secret.name
+            )));
+        }
+
+        secrets.insert(
+            new_secret.name.clone(),
+            StoredSecret {
+                versions: vec![StoredVersion {
+                    version_id: "v1".to_string(),
+                    created_date: now(),
+                    value: "{}".to_string(),
+                }],
+                description: new_secret.description.clone(),
+                tags: new_secret.tags.clone(),
+                deletion_date: None,
+            },
+        );
+
+        Ok(Some("v1".to_string()))
+    }
+
+    async fn delete(
+        &self,
+        secret_id: String,
+        recovery_days: i64,
+    ) -> Result<Option<String>, CliError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let secret = Self::find(&mut secrets, &secret_id)?;
+
+        let now = DateTime::from(std::time::SystemTime::now()).secs();
+        secret.deletion_date = DateTime::from_secs(now + recovery_days * 24 * 60 * 60)
+            .fmt(Format::DateTime)
+            .ok();
+
+        Ok(secret.deletion_date.clone())
+    }
+
+    async fn restore(&self, secret_id: String) -> Result<(), CliError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let secret = secrets
+            .get_mut(&secret_id)
+            .ok_or_else(|| Self::not_found(&secret_id))?;
+        secret.deletion_date = None;
+        Ok(())
+    }
+}
+
+fn now() -> Option<String> {
+    DateTime::from(std::time::SystemTime::now())
+        .fmt(Format::DateTime)
+        .ok()
+}
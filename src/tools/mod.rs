@@ -2,10 +2,36 @@ mod aws;
 mod config;
 mod crypto;
 mod diff;
+mod dotenv;
 mod error;
+mod files;
+mod gitfile;
+mod lease;
+mod manifest;
+mod memory;
+mod plan;
+mod resolve;
+mod s3;
+mod session;
+mod sqlite;
+mod ssm;
+mod state;
+mod store;
+mod vault;
+mod watch;
 
-pub use aws::{AWS, AWSSecret};
+pub use aws::{AWS, AwsOptions};
 pub use config::EnvFile;
 pub use crypto::Encryption;
-pub use diff::display_diff;
+pub use diff::{display_diff, display_key_changes};
 pub use error::CliError;
+pub use files::{Backups, FileLock, backup_file, check_permissions};
+pub use gitfile::{GitFile, merge_secrets_files};
+pub use lease::{Lease, Leases, Steal, format_time, is_lease_field, lease_owner};
+pub use manifest::{MANIFEST_FILE, Manifest, Target};
+pub use plan::{Direction, Plan};
+pub use resolve::{merge, resolve_conflicts};
+pub use state::SyncState;
+pub use store::{Backend, NewSecret, Secret, SecretFilter, SecretStore};
+pub use vault::Vault;
+pub use watch::FileWatcher;
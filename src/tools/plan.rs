@@ -0,0 +1,85 @@
+use console::style;
+use std::fmt;
+
+use super::diff::display_key_changes;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    UpToDate,
+    Pull,
+    Push,
+    Merge,
+    Diverged,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::UpToDate => write!(f, "none (up to date)"),
+            Direction::Pull => write!(f, "pull (remote -> local)"),
+            Direction::Push => write!(f, "push (local -> remote)"),
+            Direction::Merge => write!(f, "merge (both sides changed)"),
+            Direction::Diverged => write!(f, "none (same version, different content)"),
+        }
+    }
+}
+
+pub struct Plan {
+    pub direction: Direction,
+    pub old_version: u32,
+    pub new_version: u32,
+    pub old_content: String,
+    pub new_content: String,
+    pub conflicts: Vec<String>,
+    pub writes: Vec<String>,
+}
+
+impl Plan {
+    pub fn new(direction: Direction, old_version: u32, new_version: u32) -> Self {
+        Plan {
+            direction,
+            old_version,
+            new_version,
+            old_content: String::new(),
+            new_content: String::new(),
+            conflicts: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.direction != Direction::UpToDate
+    }
+
+    pub fn display(&self) {
+        println!("{}", style("Dry run, nothing will be written.").bold());
+        println!("  {:<11}{}", "Direction", style(self.direction).cyan());
+        println!(
+            "  {:<11}{} -> {}",
+            "Version",
+            style(self.old_version).cyan(),
+            style(self.new_version).cyan()
+        );
+
+        if self.has_changes() {
+            println!("  Keys");
+            display_key_changes(&self.old_content, &self.new_content);
+        }
+
+        if !self.conflicts.is_empty() {
+            println!(
+                "  {:<11}{}",
+                "Conflicts",
+                style(self.conflicts.join(", ")).yellow()
+            );
+        }
+
+        if self.writes.is_empty() {
+            println!("  {:<11}nothing", "Writes");
+        }
+        for (idx, write) in self.writes.iter().enumerate() {
+            let label = if idx == 0 { "Writes" } else { "" };
+            println!("  {:<11}{}", label, write);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use anyhow::Result;
+use console::style;
+use dialoguer::{Input, Select};
+
+use super::dotenv::{get_entry, mask_value, parse_entries, set_entry};
+use super::error::CliError;
+
+pub struct Conflict {
+    pub key: String,
+    pub base: Option<String>,
+    pub local: Option<String>,
+    pub remote: Option<String>,
+}
+
+pub struct Merge {
+    pub content: String,
+    pub conflicts: Vec<Conflict>,
+}
+
+pub fn merge(base: &str, local: &str, remote: &str) -> Merge {
+    let mut keys: Vec<String> = Vec::new();
+    for (key, _) in parse_entries(local)
+        .into_iter()
+        .chain(parse_entries(remote))
+        .chain(parse_entries(base))
+    {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    // start from the local content so comments and ordering are preserved
+    let mut content = local.to_string();
+    let mut conflicts = Vec::new();
+
+    for key in keys {
+        let base_value = get_entry(base, &key);
+        let local_value = get_entry(local, &key);
+        let remote_value = get_entry(remote, &key);
+
+        if local_value == remote_value || remote_value == base_value {
+            continue;
+        }
+
+        if local_value == base_value {
+            // only the remote changed this key
+            content = set_entry(&content, &key, remote_value.as_deref());
+        } else {
+            conflicts.push(Conflict {
+                key,
+                base: base_value,
+                local: local_value,
+                remote: remote_value,
+            });
+        }
+    }
+
+    Merge { content, conflicts }
+}
+
+pub fn resolve_conflicts(merge: Merge) -> Result<String, CliError> {
+    let mut content = merge.content;
+    let total = merge.conflicts.len();
+
+    for (idx, conflict) in merge.conflicts.into_iter().enumerate() {
+        let mut reveal = false;
+
+        let value = loop {
+            println!(
+                "\nConflict {}/{} on key {}",
+                idx + 1,
+                total,
+                style(&conflict.key).yellow().bold()
+            );
+            for (label, value) in [
+                ("base", &conflict.base),
+                ("local", &conflict.local),
+                ("remote", &conflict.remote),
+            ] {
+                let value = match (reveal, value) {
+                    (true, Some(value)) => value.clone(),
+                    _ => mask_value(value.as_deref()),
+                };
+                println!("  {:<7}{}", label, style(value).cyan());
+            }
+
+            let items = [
+                "Keep local",
+                "Keep remote",
+                "Edit value",
+                "Delete key",
+                if reveal {
+                    "Hide values"
+                } else {
+                    "Reveal values"
+                },
+                "Abort",
+            ];
+            let selection = Select::new()
+                .with_prompt("How do you want to resolve this key?")
+                .items(&items)
+                .default(0)
+                .interact()
+                .expect("Failed to select a resolution");
+
+            match selection {
+                0 => break conflict.local.clone(),
+                1 => break conflict.remote.clone(),
+                2 => {
+                    let mut input = Input::<String>::new()
+                        .with_prompt(format!("New value for {}", conflict.key))
+                        .allow_empty(true);
+                    if reveal && let Some(local) = &conflict.local {
+                        input = input.with_initial_text(local.clone());
+                    }
+                    break Some(input.interact_text().expect("Failed to read a value"));
+                }
+                3 => break None,
+                4 => reveal = !reveal,
+                _ => {
+                    return Err(CliError::AbortedError(
+                        "Conflict resolution aborted. No changes were written.".to_string(),
+                    ));
+                }
+            }
+        };
+
+        content = set_entry(&content, &conflict.key, value.as_deref());
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge;
+
+    const BASE: &str = "# shared\nA=1\nB=2\nC=3";
+
+    #[test]
+    fn changes_on_one_side_are_taken() {
+        let merged = merge(
+            BASE,
+            "# shared\nA=local\nB=2\nC=3",
+            "# shared\nA=1\nB=2\nC=remote",
+        );
+
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.content, "# shared\nA=local\nB=2\nC=remote");
+    }
+
+    #[test]
+    fn remote_additions_and_removals_are_applied() {
+        let merged = merge(
+            BASE,
+            "# shared\nA=1\nB=2\nC=3\nL=new",
+            "# shared\nA=1\nC=3\nR=new",
+        );
+
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.content, "# shared\nA=1\nC=3\nL=new\nR=new");
+    }
+
+    #[test]
+    fn identical_changes_do_not_conflict() {
+        let merged = merge(BASE, "# shared\nA=same\nB=2", "# shared\nA=same\nB=2");
+
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.content, "# shared\nA=same\nB=2");
+    }
+
+    #[test]
+    fn different_changes_to_a_key_conflict() {
+        let merged = merge(
+            BASE,
+            "# shared\nA=local\nB=local\nC=3",
+            "# shared\nA=remote\nC=remote",
+        );
+
+        // conflicting keys keep the local value until they are resolved
+        assert_eq!(merged.content, "# shared\nA=local\nB=local\nC=remote");
+        assert_eq!(merged.conflicts.len(), 2);
+        let conflict = &merged.conflicts[0];
+        assert_eq!(conflict.key, "A");
+        assert_eq!(conflict.base.as_deref(), Some("1"));
+        assert_eq!(conflict.local.as_deref(), Some("local"));
+        assert_eq!(conflict.remote.as_deref(), Some("remote"));
+        // a key edited on one side and removed on the other conflicts as well
+        let conflict = &merged.conflicts[1];
+        assert_eq!(conflict.key, "B");
+        assert_eq!(conflict.local.as_deref(), Some("local"));
+        assert_eq!(conflict.remote, None);
+    }
+}
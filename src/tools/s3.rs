@@ -0,0 +1,521 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::primitives::{ByteStream, DateTime, DateTimeFormat};
+use aws_sdk_s3::types::{BucketVersioningStatus, ServerSideEncryption};
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+use std::collections::BTreeMap;
+
+use super::aws::{AWS, AwsOptions, sdk_error};
+use super::error::CliError;
+use super::store::{NewSecret, Secret, SecretFilter, SecretInfo, SecretStore, SecretVersion};
+
+const OBJECT_EXTENSION: &str = ".json";
+const DESCRIPTION_METADATA: &str = "description";
+const CURRENT_STAGE: &str = "current";
+const PREVIOUS_STAGE: &str = "previous";
+// returned by S3 when a conditional write doesn't match
+const PRECONDITION_FAILED: u16 = 412;
+const CONDITIONAL_REQUEST_CONFLICT: u16 = 409;
+const NOT_FOUND: u16 = 404;
+
+#[derive(Deserialize, Clone, PartialEq, Default)]
+#[serde(try_from = "S3Table")]
+pub struct S3Options {
+    pub bucket: String,
+    // secrets are stored as <prefix><secret_id>.json
+    pub prefix: String,
+    pub kms_key_id: Option<String>,
+    pub aws: AwsOptions,
+}
+
+// `deny_unknown_fields` has no effect through `flatten`, leftover keys are collected instead
+#[derive(Deserialize)]
+struct S3Table {
+    bucket: String,
+    #[serde(default)]
+    prefix: String,
+    kms_key_id: Option<String>,
+    #[serde(flatten)]
+    aws: AwsOptions,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl TryFrom<S3Table> for S3Options {
+    type Error = String;
+
+    fn try_from(table: S3Table) -> Result<Self, Self::Error> {
+        if let Some(key) = table.unknown.keys().next() {
+            return Err(format!("unknown field `{}` in the s3 backend", key));
+        }
+        Ok(S3Options {
+            bucket: table.bucket,
+            prefix: table.prefix,
+            kms_key_id: table.kms_key_id,
+            aws: table.aws,
+        })
+    }
+}
+
+pub struct S3 {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    kms_key_id: Option<String>,
+}
+
+// what HeadObject tells about the current object of a secret
+struct Head {
+    version_id: String,
+    etag: Option<String>,
+    description: Option<String>,
+    kms_key_id: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl S3 {
+    pub async fn new(options: &S3Options) -> Result<Self, CliError> {
+        if options.bucket.is_empty() {
+            return Err(CliError::ManifestError(
+                "the s3 backend needs a `bucket` in the [backend] table".to_string(),
+            ));
+        }
+
+        let config = AWS::sdk_config(&options.aws).await?;
+        // local stand-ins like MinIO don't serve buckets as subdomains
+        let s3_config = aws_sdk_s3::config::Builder::from(&config)
+            .force_path_style(config.endpoint_url().is_some())
+            .build();
+
+        Ok(S3 {
+            client: Client::from_conf(s3_config),
+            bucket: options.bucket.clone(),
+            prefix: options.prefix.clone(),
+            kms_key_id: options.kms_key_id.clone(),
+        })
+    }
+
+    fn key(&self, secret_id: &str) -> String {
+        format!("{}{}{}", self.prefix, secret_id, OBJECT_EXTENSION)
+    }
+
+    fn not_found(secret_id: &str) -> CliError {
+        CliError::NotFoundError(secret_id.to_string())
+    }
+
+    async fn head(&self, secret_id: &str) -> Result<Head, CliError> {
+        let resp = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.key(secret_id))
+            .send()
+            .await
+            .map_err(|e| match status(&e) {
+                Some(NOT_FOUND) => Self::not_found(secret_id),
+                _ => s3_error(e),
+            })?;
+
+        Ok(Head {
+            version_id: object_version(resp.version_id(), resp.e_tag()),
+            etag: resp.e_tag().map(String::from),
+            description: resp
+                .metadata()
+                .and_then(|metadata| metadata.get(DESCRIPTION_METADATA))
+                .cloned(),
+            kms_key_id: resp.ssekms_key_id().map(String::from),
+            last_modified: resp.last_modified().and_then(format_date),
+        })
+    }
+
+    async fn tags(&self, secret_id: &str) -> Result<BTreeMap<String, String>, CliError> {
+        let resp = self
+            .client
+            .get_object_tagging()
+            .bucket(&self.bucket)
+            .key(self.key(secret_id))
+            .send()
+            .await
+            .map_err(s3_error)?;
+
+        Ok(resp
+            .tag_set()
+            .iter()
+            .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+            .collect())
+    }
+
+    async fn versioning_enabled(&self) -> Result<bool, CliError> {
+        let resp = self
+            .client
+            .get_bucket_versioning()
+            .bucket(&self.bucket)
+            .send()
+            .await
+            .map_err(s3_error)?;
+        Ok(resp.status() == Some(&BucketVersioningStatus::Enabled))
+    }
+
+    // writes a new object for the secret, conditional on the current one or on none existing
+    async fn write(
+        &self,
+        secret_id: &str,
+        body: String,
+        condition: Option<&str>,
+        description: Option<&str>,
+        tags: &BTreeMap<String, String>,
+        kms_key_id: Option<&str>,
+    ) -> Result<String, CliError> {
+        let request = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.key(secret_id))
+            .content_type("application/json")
+            .body(ByteStream::from(body.into_bytes()))
+            .set_tagging((!tags.is_empty()).then(|| encode_tags(tags)));
+        let request = match condition {
+            Some(etag) => request.if_match(etag),
+            None => request.if_none_match("*"),
+        };
+        let request = match description {
+            Some(description) => request.metadata(DESCRIPTION_METADATA, description),
+            None => request,
+        };
+        let request = match kms_key_id {
+            Some(kms_key_id) => request
+                .server_side_encryption(ServerSideEncryption::AwsKms)
+                .ssekms_key_id(kms_key_id),
+            None => request,
+        };
+
+        let resp = request.send().await.map_err(|e| match status(&e) {
+            Some(PRECONDITION_FAILED | CONDITIONAL_REQUEST_CONFLICT) => {
+                CliError::RemoteConflictError(match condition {
+                    Some(_) => format!("secret {} changed since it was read", secret_id),
+                    None => format!("secret {} already exists", secret_id),
+                })
+            }
+            _ => s3_error(e),
+        })?;
+
+        Ok(object_version(resp.version_id(), resp.e_tag()))
+    }
+}
+
+#[async_trait]
+impl SecretStore for S3 {
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError> {
+        let mut names = Vec::new();
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&self.prefix)
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(s3_error)?;
+            names.extend(page.contents().iter().filter_map(|object| {
+                object
+                    .key()?
+                    .strip_prefix(&self.prefix)?
+                    .strip_suffix(OBJECT_EXTENSION)
+                    .map(String::from)
+            }));
+        }
+
+        let mut secrets = Vec::new();
+        let prefix = filter.name_prefix.as_deref().unwrap_or("").to_lowercase();
+        for name in names {
+            if !name.to_lowercase().starts_with(&prefix) {
+                continue;
+            }
+
+            // the tags are only known after describing the secret
+            let info = self.describe(name).await?;
+            if filter.matches(&info) {
+                secrets.push(info);
+            }
+        }
+
+        secrets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(secrets)
+    }
+
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError> {
+        // without versioning the ETag identifies the only version there is
+        let version = version_id.as_deref().filter(|id| !is_etag(id));
+        let resp = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(&secret_id))
+            .set_version_id(version.map(String::from))
+            .send()
+            .await
+            .map_err(|e| match (status(&e), &version_id) {
+                (Some(NOT_FOUND), None) => Self::not_found(&secret_id),
+                (Some(NOT_FOUND), Some(version_id)) => CliError::AwsS3Error(format!(
+                    "version {} of secret {} not found",
+                    version_id, secret_id
+                )),
+                _ => s3_error(e),
+            })?;
+
+        let current = object_version(resp.version_id(), resp.e_tag());
+        if let Some(version_id) = &version_id
+            && &current != version_id
+        {
+            return Err(CliError::AwsS3Error(format!(
+                "version {} of secret {} not found, enable versioning on the bucket to keep earlier versions",
+                version_id, secret_id
+            )));
+        }
+
+        let body = resp
+            .body
+            .collect()
+            .await
+            .map_err(|e| CliError::AwsS3Error(e.to_string()))?;
+        let content = String::from_utf8(body.to_vec())
+            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?;
+
+        Secret::new(content, Some(current))
+    }
+
+    async fn put(
+        &self,
+        secret_id: String,
+        secret_value: String,
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError> {
+        let head = self.head(&secret_id).await?;
+        if let Some(expected) = &expected_version
+            && &head.version_id != expected
+        {
+            return Err(CliError::RemoteConflictError(format!(
+                "secret {} changed since it was read",
+                secret_id
+            )));
+        }
+
+        // a new object replaces the metadata and tags, so carry them over
+        let tags = self.tags(&secret_id).await?;
+        let version_id = self
+            .write(
+                &secret_id,
+                secret_value,
+                Some(head.etag.as_deref().unwrap_or("*")),
+                head.description.as_deref(),
+                &tags,
+                head.kms_key_id.as_deref(),
+            )
+            .await?;
+
+        Ok(Some(version_id))
+    }
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError> {
+        let head = self.head(&secret_id).await?;
+        let tags = self.tags(&secret_id).await?;
+
+        Ok(SecretInfo {
+            name: secret_id,
+            description: head.description,
+            last_changed: head.last_modified,
+            version_id: Some(head.version_id),
+            tags,
+        })
+    }
+
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError> {
+        let key = self.key(&secret_id);
+        let mut versions = Vec::new();
+        let mut markers = (None, None);
+        loop {
+            let page = self
+                .client
+                .list_object_versions()
+                .bucket(&self.bucket)
+                .prefix(&key)
+                .set_key_marker(markers.0)
+                .set_version_id_marker(markers.1)
+                .send()
+                .await
+                .map_err(s3_error)?;
+            versions.extend(
+                page.versions()
+                    .iter()
+                    .filter(|version| version.key() == Some(key.as_str()))
+                    .map(|version| {
+                        (
+                            object_version(version.version_id(), version.e_tag()),
+                            version.last_modified().and_then(format_date),
+                        )
+                    }),
+            );
+
+            if page.is_truncated() != Some(true) {
+                break;
+            }
+            markers = (
+                page.next_key_marker().map(String::from),
+                page.next_version_id_marker().map(String::from),
+            );
+        }
+        if versions.is_empty() {
+            return Err(Self::not_found(&secret_id));
+        }
+
+        // S3 lists the newest version first
+        Ok(versions
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (version_id, created_date))| SecretVersion {
+                version_id,
+                created_date,
+                stages: match idx {
+                    0 => vec![CURRENT_STAGE.to_string()],
+                    1 => vec![PREVIOUS_STAGE.to_string()],
+                    _ => Vec::new(),
+                },
+            })
+            .collect())
+    }
+
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError> {
+        let kms_key_id = new_secret.kms_key_id.as_ref().or(self.kms_key_id.as_ref());
+        let version_id = self
+            .write(
+                &new_secret.name,
+                "{}".to_string(),
+                None,
+                new_secret.description.as_deref(),
+                &new_secret.tags,
+                kms_key_id.map(String::as_str),
+            )
+            .await
+            .map_err(|e| match e {
+                CliError::RemoteConflictError(reason) => CliError::AwsS3Error(reason),
+                e => e,
+            })?;
+
+        Ok(Some(version_id))
+    }
+
+    async fn delete(
+        &self,
+        secret_id: String,
+        _recovery_days: i64,
+    ) -> Result<Option<String>, CliError> {
+        self.head(&secret_id).await?;
+
+        // only a delete marker is added, the versions stay until a lifecycle rule expires them
+        if !self.versioning_enabled().await? {
+            return Err(CliError::AwsS3Error(format!(
+                "versioning is disabled on bucket {}, so deleting {} could not be undone",
+                self.bucket, secret_id
+            )));
+        }
+
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.key(&secret_id))
+            .send()
+            .await
+            .map_err(s3_error)?;
+
+        Ok(None)
+    }
+
+    async fn restore(&self, secret_id: String) -> Result<(), CliError> {
+        let key = self.key(&secret_id);
+        let resp = self
+            .client
+            .list_object_versions()
+            .bucket(&self.bucket)
+            .prefix(&key)
+            .send()
+            .await
+            .map_err(s3_error)?;
+
+        // removing the latest delete marker brings back the version before it
+        let marker = resp
+            .delete_markers()
+            .iter()
+            .find(|marker| marker.key() == Some(key.as_str()) && marker.is_latest() == Some(true))
+            .and_then(|marker| marker.version_id())
+            .ok_or_else(|| CliError::AwsS3Error(format!("secret {} is not deleted", secret_id)))?;
+
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .version_id(marker)
+            .send()
+            .await
+            .map_err(s3_error)?;
+
+        Ok(())
+    }
+
+    fn has_recovery_window(&self) -> bool {
+        false
+    }
+}
+
+fn status<E>(error: &SdkError<E, HttpResponse>) -> Option<u16> {
+    error
+        .raw_response()
+        .map(|response| response.status().as_u16())
+}
+
+fn s3_error<E, R>(error: SdkError<E, R>) -> CliError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    sdk_error(error, CliError::AwsS3Error)
+}
+
+// S3 reports "null" as the version id of objects written without versioning
+fn object_version(version_id: Option<&str>, etag: Option<&str>) -> String {
+    match version_id {
+        Some(version_id) if version_id != "null" => version_id.to_string(),
+        _ => etag.unwrap_or_default().to_string(),
+    }
+}
+
+fn is_etag(version_id: &str) -> bool {
+    version_id.starts_with('"')
+}
+
+fn encode_tags(tags: &BTreeMap<String, String>) -> String {
+    tags.iter()
+        .map(|(key, value)| format!("{}={}", encode_query(key), encode_query(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+fn format_date(date: &DateTime) -> Option<String> {
+    date.fmt(DateTimeFormat::DateTime).ok()
+}
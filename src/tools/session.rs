@@ -0,0 +1,64 @@
+use anyhow::Result;
+use aws_smithy_types::DateTime;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use super::crypto::hash_content;
+use super::error::CliError;
+use super::files::{create_private_dir, write_atomic};
+
+// sessions about to expire are renewed instead of failing halfway through a command
+const EXPIRY_MARGIN_SECS: i64 = 5 * 60;
+
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: String,
+    pub expires_at: i64,
+}
+
+impl Session {
+    pub fn load(key: &str) -> Option<Self> {
+        let content = std::fs::read_to_string(cache_path(key)?).ok()?;
+        let session: Session = serde_json::from_str(&content).ok()?;
+
+        let now = DateTime::from(std::time::SystemTime::now()).secs();
+        (session.expires_at > now + EXPIRY_MARGIN_SECS).then_some(session)
+    }
+
+    pub fn save(&self, key: &str) -> Result<(), CliError> {
+        let Some(path) = cache_path(key) else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            create_private_dir(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| CliError::StateFileError(e.to_string()))?;
+        write_atomic(&path, &content)
+    }
+}
+
+pub fn session_key(parts: &[Option<&str>]) -> String {
+    let parts: Vec<&str> = parts.iter().map(|part| part.unwrap_or("")).collect();
+    hash_content(&parts.join("\n"))
+}
+
+fn cache_path(key: &str) -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".cache"))
+        })?;
+
+    Some(
+        cache_dir
+            .join("tc-secrets")
+            .join("sessions")
+            .join(format!("{}.json", key)),
+    )
+}
@@ -0,0 +1,478 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_smithy_types::date_time::{DateTime, Format};
+use rusqlite::{Connection, OptionalExtension, Transaction, TransactionBehavior, params};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::error::CliError;
+use super::files::create_private;
+use super::store::{NewSecret, Secret, SecretFilter, SecretInfo, SecretStore, SecretVersion};
+
+const DEFAULT_PATH: &str = "secrets.db";
+const CURRENT_STAGE: &str = "current";
+const PREVIOUS_STAGE: &str = "previous";
+// how long to wait for another command holding the database lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// every version keeps all of its fields, so history can be queried per field
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS secrets (
+    name TEXT PRIMARY KEY,
+    version INTEGER NOT NULL,
+    description TEXT,
+    deletion_date TEXT
+);
+CREATE TABLE IF NOT EXISTS tags (
+    secret TEXT NOT NULL REFERENCES secrets (name) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (secret, key)
+);
+CREATE TABLE IF NOT EXISTS versions (
+    secret TEXT NOT NULL REFERENCES secrets (name) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    created TEXT NOT NULL,
+    PRIMARY KEY (secret, version)
+);
+CREATE TABLE IF NOT EXISTS fields (
+    secret TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    field TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (secret, version, field),
+    FOREIGN KEY (secret, version) REFERENCES versions (secret, version) ON DELETE CASCADE
+);
+";
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SqliteOptions {
+    // relative to the directory of the manifest
+    #[serde(default = "default_path")]
+    pub path: PathBuf,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        SqliteOptions {
+            path: default_path(),
+        }
+    }
+}
+
+fn default_path() -> PathBuf {
+    PathBuf::from(DEFAULT_PATH)
+}
+
+pub struct Sqlite {
+    path: PathBuf,
+}
+
+impl Sqlite {
+    pub fn new(options: &SqliteOptions) -> Self {
+        Sqlite {
+            path: options.path.clone(),
+        }
+    }
+
+    fn not_found(secret_id: &str) -> CliError {
+        CliError::NotFoundError(secret_id.to_string())
+    }
+
+    // a connection per command, the default rollback journal also works on network drives
+    fn connect(&self) -> Result<Connection, CliError> {
+        // the database holds every secret, so a new one is only readable by the owner
+        match create_private(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e.into()),
+            _ => {}
+        }
+        let conn = Connection::open(&self.path).map_err(sqlite_error)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(sqlite_error)?;
+        conn.pragma_update(None, "foreign_keys", true)
+            .map_err(sqlite_error)?;
+        conn.execute_batch(SCHEMA).map_err(sqlite_error)?;
+        Ok(conn)
+    }
+
+    // runs the change in a transaction that holds the write lock from the start,
+    // so reading the current version and writing the next one is a single step
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&Transaction) -> Result<T, CliError>,
+    ) -> Result<T, CliError> {
+        let mut conn = self.connect()?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sqlite_error)?;
+
+        // deletions take effect on the next write after the recovery window
+        tx.execute(
+            "DELETE FROM secrets WHERE deletion_date <= ?1",
+            params![now()],
+        )
+        .map_err(sqlite_error)?;
+
+        let result = change(&tx)?;
+        tx.commit().map_err(sqlite_error)?;
+        Ok(result)
+    }
+
+    // the current version of a secret, secrets scheduled for deletion can only be restored
+    fn version(conn: &Connection, secret_id: &str) -> Result<i64, CliError> {
+        let (version, deletion_date): (i64, Option<String>) = conn
+            .query_row(
+                "SELECT version, deletion_date FROM secrets WHERE name = ?1",
+                params![secret_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(sqlite_error)?
+            .ok_or_else(|| Self::not_found(secret_id))?;
+
+        if deletion_date.is_some() {
+            return Err(CliError::SqliteError(format!(
+                "secret {} is scheduled for deletion",
+                secret_id
+            )));
+        }
+        Ok(version)
+    }
+
+    fn info(conn: &Connection, secret_id: &str) -> Result<SecretInfo, CliError> {
+        let version = Self::version(conn, secret_id)?;
+        let (description, last_changed) = conn
+            .query_row(
+                "SELECT s.description, v.created FROM secrets s
+                 JOIN versions v ON v.secret = s.name AND v.version = s.version
+                 WHERE s.name = ?1",
+                params![secret_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(sqlite_error)?;
+
+        let mut stmt = conn
+            .prepare("SELECT key, value FROM tags WHERE secret = ?1")
+            .map_err(sqlite_error)?;
+        let tags = stmt
+            .query_map(params![secret_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(sqlite_error)?
+            .collect::<Result<BTreeMap<String, String>, _>>()
+            .map_err(sqlite_error)?;
+
+        Ok(SecretInfo {
+            name: secret_id.to_string(),
+            description,
+            last_changed,
+            version_id: Some(version.to_string()),
+            tags,
+        })
+    }
+
+    fn insert_version(
+        tx: &Transaction,
+        secret_id: &str,
+        version: i64,
+        fields: &BTreeMap<String, String>,
+    ) -> Result<(), CliError> {
+        tx.execute(
+            "INSERT INTO versions (secret, version, created) VALUES (?1, ?2, ?3)",
+            params![secret_id, version, now()],
+        )
+        .map_err(sqlite_error)?;
+        for (field, value) in fields {
+            tx.execute(
+                "INSERT INTO fields (secret, version, field, value) VALUES (?1, ?2, ?3, ?4)",
+                params![secret_id, version, field, value],
+            )
+            .map_err(sqlite_error)?;
+        }
+        tx.execute(
+            "UPDATE secrets SET version = ?2 WHERE name = ?1",
+            params![secret_id, version],
+        )
+        .map_err(sqlite_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SecretStore for Sqlite {
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError> {
+        let conn = self.connect()?;
+        let mut stmt = conn
+            .prepare("SELECT name FROM secrets WHERE deletion_date IS NULL ORDER BY name")
+            .map_err(sqlite_error)?;
+        let names = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(sqlite_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sqlite_error)?;
+
+        let mut secrets = Vec::new();
+        for name in names {
+            let info = Self::info(&conn, &name)?;
+            if filter.matches(&info) {
+                secrets.push(info);
+            }
+        }
+        Ok(secrets)
+    }
+
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError> {
+        let conn = self.connect()?;
+        let current = Self::version(&conn, &secret_id)?;
+        let version = match &version_id {
+            Some(version_id) => {
+                let version: Option<i64> = conn
+                    .query_row(
+                        "SELECT version FROM versions WHERE secret = ?1 AND version = ?2",
+                        params![secret_id, version_id],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(sqlite_error)?;
+                version.ok_or_else(|| {
+                    CliError::SqliteError(format!(
+                        "version {} of secret {} not found",
+                        version_id, secret_id
+                    ))
+                })?
+            }
+            None => current,
+        };
+
+        let mut stmt = conn
+            .prepare("SELECT field, value FROM fields WHERE secret = ?1 AND version = ?2")
+            .map_err(sqlite_error)?;
+        let data = stmt
+            .query_map(params![secret_id, version], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(sqlite_error)?
+            .collect::<Result<_, _>>()
+            .map_err(sqlite_error)?;
+
+        Ok(Secret {
+            data,
+            version_id: Some(version.to_string()),
+        })
+    }
+
+    async fn put(
+        &self,
+        secret_id: String,
+        secret_value: String,
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError> {
+        let fields: BTreeMap<String, String> = serde_json::from_str(&secret_value)
+            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?;
+
+        self.update(|tx| {
+            let version = Self::version(tx, &secret_id)?;
+            if let Some(expected) = &expected_version
+                && &version.to_string() != expected
+            {
+                return Err(CliError::RemoteConflictError(format!(
+                    "secret {} changed since it was read",
+                    secret_id
+                )));
+            }
+
+            Self::insert_version(tx, &secret_id, version + 1, &fields)?;
+            Ok(Some((version + 1).to_string()))
+        })
+    }
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError> {
+        Self::info(&self.connect()?, &secret_id)
+    }
+
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError> {
+        let conn = self.connect()?;
+        Self::version(&conn, &secret_id)?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT version, created FROM versions WHERE secret = ?1 ORDER BY version DESC",
+            )
+            .map_err(sqlite_error)?;
+        let versions = stmt
+            .query_map(params![secret_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sqlite_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sqlite_error)?;
+
+        Ok(versions
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (version, created))| SecretVersion {
+                version_id: version.to_string(),
+                created_date: Some(created),
+                stages: match idx {
+                    0 => vec![CURRENT_STAGE.to_string()],
+                    1 => vec![PREVIOUS_STAGE.to_string()],
+                    _ => Vec::new(),
+                },
+            })
+            .collect())
+    }
+
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError> {
+        if new_secret.kms_key_id.is_some() {
+            return Err(CliError::SqliteError(
+                "fields are encrypted locally, --kms-key-id is not supported".to_string(),
+            ));
+        }
+
+        self.update(|tx| {
+            let inserted = tx
+                .execute(
+                    "INSERT INTO secrets (name, version, description) VALUES (?1, 0, ?2)
+                     ON CONFLICT (name) DO NOTHING",
+                    params![new_secret.name, new_secret.description],
+                )
+                .map_err(sqlite_error)?;
+            if inserted == 0 {
+                return Err(CliError::SqliteError(format!(
+                    "secret {} already exists",
+                    new_secret.name
+                )));
+            }
+
+            for (key, value) in &new_secret.tags {
+                tx.execute(
+                    "INSERT INTO tags (secret, key, value) VALUES (?1, ?2, ?3)",
+                    params![new_secret.name, key, value],
+                )
+                .map_err(sqlite_error)?;
+            }
+            Self::insert_version(tx, &new_secret.name, 1, &BTreeMap::new())?;
+            Ok(Some("1".to_string()))
+        })
+    }
+
+    async fn delete(
+        &self,
+        secret_id: String,
+        recovery_days: i64,
+    ) -> Result<Option<String>, CliError> {
+        self.update(|tx| {
+            Self::version(tx, &secret_id)?;
+
+            let now = DateTime::from(std::time::SystemTime::now()).secs();
+            let deletion_date = DateTime::from_secs(now + recovery_days * 24 * 60 * 60)
+                .fmt(Format::DateTime)
+                .ok();
+            tx.execute(
+                "UPDATE secrets SET deletion_date = ?2 WHERE name = ?1",
+                params![secret_id, deletion_date],
+            )
+            .map_err(sqlite_error)?;
+            Ok(deletion_date)
+        })
+    }
+
+    async fn restore(&self, secret_id: String) -> Result<(), CliError> {
+        self.update(|tx| {
+            let restored = tx
+                .execute(
+                    "UPDATE secrets SET deletion_date = NULL WHERE name = ?1",
+                    params![secret_id],
+                )
+                .map_err(sqlite_error)?;
+            if restored == 0 {
+                return Err(Self::not_found(&secret_id));
+            }
+            Ok(())
+        })
+    }
+}
+
+fn sqlite_error(error: rusqlite::Error) -> CliError {
+    CliError::SqliteError(error.to_string())
+}
+
+fn now() -> Option<String> {
+    DateTime::from(std::time::SystemTime::now())
+        .fmt(Format::DateTime)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sqlite, SqliteOptions};
+    use crate::tools::error::CliError;
+    use crate::tools::store::{NewSecret, SecretStore};
+    use std::path::PathBuf;
+
+    // removes the directory even when the test fails
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_based_on_a_stale_version_are_rejected() {
+        let dir =
+            TempDir(std::env::temp_dir().join(format!("tc-secrets-sqlite-{}", std::process::id())));
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let store = Sqlite::new(&SqliteOptions {
+            path: dir.0.join("secrets.db"),
+        });
+        store
+            .create(&NewSecret {
+                name: "app".to_string(),
+                description: None,
+                tags: Default::default(),
+                kms_key_id: None,
+            })
+            .await
+            .unwrap();
+
+        let ours = store.get("app".to_string(), None).await.unwrap();
+        let version = ours.version_id.clone();
+        let theirs = store
+            .put(
+                "app".to_string(),
+                r#"{"dev":"A=1"}"#.to_string(),
+                version.clone(),
+            )
+            .await
+            .unwrap();
+        let result = store
+            .put("app".to_string(), r#"{"dev":"A=2"}"#.to_string(), version)
+            .await;
+
+        assert!(matches!(result, Err(CliError::RemoteConflictError(_))));
+        let latest = store.get("app".to_string(), None).await.unwrap();
+        assert_eq!(latest.version_id, theirs);
+        assert_eq!(latest.data["dev"], "A=1");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_databases_are_only_readable_by_the_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir(
+            std::env::temp_dir().join(format!("tc-secrets-sqlite-mode-{}", std::process::id())),
+        );
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let path = dir.0.join("secrets.db");
+
+        Sqlite::new(&SqliteOptions { path: path.clone() })
+            .connect()
+            .unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,678 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_sdk_ssm::Client;
+use aws_sdk_ssm::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_ssm::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_ssm::types::{ParameterStringFilter, ParameterType, ResourceTypeForTagging, Tag};
+use console::style;
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+use std::collections::{BTreeMap, HashMap};
+
+use super::aws::{AWS, AwsOptions, sdk_error};
+use super::crypto::hash_content;
+use super::error::CliError;
+use super::store::{NewSecret, Secret, SecretFilter, SecretInfo, SecretStore, SecretVersion};
+
+const DEFAULT_PATH: &str = "/tc-secrets";
+// parameter holding the description and tags of a secret, field names never start with a dot
+const MARKER_NAME: &str = ".secret";
+const MARKER_VALUE: &str = "tc-secrets";
+// Parameter Store rejects empty values
+const EMPTY_VALUE: &str = "\n";
+const CURRENT_STAGE: &str = "current";
+const PREVIOUS_STAGE: &str = "previous";
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(try_from = "SsmTable")]
+pub struct SsmOptions {
+    // fields are stored as <path>/<secret_id>/<field_id>
+    pub path: String,
+    pub kms_key_id: Option<String>,
+    pub aws: AwsOptions,
+}
+
+// `deny_unknown_fields` has no effect through `flatten`, leftover keys are collected instead
+#[derive(Deserialize)]
+struct SsmTable {
+    #[serde(default = "default_path")]
+    path: String,
+    kms_key_id: Option<String>,
+    #[serde(flatten)]
+    aws: AwsOptions,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl TryFrom<SsmTable> for SsmOptions {
+    type Error = String;
+
+    fn try_from(table: SsmTable) -> Result<Self, Self::Error> {
+        if let Some(key) = table.unknown.keys().next() {
+            return Err(format!("unknown field `{}` in the ssm backend", key));
+        }
+        Ok(SsmOptions {
+            path: table.path,
+            kms_key_id: table.kms_key_id,
+            aws: table.aws,
+        })
+    }
+}
+
+impl Default for SsmOptions {
+    fn default() -> Self {
+        SsmOptions {
+            path: default_path(),
+            kms_key_id: None,
+            aws: AwsOptions::default(),
+        }
+    }
+}
+
+fn default_path() -> String {
+    DEFAULT_PATH.to_string()
+}
+
+pub struct Ssm {
+    client: Client,
+    path: String,
+    kms_key_id: Option<String>,
+}
+
+struct Field {
+    value: Option<String>,
+    version: i64,
+    last_modified: Option<i64>,
+}
+
+// the fields of a secret at one point in its history
+struct Snapshot {
+    version_id: String,
+    created: Option<i64>,
+    values: BTreeMap<String, String>,
+}
+
+impl Ssm {
+    pub async fn new(options: &SsmOptions) -> Result<Self, CliError> {
+        let config = AWS::sdk_config(&options.aws).await?;
+
+        Ok(Ssm {
+            client: Client::new(&config),
+            path: options.path.trim_end_matches('/').to_string(),
+            kms_key_id: options.kms_key_id.clone(),
+        })
+    }
+
+    fn secret_path(&self, secret_id: &str) -> String {
+        format!("{}/{}", self.path, encode_id(secret_id))
+    }
+
+    fn parameter_name(&self, secret_id: &str, field_id: &str) -> String {
+        format!("{}/{}", self.secret_path(secret_id), encode(field_id))
+    }
+
+    fn not_found(secret_id: &str) -> CliError {
+        CliError::NotFoundError(secret_id.to_string())
+    }
+
+    // fields of the secret and whether the secret exists at all
+    async fn load(
+        &self,
+        secret_id: &str,
+        decrypt: bool,
+    ) -> Result<(BTreeMap<String, Field>, bool), CliError> {
+        let mut fields = BTreeMap::new();
+        let mut exists = false;
+        let mut next_token = None;
+
+        loop {
+            let resp = self
+                .client
+                .get_parameters_by_path()
+                .path(self.secret_path(secret_id))
+                .recursive(false)
+                .with_decryption(decrypt)
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(|e| sdk_error(e, CliError::AwsParameterStoreError))?;
+
+            for parameter in resp.parameters() {
+                let Some(name) = parameter.name().and_then(|name| name.rsplit('/').next()) else {
+                    continue;
+                };
+                exists = true;
+                if name == MARKER_NAME {
+                    continue;
+                }
+
+                fields.insert(
+                    decode(name),
+                    Field {
+                        value: parameter.value().map(stored_to_value),
+                        version: parameter.version(),
+                        last_modified: parameter.last_modified_date().map(DateTime::secs),
+                    },
+                );
+            }
+
+            next_token = resp.next_token().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok((fields, exists))
+    }
+
+    // every state of the secret, oldest first, from the versions of its parameters
+    async fn snapshots(&self, secret_id: &str, decrypt: bool) -> Result<Vec<Snapshot>, CliError> {
+        let (fields, exists) = self.load(secret_id, false).await?;
+        if !exists {
+            return Err(Self::not_found(secret_id));
+        }
+
+        let mut changes = Vec::new();
+        for field_id in fields.keys() {
+            let mut next_token = None;
+            loop {
+                let resp = self
+                    .client
+                    .get_parameter_history()
+                    .name(self.parameter_name(secret_id, field_id))
+                    .with_decryption(decrypt)
+                    .set_next_token(next_token)
+                    .send()
+                    .await
+                    .map_err(|e| sdk_error(e, CliError::AwsParameterStoreError))?;
+
+                changes.extend(resp.parameters().iter().map(|version| {
+                    (
+                        version.last_modified_date().map(DateTime::secs),
+                        field_id.clone(),
+                        version.version(),
+                        version.value().map(stored_to_value).unwrap_or_default(),
+                    )
+                }));
+
+                next_token = resp.next_token().map(String::from);
+                if next_token.is_none() {
+                    break;
+                }
+            }
+        }
+        changes.sort_by_key(|change| (change.0, change.2));
+
+        let mut versions = BTreeMap::new();
+        let mut values = BTreeMap::new();
+        Ok(changes
+            .into_iter()
+            .map(|(created, field_id, version, value)| {
+                versions.insert(field_id.clone(), version);
+                values.insert(field_id, value);
+                Snapshot {
+                    version_id: version_id(&versions),
+                    created,
+                    values: values.clone(),
+                }
+            })
+            .collect())
+    }
+
+    async fn tags(&self, secret_id: &str) -> Result<BTreeMap<String, String>, CliError> {
+        let resp = self
+            .client
+            .list_tags_for_resource()
+            .resource_type(ResourceTypeForTagging::Parameter)
+            .resource_id(self.parameter_name_raw(secret_id, MARKER_NAME))
+            .send()
+            .await
+            .map_err(|e| sdk_error(e, CliError::AwsParameterStoreError))?;
+
+        Ok(resp
+            .tag_list()
+            .iter()
+            .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+            .collect())
+    }
+
+    fn parameter_name_raw(&self, secret_id: &str, name: &str) -> String {
+        format!("{}/{}", self.secret_path(secret_id), name)
+    }
+
+    // metadata of all parameters matching the filters
+    async fn describe_parameters(
+        &self,
+        filters: Vec<ParameterStringFilter>,
+    ) -> Result<Vec<(String, Option<String>, i64, Option<i64>)>, CliError> {
+        let mut parameters = Vec::new();
+        let mut next_token = None;
+
+        loop {
+            let resp = self
+                .client
+                .describe_parameters()
+                .set_parameter_filters(Some(filters.clone()))
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(|e| sdk_error(e, CliError::AwsParameterStoreError))?;
+
+            parameters.extend(resp.parameters().iter().filter_map(|parameter| {
+                Some((
+                    parameter.name()?.to_string(),
+                    parameter.description().map(String::from),
+                    parameter.version(),
+                    parameter.last_modified_date().map(DateTime::secs),
+                ))
+            }));
+
+            next_token = resp.next_token().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(parameters)
+    }
+}
+
+#[async_trait]
+impl SecretStore for Ssm {
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError> {
+        let mut filters = vec![parameter_filter("Path", "Recursive", &self.path)?];
+        if let Some(prefix) = &filter.name_prefix {
+            let name_prefix = format!("{}/{}", self.path, encode_id(prefix));
+            filters.push(parameter_filter("Name", "BeginsWith", &name_prefix)?);
+        }
+
+        // group the parameters by the secret they belong to
+        let mut secrets: BTreeMap<String, SecretInfo> = BTreeMap::new();
+        let mut versions: HashMap<String, BTreeMap<String, i64>> = HashMap::new();
+        let mut markers = Vec::new();
+        let root = format!("{}/", self.path);
+        for (name, description, version, last_modified) in self.describe_parameters(filters).await?
+        {
+            let Some((secret_path, field_name)) = name
+                .strip_prefix(&root)
+                .and_then(|name| name.rsplit_once('/'))
+            else {
+                continue;
+            };
+            let secret_id = decode_id(secret_path);
+
+            let info = secrets
+                .entry(secret_id.clone())
+                .or_insert_with(|| SecretInfo {
+                    name: secret_id.clone(),
+                    description: None,
+                    last_changed: None,
+                    version_id: None,
+                    tags: BTreeMap::new(),
+                });
+            let last_changed = last_modified.and_then(format_date);
+            if last_changed > info.last_changed {
+                info.last_changed = last_changed;
+            }
+
+            if field_name == MARKER_NAME {
+                info.description = description;
+                markers.push(secret_id);
+            } else {
+                versions
+                    .entry(secret_id)
+                    .or_default()
+                    .insert(decode(field_name), version);
+            }
+        }
+
+        for secret_id in markers {
+            let tags = self.tags(&secret_id).await?;
+            if let Some(info) = secrets.get_mut(&secret_id) {
+                info.tags = tags;
+            }
+        }
+
+        Ok(secrets
+            .into_values()
+            .map(|mut info| {
+                info.version_id =
+                    Some(version_id(&versions.remove(&info.name).unwrap_or_default()));
+                info
+            })
+            .filter(|info| filter.matches(info))
+            .collect())
+    }
+
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError> {
+        let Some(version_id) = version_id else {
+            let (fields, exists) = self.load(&secret_id, true).await?;
+            if !exists {
+                return Err(Self::not_found(&secret_id));
+            }
+
+            return Ok(Secret {
+                version_id: Some(field_version_id(&fields)),
+                data: fields
+                    .into_iter()
+                    .map(|(field_id, field)| (field_id, field.value.unwrap_or_default()))
+                    .collect(),
+            });
+        };
+
+        let snapshot = self
+            .snapshots(&secret_id, true)
+            .await?
+            .into_iter()
+            .rev()
+            .find(|snapshot| snapshot.version_id == version_id)
+            .ok_or_else(|| {
+                CliError::AwsParameterStoreError(format!(
+                    "version {} of secret {} not found",
+                    version_id, secret_id
+                ))
+            })?;
+
+        Ok(Secret {
+            data: snapshot.values.into_iter().collect(),
+            version_id: Some(snapshot.version_id),
+        })
+    }
+
+    async fn put(
+        &self,
+        secret_id: String,
+        secret_value: String,
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError> {
+        let values: BTreeMap<String, String> = serde_json::from_str(&secret_value)
+            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?;
+
+        let (fields, exists) = self.load(&secret_id, true).await?;
+        if !exists {
+            return Err(Self::not_found(&secret_id));
+        }
+        if let Some(expected) = &expected_version
+            && &field_version_id(&fields) != expected
+        {
+            return Err(CliError::RemoteConflictError(format!(
+                "secret {} changed since it was read",
+                secret_id
+            )));
+        }
+
+        // only the changed fields are written, each one gets a new parameter version
+        let mut versions: BTreeMap<String, i64> = fields
+            .iter()
+            .map(|(field_id, field)| (field_id.clone(), field.version))
+            .collect();
+        // Parameter Store has no transactions, so a failure can leave some fields written
+        let mut written = Vec::new();
+        let mut overwritten = Vec::new();
+        for (field_id, value) in &values {
+            let field = fields.get(field_id);
+            if field.and_then(|field| field.value.as_ref()) == Some(value) {
+                continue;
+            }
+
+            let resp = self
+                .client
+                .put_parameter()
+                .name(self.parameter_name(&secret_id, field_id))
+                .value(value_to_stored(value))
+                .r#type(ParameterType::SecureString)
+                .set_key_id(self.kms_key_id.clone())
+                .overwrite(true)
+                .send()
+                .await
+                .map_err(|e| partial_write(&secret_id, &written, e))?;
+
+            // someone else wrote the same field when the version skipped one
+            let expected = field.map_or(1, |field| field.version + 1);
+            if resp.version() != expected {
+                overwritten.push(format!("{}/{}", secret_id, field_id));
+            }
+            versions.insert(field_id.clone(), resp.version());
+            written.push(field_id.clone());
+        }
+
+        for field_id in fields
+            .keys()
+            .filter(|field_id| !values.contains_key(*field_id))
+        {
+            self.client
+                .delete_parameter()
+                .name(self.parameter_name(&secret_id, field_id))
+                .send()
+                .await
+                .map_err(|e| partial_write(&secret_id, &written, e))?;
+            versions.remove(field_id);
+            written.push(field_id.clone());
+        }
+
+        if !overwritten.is_empty() {
+            return Err(CliError::FieldConflictError(format!(
+                "field {} was changed by someone else while pushing, check its history",
+                overwritten.join(", ")
+            )));
+        }
+
+        Ok(Some(version_id(&versions)))
+    }
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError> {
+        let (fields, exists) = self.load(&secret_id, false).await?;
+        if !exists {
+            return Err(Self::not_found(&secret_id));
+        }
+
+        let marker = self
+            .describe_parameters(vec![parameter_filter(
+                "Name",
+                "Equals",
+                &self.parameter_name_raw(&secret_id, MARKER_NAME),
+            )?])
+            .await?
+            .pop();
+        let tags = match marker {
+            Some(_) => self.tags(&secret_id).await?,
+            None => BTreeMap::new(),
+        };
+        let last_changed = fields
+            .values()
+            .filter_map(|field| field.last_modified)
+            .chain(marker.as_ref().and_then(|marker| marker.3))
+            .max()
+            .and_then(format_date);
+
+        Ok(SecretInfo {
+            name: secret_id,
+            description: marker.and_then(|marker| marker.1),
+            last_changed,
+            version_id: Some(field_version_id(&fields)),
+            tags,
+        })
+    }
+
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError> {
+        let snapshots = self.snapshots(&secret_id, false).await?;
+
+        Ok(snapshots
+            .into_iter()
+            .rev()
+            .enumerate()
+            .map(|(idx, snapshot)| SecretVersion {
+                version_id: snapshot.version_id,
+                created_date: snapshot.created.and_then(format_date),
+                stages: match idx {
+                    0 => vec![CURRENT_STAGE.to_string()],
+                    1 => vec![PREVIOUS_STAGE.to_string()],
+                    _ => Vec::new(),
+                },
+            })
+            .collect())
+    }
+
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError> {
+        if self.load(&new_secret.name, false).await?.1 {
+            return Err(CliError::AwsParameterStoreError(format!(
+                "secret {} already exists",
+                new_secret.name
+            )));
+        }
+
+        let tags = new_secret
+            .tags
+            .iter()
+            .map(|(key, value)| {
+                Tag::builder()
+                    .key(key)
+                    .value(value)
+                    .build()
+                    .map_err(|e| CliError::AwsParameterStoreError(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.client
+            .put_parameter()
+            .name(self.parameter_name_raw(&new_secret.name, MARKER_NAME))
+            .value(MARKER_VALUE)
+            .r#type(ParameterType::String)
+            .set_description(new_secret.description.clone())
+            .set_tags((!tags.is_empty()).then_some(tags))
+            .send()
+            .await
+            .map_err(|e| sdk_error(e, CliError::AwsParameterStoreError))?;
+
+        Ok(Some(version_id(&BTreeMap::new())))
+    }
+
+    async fn delete(
+        &self,
+        _secret_id: String,
+        _recovery_days: i64,
+    ) -> Result<Option<String>, CliError> {
+        Err(CliError::AwsParameterStoreError(
+            "Parameter Store has no recovery window. Remove the fields with `tc-secrets field rm` instead.".to_string(),
+        ))
+    }
+
+    async fn restore(&self, _secret_id: String) -> Result<(), CliError> {
+        Err(CliError::AwsParameterStoreError(
+            "Parameter Store cannot restore deleted parameters.".to_string(),
+        ))
+    }
+}
+
+fn parameter_filter(
+    key: &str,
+    option: &str,
+    value: &str,
+) -> Result<ParameterStringFilter, CliError> {
+    ParameterStringFilter::builder()
+        .key(key)
+        .option(option)
+        .values(value)
+        .build()
+        .map_err(|e| CliError::AwsParameterStoreError(e.to_string()))
+}
+
+// the version of a secret is derived from the versions of its parameters
+fn version_id(versions: &BTreeMap<String, i64>) -> String {
+    let content: Vec<String> = versions
+        .iter()
+        .map(|(field_id, version)| format!("{}:{}", field_id, version))
+        .collect();
+    hash_content(&content.join("\n"))[..16].to_string()
+}
+
+// reports the fields that were changed before a write failed, the rest keep their old value
+fn partial_write<E, R>(secret_id: &str, written: &[String], error: SdkError<E, R>) -> CliError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    if !written.is_empty() {
+        eprintln!(
+            "{} secret {} was only partially updated, the fields {} were already written. Push again to write the rest.",
+            style("Warning:").yellow().bold(),
+            style(secret_id).cyan(),
+            written.join(", ")
+        );
+    }
+    sdk_error(error, CliError::AwsParameterStoreError)
+}
+
+fn field_version_id(fields: &BTreeMap<String, Field>) -> String {
+    version_id(
+        &fields
+            .iter()
+            .map(|(field_id, field)| (field_id.clone(), field.version))
+            .collect(),
+    )
+}
+
+fn format_date(secs: i64) -> Option<String> {
+    DateTime::from_secs(secs).fmt(DateTimeFormat::DateTime).ok()
+}
+
+fn value_to_stored(value: &str) -> &str {
+    if value.is_empty() { EMPTY_VALUE } else { value }
+}
+
+fn stored_to_value(value: &str) -> String {
+    if value == EMPTY_VALUE {
+        String::new()
+    } else {
+        value.to_string()
+    }
+}
+
+// parameter names only allow letters, digits and `.-_/`, everything else is escaped as _XX
+fn encode(name: &str) -> String {
+    name.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' {
+                (byte as char).to_string()
+            } else {
+                format!("_{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+fn encode_id(secret_id: &str) -> String {
+    secret_id
+        .split('/')
+        .map(encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn decode(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'_'
+            && let Some(byte) = name
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn decode_id(secret_path: &str) -> String {
+    secret_path
+        .split('/')
+        .map(decode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
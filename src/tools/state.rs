@@ -0,0 +1,85 @@
+use anyhow::Result;
+use aws_smithy_types::date_time::{DateTime, Format};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::config::EnvFile;
+use super::crypto::Encryption;
+use super::error::CliError;
+use super::files::{create_private_dir, state_file_path, write_atomic};
+
+#[derive(Default, Serialize, Deserialize)]
+struct StateData {
+    // encrypted snapshot of the content at the last successful sync
+    #[serde(default)]
+    base: Option<String>,
+    // time of the last successful sync
+    #[serde(default)]
+    synced_at: Option<String>,
+}
+
+pub struct SyncState {
+    path: PathBuf,
+    data: StateData,
+}
+
+impl SyncState {
+    pub fn load(filepath: &Path) -> Result<Self, CliError> {
+        let path = state_file_path(filepath, "state.json")?;
+
+        let data = if path.exists() {
+            let content = std::fs::read_to_string(&path)?;
+            serde_json::from_str(&content).map_err(|e| {
+                CliError::StateFileError(format!("{}: {}", path.to_string_lossy(), e))
+            })?
+        } else {
+            StateData::default()
+        };
+
+        Ok(SyncState { path, data })
+    }
+
+    pub fn base(&self, encryption: &Encryption) -> Result<Option<String>, CliError> {
+        self.data
+            .base
+            .clone()
+            .map(|base| encryption.decrypt(base))
+            .transpose()
+    }
+
+    // the body of the last synced content, decrypted rather than hashed so the state file
+    // doesn't hold a checksum of the plaintext that could be brute-forced
+    pub fn synced_body(&self, encryption: &Encryption) -> Result<Option<String>, CliError> {
+        self.base(encryption)?
+            .map(|base| Ok(EnvFile::new_remote(base)?.body()))
+            .transpose()
+    }
+
+    pub fn synced_at(&self) -> Option<&str> {
+        self.data.synced_at.as_deref()
+    }
+
+    pub fn record_sync(
+        &mut self,
+        encryption: &Encryption,
+        content: String,
+    ) -> Result<(), CliError> {
+        self.data.base = Some(encryption.encrypt(content)?);
+        self.data.synced_at = DateTime::from(std::time::SystemTime::now())
+            .fmt(Format::DateTime)
+            .ok();
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(), CliError> {
+        if let Some(parent) = self.path.parent() {
+            create_private_dir(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(&self.data)
+            .map_err(|e| CliError::StateFileError(e.to_string()))?;
+        write_atomic(&self.path, &content)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,591 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use console::style;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use tokio::sync::OnceCell;
+
+use super::aws::{AWS, AwsOptions};
+use super::error::CliError;
+use super::gitfile::{GitFile, GitOptions};
+use super::lease::is_lease_field;
+use super::memory::MemoryStore;
+use super::s3::{S3, S3Options};
+use super::sqlite::{Sqlite, SqliteOptions};
+use super::ssm::{Ssm, SsmOptions};
+use super::vault::{Vault, VaultOptions};
+
+const MAX_PUT_ATTEMPTS: usize = 5;
+
+#[async_trait]
+pub trait SecretStore: Sync {
+    // all matching secrets sorted by name
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError>;
+
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError>;
+
+    // fails with a RemoteConflictError when the current version is not the expected one
+    async fn put(
+        &self,
+        secret_id: String,
+        secret_value: String,
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError>;
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError>;
+
+    // newest version first
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError>;
+
+    // creates a secret without fields
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError>;
+
+    // schedules the deletion after the recovery window and returns its date
+    async fn delete(
+        &self,
+        secret_id: String,
+        recovery_days: i64,
+    ) -> Result<Option<String>, CliError>;
+
+    // cancels a scheduled deletion
+    async fn restore(&self, secret_id: String) -> Result<(), CliError>;
+
+    // false when `delete` takes effect right away and only `restore` reverses it
+    fn has_recovery_window(&self) -> bool {
+        true
+    }
+
+    // the secret to reapply our changes to after a conflicting put: the latest version, unless
+    // the put replaced a concurrent write whose fields have to be kept
+    async fn rebase(&self, secret_id: String) -> Result<Secret, CliError> {
+        self.get(secret_id, None).await
+    }
+
+    async fn update_field(
+        &self,
+        secret_id: String,
+        secret: &mut Secret,
+        field_id: String,
+        value: String,
+    ) -> Result<(), CliError> {
+        self.update_fields(secret_id, secret, vec![(field_id, Some(value))])
+            .await
+    }
+
+    // writes all changes in a single version, a change without a value removes the field
+    async fn update_fields(
+        &self,
+        secret_id: String,
+        secret: &mut Secret,
+        changes: Vec<(String, Option<String>)>,
+    ) -> Result<(), CliError> {
+        let originals: Vec<Option<String>> = changes
+            .iter()
+            .map(|(field_id, _)| secret.data.get(field_id).cloned())
+            .collect();
+
+        for _ in 0..MAX_PUT_ATTEMPTS {
+            for (field_id, value) in &changes {
+                match value {
+                    Some(value) => secret.put_field(field_id.clone(), value.clone())?,
+                    None => {
+                        secret.data.remove(field_id);
+                    }
+                }
+            }
+
+            match self
+                .put(
+                    secret_id.clone(),
+                    secret.to_string()?,
+                    secret.version_id.clone(),
+                )
+                .await
+            {
+                Ok(version_id) => {
+                    secret.version_id = version_id;
+                    return Ok(());
+                }
+                Err(CliError::RemoteConflictError(reason)) => {
+                    // re-read the secret and reapply only our field changes, which may already
+                    // be in it when our write landed before the conflict was noticed
+                    let latest = self.rebase(secret_id.clone()).await?;
+                    for ((field_id, value), original) in changes.iter().zip(&originals) {
+                        // a new field is empty until its first write creates it remotely
+                        let latest_value = latest.data.get(field_id);
+                        let changed = latest_value.map(String::as_str).unwrap_or_default()
+                            != original.as_deref().unwrap_or_default();
+                        if changed && latest_value != value.as_ref() {
+                            return Err(CliError::FieldConflictError(format!(
+                                "field {}/{} was changed by someone else while pushing",
+                                secret_id, field_id
+                            )));
+                        }
+                    }
+
+                    println!(
+                        "Remote {} ({}). Retrying with the latest version...",
+                        reason,
+                        style(&secret_id).cyan()
+                    );
+                    *secret = latest;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(CliError::RemoteConflictError(format!(
+            "secret {} kept changing, gave up after {} attempts",
+            secret_id, MAX_PUT_ATTEMPTS
+        )))
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Backend {
+    Aws(AwsOptions),
+    // every field is a SecureString parameter
+    Ssm(SsmOptions),
+    // every secret is a JSON object in a bucket
+    S3(S3Options),
+    // every secret is a path of a KV v2 secrets engine
+    Vault(VaultOptions),
+    // every secret is an entry of a JSON file, e.g. committed next to the code
+    Git(GitOptions),
+    // every secret is a row of a SQLite database, with a row per version of each field
+    Sqlite(SqliteOptions),
+    // nothing is persisted, meant for trying out commands and for tests
+    Memory {
+        // initial fields of each secret
+        #[serde(default)]
+        secrets: HashMap<String, HashMap<String, String>>,
+    },
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Aws(AwsOptions::default())
+    }
+}
+
+impl Backend {
+    pub fn with_aws_options(self, options: AwsOptions) -> Self {
+        // options from the command line take precedence
+        match self {
+            Backend::Aws(config) => Backend::Aws(options.or(config)),
+            Backend::Ssm(config) => Backend::Ssm(SsmOptions {
+                aws: options.or(config.aws),
+                ..config
+            }),
+            Backend::S3(config) => Backend::S3(S3Options {
+                aws: options.or(config.aws),
+                ..config
+            }),
+            // Vault only shares the retry settings
+            Backend::Vault(config) => Backend::Vault(VaultOptions {
+                max_attempts: options.max_attempts.or(config.max_attempts),
+                timeout: options.timeout.or(config.timeout),
+                ..config
+            }),
+            backend => backend,
+        }
+    }
+
+    pub fn same_type(&self, other: &Backend) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    // resolves relative paths against the directory of the manifest
+    pub fn with_root(self, root: &Path) -> Self {
+        match self {
+            Backend::Git(options) => Backend::Git(GitOptions {
+                path: root.join(options.path),
+            }),
+            Backend::Sqlite(options) => Backend::Sqlite(SqliteOptions {
+                path: root.join(options.path),
+            }),
+            backend => backend,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, CliError> {
+        match name {
+            "aws" => Ok(Backend::default()),
+            "ssm" => Ok(Backend::Ssm(SsmOptions::default())),
+            "s3" => Ok(Backend::S3(S3Options::default())),
+            "vault" => Ok(Backend::Vault(VaultOptions::default())),
+            "git" => Ok(Backend::Git(GitOptions::default())),
+            "sqlite" => Ok(Backend::Sqlite(SqliteOptions::default())),
+            "memory" => Ok(Backend::Memory {
+                secrets: HashMap::new(),
+            }),
+            _ => Err(CliError::ManifestError(format!(
+                "unknown backend '{}'",
+                name
+            ))),
+        }
+    }
+
+    // a missing secret is reported with similar names when `suggest` is set
+    pub async fn open(&self, suggest: bool) -> Result<Box<dyn SecretStore>, CliError> {
+        let store: Box<dyn SecretStore> = match self {
+            Backend::Aws(options) => Box::new(AWS::new(options).await?),
+            Backend::Ssm(options) => Box::new(Ssm::new(options).await?),
+            Backend::S3(options) => Box::new(S3::new(options).await?),
+            Backend::Vault(options) => Box::new(Vault::new(options).await?),
+            Backend::Git(options) => Box::new(GitFile::new(options)),
+            Backend::Sqlite(options) => Box::new(Sqlite::new(options)),
+            Backend::Memory { secrets } => Box::new(MemoryStore::with_secrets(secrets)?),
+        };
+        if !suggest {
+            return Ok(store);
+        }
+        Ok(Box::new(Suggestions {
+            store,
+            names: OnceCell::new(),
+        }))
+    }
+}
+
+// suggests similar names when a secret is not found, whatever the backend
+struct Suggestions {
+    store: Box<dyn SecretStore>,
+    // listed once on the first miss, since listing pages through every secret
+    names: OnceCell<Vec<String>>,
+}
+
+impl Suggestions {
+    async fn hint<T>(&self, result: Result<T, CliError>) -> Result<T, CliError> {
+        let Err(CliError::NotFoundError(secret_id)) = &result else {
+            return result;
+        };
+        // the suggestions are best effort, the original error is what matters
+        let listed = self
+            .names
+            .get_or_try_init(|| async {
+                let secrets = self.store.list(&SecretFilter::default()).await?;
+                Ok::<_, CliError>(secrets.into_iter().map(|info| info.name).collect())
+            })
+            .await;
+        let Ok(secrets) = listed else {
+            return result;
+        };
+
+        let names = similar_names(secret_id, secrets.iter().map(String::as_str));
+        if names.is_empty() {
+            return result;
+        }
+        Err(CliError::NotFoundError(format!(
+            "{}, did you mean {}?",
+            secret_id,
+            names.join(" or ")
+        )))
+    }
+}
+
+#[async_trait]
+impl SecretStore for Suggestions {
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError> {
+        self.store.list(filter).await
+    }
+
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError> {
+        self.hint(self.store.get(secret_id, version_id).await).await
+    }
+
+    async fn put(
+        &self,
+        secret_id: String,
+        secret_value: String,
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError> {
+        let result = self
+            .store
+            .put(secret_id, secret_value, expected_version)
+            .await;
+        self.hint(result).await
+    }
+
+    async fn rebase(&self, secret_id: String) -> Result<Secret, CliError> {
+        self.hint(self.store.rebase(secret_id).await).await
+    }
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError> {
+        self.hint(self.store.describe(secret_id).await).await
+    }
+
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError> {
+        self.hint(self.store.history(secret_id).await).await
+    }
+
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError> {
+        self.store.create(new_secret).await
+    }
+
+    async fn delete(
+        &self,
+        secret_id: String,
+        recovery_days: i64,
+    ) -> Result<Option<String>, CliError> {
+        self.hint(self.store.delete(secret_id, recovery_days).await)
+            .await
+    }
+
+    async fn restore(&self, secret_id: String) -> Result<(), CliError> {
+        self.hint(self.store.restore(secret_id).await).await
+    }
+
+    fn has_recovery_window(&self) -> bool {
+        self.store.has_recovery_window()
+    }
+}
+
+// up to three names within a few typos of the name, closest first
+fn similar_names<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(2);
+
+    let mut similar: Vec<(usize, &str)> = candidates
+        .filter_map(|candidate| {
+            let distance = edit_distance(&name, &candidate.to_lowercase());
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect();
+    similar.sort();
+    similar.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+// the Levenshtein distance between both strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+pub struct SecretInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub last_changed: Option<String>,
+    pub version_id: Option<String>,
+    pub tags: BTreeMap<String, String>,
+}
+
+pub struct NewSecret {
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub kms_key_id: Option<String>,
+}
+
+#[derive(Default)]
+pub struct SecretFilter {
+    pub name_prefix: Option<String>,
+    // a tag without a value only requires the key
+    pub tags: Vec<(String, Option<String>)>,
+}
+
+impl SecretFilter {
+    pub fn new(name_prefix: Option<String>, tags: &[String]) -> Self {
+        let tags = tags
+            .iter()
+            .map(|tag| match tag.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (tag.clone(), None),
+            })
+            .collect();
+
+        SecretFilter { name_prefix, tags }
+    }
+
+    pub fn matches(&self, info: &SecretInfo) -> bool {
+        // name prefixes are not case-sensitive, like in Secrets Manager
+        let name_matches = self
+            .name_prefix
+            .as_ref()
+            .is_none_or(|prefix| info.name.to_lowercase().starts_with(&prefix.to_lowercase()));
+        let tags_match = self.tags.iter().all(|(key, value)| match value {
+            Some(value) => info.tags.get(key) == Some(value),
+            None => info.tags.contains_key(key),
+        });
+
+        name_matches && tags_match
+    }
+}
+
+pub struct SecretVersion {
+    pub version_id: String,
+    pub created_date: Option<String>,
+    pub stages: Vec<String>,
+}
+
+pub struct Secret {
+    pub data: HashMap<String, String>,
+    pub version_id: Option<String>,
+}
+
+impl Secret {
+    pub fn new(secret: String, version_id: Option<String>) -> Result<Self, CliError> {
+        let data: HashMap<String, String> = serde_json::from_str(&secret)
+            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?;
+
+        Ok(Secret { data, version_id })
+    }
+
+    pub fn list_fields(&self) -> Vec<String> {
+        // skip the reserved metadata entries
+        self.data
+            .keys()
+            .filter(|key| !is_lease_field(key))
+            .cloned()
+            .collect()
+    }
+
+    pub fn load_field(&self, field_id: String) -> Result<String, CliError> {
+        self.data.get(field_id.as_str()).cloned().ok_or_else(|| {
+            CliError::AwsSecretsFormatError(format!("Field '{}' not found", field_id))
+        })
+    }
+
+    pub fn put_field(&mut self, field_id: String, value: String) -> Result<(), CliError> {
+        self.data.insert(field_id, value);
+        Ok(())
+    }
+
+    pub fn to_string(&self) -> Result<String, CliError> {
+        serde_json::to_string(&self.data)
+            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryStore, SecretStore, Suggestions, edit_distance, similar_names};
+    use crate::tools::error::CliError;
+    use std::collections::HashMap;
+    use tokio::sync::OnceCell;
+
+    fn store() -> MemoryStore {
+        let fields = HashMap::from([("dev".to_string(), "A=1".to_string())]);
+        MemoryStore::with_secrets(&HashMap::from([("app".to_string(), fields)])).unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_new_field_is_created_by_its_first_write_after_a_concurrent_one() {
+        let store = store();
+        let mut secret = store.get("app".to_string(), None).await.unwrap();
+        // a field selected as new only exists locally
+        secret.data.insert("prod".to_string(), String::new());
+        assert!(
+            !store
+                .get("app".to_string(), None)
+                .await
+                .unwrap()
+                .data
+                .contains_key("prod")
+        );
+
+        let mut other = store.get("app".to_string(), None).await.unwrap();
+        store
+            .update_field(
+                "app".to_string(),
+                &mut other,
+                "dev".to_string(),
+                "A=2".to_string(),
+            )
+            .await
+            .unwrap();
+
+        store
+            .update_field(
+                "app".to_string(),
+                &mut secret,
+                "prod".to_string(),
+                "B=1".to_string(),
+            )
+            .await
+            .unwrap();
+        let latest = store.get("app".to_string(), None).await.unwrap();
+        assert_eq!(latest.data["dev"], "A=2");
+        assert_eq!(latest.data["prod"], "B=1");
+    }
+
+    #[tokio::test]
+    async fn a_field_changed_concurrently_is_a_conflict() {
+        let store = store();
+        let mut secret = store.get("app".to_string(), None).await.unwrap();
+
+        let mut other = store.get("app".to_string(), None).await.unwrap();
+        store
+            .update_field(
+                "app".to_string(),
+                &mut other,
+                "dev".to_string(),
+                "A=2".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let result = store
+            .update_field(
+                "app".to_string(),
+                &mut secret,
+                "dev".to_string(),
+                "A=3".to_string(),
+            )
+            .await;
+        assert!(matches!(result, Err(CliError::FieldConflictError(_))));
+    }
+
+    #[test]
+    fn edit_distance_counts_insertions_deletions_and_substitutions() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("app", "app"), 0);
+        assert_eq!(edit_distance("", "app"), 3);
+        assert_eq!(edit_distance("app", "apps"), 1);
+        assert_eq!(edit_distance("apps", "app"), 1);
+        assert_eq!(edit_distance("app", "apq"), 1);
+        assert_eq!(edit_distance("paymnets", "payments"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("café", "cafe"), 1);
+    }
+
+    #[test]
+    fn similar_names_are_the_closest_three_ignoring_case() {
+        let candidates = ["payments", "Payment", "paymentz", "payments-v2", "users"];
+
+        assert_eq!(
+            similar_names("payments", candidates.into_iter()),
+            vec!["payments", "Payment", "paymentz"]
+        );
+        assert_eq!(
+            similar_names("PAYMNETS", candidates.into_iter()),
+            vec!["payments"]
+        );
+        assert!(similar_names("orders", candidates.into_iter()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_secrets_are_reported_with_similar_names() {
+        let store = Suggestions {
+            store: Box::new(store()),
+            names: OnceCell::new(),
+        };
+
+        let result = store.get("ap".to_string(), None).await;
+        assert!(
+            matches!(result, Err(CliError::NotFoundError(message)) if message == "ap, did you mean app?")
+        );
+        let result = store.describe("orders".to_string()).await;
+        assert!(matches!(result, Err(CliError::NotFoundError(message)) if message == "orders"));
+    }
+}
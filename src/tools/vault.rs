@@ -0,0 +1,632 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_smithy_types::date_time::{DateTime, Format};
+use console::style;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::error::CliError;
+use super::store::{NewSecret, Secret, SecretFilter, SecretInfo, SecretStore, SecretVersion};
+
+const DEFAULT_ADDRESS: &str = "http://127.0.0.1:8200";
+const DEFAULT_MOUNT: &str = "secret";
+const DEFAULT_APPROLE_MOUNT: &str = "approle";
+// custom metadata key holding the description, every other key is a tag
+const DESCRIPTION_KEY: &str = "description";
+const CURRENT_STAGE: &str = "current";
+const PREVIOUS_STAGE: &str = "previous";
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct VaultOptions {
+    // falls back to VAULT_ADDR
+    pub address: Option<String>,
+    // path of the KV v2 secrets engine
+    pub mount: Option<String>,
+    // falls back to VAULT_NAMESPACE
+    pub namespace: Option<String>,
+    // logs in with AppRole instead of a token, falls back to VAULT_ROLE_ID
+    pub role_id: Option<String>,
+    pub approle_mount: Option<String>,
+    // attempts per request, including retries of throttled requests and server errors
+    pub max_attempts: Option<u32>,
+    // seconds to wait for each attempt
+    pub timeout: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+enum Method {
+    Get,
+    Post,
+    Delete,
+}
+
+pub struct Vault {
+    agent: ureq::Agent,
+    address: String,
+    mount: String,
+    namespace: Option<String>,
+    token: String,
+    max_attempts: u32,
+}
+
+impl Vault {
+    pub async fn new(options: &VaultOptions) -> Result<Self, CliError> {
+        let address = options
+            .address
+            .clone()
+            .or_else(|| std::env::var("VAULT_ADDR").ok())
+            .unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_global(Some(Duration::from_secs(
+                options.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS),
+            )))
+            .build()
+            .new_agent();
+
+        let mut vault = Vault {
+            agent,
+            address: address.trim_end_matches('/').to_string(),
+            mount: options
+                .mount
+                .as_deref()
+                .unwrap_or(DEFAULT_MOUNT)
+                .trim_matches('/')
+                .to_string(),
+            namespace: options
+                .namespace
+                .clone()
+                .or_else(|| std::env::var("VAULT_NAMESPACE").ok()),
+            token: String::new(),
+            max_attempts: options.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1),
+        };
+        vault.token = vault.login(options).await?;
+
+        Ok(vault)
+    }
+
+    // verifies the token and reports who it belongs to
+    pub async fn check(options: &VaultOptions) -> Result<Self, CliError> {
+        let vault = Self::new(options).await?;
+        let resp = vault
+            .send(Method::Get, "auth/token/lookup-self", None)
+            .await
+            .and_then(|(status, body)| expect_ok(status, body, None))
+            .map_err(|e| CliError::VaultError(format!("Failed to look up the token: {}", e)))?;
+
+        println!(
+            "Vault: {} as {}",
+            style(&vault.address).cyan(),
+            style(resp["data"]["display_name"].as_str().unwrap_or("unknown")).cyan()
+        );
+        Ok(vault)
+    }
+
+    async fn login(&self, options: &VaultOptions) -> Result<String, CliError> {
+        let role_id = options
+            .role_id
+            .clone()
+            .or_else(|| std::env::var("VAULT_ROLE_ID").ok());
+
+        let Some(role_id) = role_id else {
+            // the token of `vault login` is kept in ~/.vault-token
+            return std::env::var("VAULT_TOKEN")
+                .ok()
+                .or_else(|| {
+                    let home = std::env::var_os("HOME")?;
+                    let token = std::fs::read_to_string(PathBuf::from(home).join(".vault-token"));
+                    token.ok().map(|token| token.trim().to_string())
+                })
+                .filter(|token| !token.is_empty())
+                .ok_or_else(|| {
+                    CliError::VaultError(
+                        "No token found. Set VAULT_TOKEN, run `vault login` or configure AppRole with VAULT_ROLE_ID and VAULT_SECRET_ID.".to_string(),
+                    )
+                });
+        };
+
+        let secret_id = std::env::var("VAULT_SECRET_ID").map_err(|_| {
+            CliError::VaultError("AppRole login requires VAULT_SECRET_ID to be set.".to_string())
+        })?;
+        let mount = options
+            .approle_mount
+            .as_deref()
+            .unwrap_or(DEFAULT_APPROLE_MOUNT);
+
+        let (status, body) = self
+            .send(
+                Method::Post,
+                &format!("auth/{}/login", mount.trim_matches('/')),
+                Some(json!({ "role_id": role_id, "secret_id": secret_id })),
+            )
+            .await?;
+        let body = expect_ok(status, body, None)?;
+
+        body["auth"]["client_token"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| CliError::VaultError("AppRole login returned no token".to_string()))
+    }
+
+    // sends a request to the HTTP API and returns the status with the parsed body,
+    // throttled requests, server errors and failed connections are retried with backoff
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<(u16, Value), CliError> {
+        let mut attempt = 1;
+        loop {
+            let result = self.send_once(method, path, body.clone()).await;
+            let retry = match &result {
+                Ok((status, _)) => *status == 429 || *status >= 500,
+                Err(e) => matches!(e, CliError::TimeoutError(_) | CliError::NetworkError(_)),
+            };
+            if !retry || attempt >= self.max_attempts {
+                return result;
+            }
+            tokio::time::sleep(backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send_once(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<(u16, Value), CliError> {
+        let agent = self.agent.clone();
+        let url = format!("{}/v1/{}", self.address, path);
+        let token = self.token.clone();
+        let namespace = self.namespace.clone();
+
+        // ureq is blocking, keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            let resp = match method {
+                Method::Get => with_headers(agent.get(&url), &token, &namespace).call(),
+                Method::Delete => with_headers(agent.delete(&url), &token, &namespace).call(),
+                Method::Post => with_headers(agent.post(&url), &token, &namespace)
+                    .send_json(body.unwrap_or_else(|| json!({}))),
+            };
+            let mut resp = resp.map_err(|e| request_error(&url, e))?;
+
+            let status = resp.status().as_u16();
+            let text = resp
+                .body_mut()
+                .read_to_string()
+                .map_err(|e| CliError::VaultError(e.to_string()))?;
+            let body = if text.trim().is_empty() {
+                Value::Null
+            } else {
+                serde_json::from_str(&text).map_err(|e| CliError::VaultError(e.to_string()))?
+            };
+
+            Ok((status, body))
+        })
+        .await
+        .map_err(|e| CliError::VaultError(e.to_string()))?
+    }
+
+    fn data_path(&self, secret_id: &str) -> String {
+        format!("{}/data/{}", self.mount, encode_path(secret_id))
+    }
+
+    fn metadata_path(&self, secret_id: &str) -> String {
+        format!("{}/metadata/{}", self.mount, encode_path(secret_id))
+    }
+
+    fn not_found(secret_id: &str) -> CliError {
+        CliError::NotFoundError(secret_id.to_string())
+    }
+
+    // metadata of a secret whose current version has not been deleted
+    async fn metadata(&self, secret_id: &str) -> Result<Value, CliError> {
+        let (status, body) = self
+            .send(Method::Get, &self.metadata_path(secret_id), None)
+            .await?;
+        if status == 404 {
+            return Err(Self::not_found(secret_id));
+        }
+        let metadata = expect_ok(status, body, Some(secret_id))?["data"].take();
+
+        let current = metadata["current_version"].as_u64().unwrap_or(0);
+        if !is_readable(&metadata["versions"][current.to_string()]) {
+            return Err(CliError::VaultError(format!(
+                "secret {} is deleted",
+                secret_id
+            )));
+        }
+        Ok(metadata)
+    }
+
+    // names of all secrets below the path, depth first
+    async fn list_keys(&self, path: String) -> Result<Vec<String>, CliError> {
+        let mut names = Vec::new();
+        let mut folders = vec![path];
+
+        while let Some(folder) = folders.pop() {
+            let (status, body) = self
+                .send(
+                    Method::Get,
+                    &format!("{}/metadata/{}?list=true", self.mount, encode_path(&folder)),
+                    None,
+                )
+                .await?;
+            if status == 404 {
+                continue;
+            }
+
+            let body = expect_ok(status, body, None)?;
+            for key in body["data"]["keys"].as_array().into_iter().flatten() {
+                let Some(key) = key.as_str() else {
+                    continue;
+                };
+                let name = format!("{}{}", folder, key);
+                if key.ends_with('/') {
+                    folders.push(name);
+                } else {
+                    names.push(name);
+                }
+            }
+        }
+
+        Ok(names)
+    }
+}
+
+#[async_trait]
+impl SecretStore for Vault {
+    async fn list(&self, filter: &SecretFilter) -> Result<Vec<SecretInfo>, CliError> {
+        // only the folder of the prefix has to be listed
+        let folder = filter
+            .name_prefix
+            .as_deref()
+            .and_then(|prefix| prefix.rsplit_once('/'))
+            .map(|(folder, _)| format!("{}/", folder))
+            .unwrap_or_default();
+        let prefix = filter.name_prefix.as_deref().unwrap_or("").to_lowercase();
+
+        let mut secrets = Vec::new();
+        for name in self.list_keys(folder).await? {
+            if !name.to_lowercase().starts_with(&prefix) {
+                continue;
+            }
+            match self.describe(name).await {
+                Ok(info) if filter.matches(&info) => secrets.push(info),
+                Ok(_) => {}
+                // deleted secrets are still listed by Vault
+                Err(CliError::VaultError(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        secrets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(secrets)
+    }
+
+    async fn get(&self, secret_id: String, version_id: Option<String>) -> Result<Secret, CliError> {
+        let mut path = self.data_path(&secret_id);
+        if let Some(version_id) = &version_id {
+            path = format!("{}?version={}", path, version_number(version_id)?);
+        }
+
+        let (status, body) = self.send(Method::Get, &path, None).await?;
+        if status == 404 {
+            return Err(match version_id {
+                Some(version_id) => CliError::VaultError(format!(
+                    "version {} of secret {} not found",
+                    version_id, secret_id
+                )),
+                None => Self::not_found(&secret_id),
+            });
+        }
+        let body = expect_ok(status, body, Some(&secret_id))?;
+
+        // KV allows any JSON value, only strings can be fields
+        let data = body["data"]["data"].to_string();
+        Secret::new(
+            if data == "null" {
+                "{}".to_string()
+            } else {
+                data
+            },
+            body["data"]["metadata"]["version"]
+                .as_u64()
+                .map(|version| version.to_string()),
+        )
+    }
+
+    async fn put(
+        &self,
+        secret_id: String,
+        secret_value: String,
+        expected_version: Option<String>,
+    ) -> Result<Option<String>, CliError> {
+        let data: Value = serde_json::from_str(&secret_value)
+            .map_err(|e| CliError::AwsSecretsFormatError(e.to_string()))?;
+
+        // check-and-set rejects the write unless the current version is the expected one
+        let mut request = json!({ "data": data });
+        if let Some(expected) = &expected_version {
+            request["options"] = json!({ "cas": version_number(expected)? });
+        }
+
+        let (status, body) = self
+            .send(Method::Post, &self.data_path(&secret_id), Some(request))
+            .await?;
+        if status == 400 && error_message(&body).contains("check-and-set") {
+            return Err(CliError::RemoteConflictError(format!(
+                "secret {} changed since it was read",
+                secret_id
+            )));
+        }
+        let body = expect_ok(status, body, Some(&secret_id))?;
+
+        Ok(body["data"]["version"]
+            .as_u64()
+            .map(|version| version.to_string()))
+    }
+
+    async fn describe(&self, secret_id: String) -> Result<SecretInfo, CliError> {
+        let metadata = self.metadata(&secret_id).await?;
+
+        let mut tags: BTreeMap<String, String> = metadata["custom_metadata"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+            .collect();
+
+        Ok(SecretInfo {
+            name: secret_id,
+            description: tags.remove(DESCRIPTION_KEY),
+            last_changed: metadata["updated_time"].as_str().and_then(parse_vault_time),
+            version_id: metadata["current_version"]
+                .as_u64()
+                .map(|version| version.to_string()),
+            tags,
+        })
+    }
+
+    async fn history(&self, secret_id: String) -> Result<Vec<SecretVersion>, CliError> {
+        let metadata = self.metadata(&secret_id).await?;
+
+        // deleted and destroyed versions can't be read anymore
+        let mut versions: Vec<(u64, &Value)> = metadata["versions"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(_, version)| is_readable(version))
+            .filter_map(|(number, version)| Some((number.parse().ok()?, version)))
+            .collect();
+        versions.sort_by_key(|(number, _)| std::cmp::Reverse(*number));
+
+        Ok(versions
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (number, version))| SecretVersion {
+                version_id: number.to_string(),
+                created_date: version["created_time"].as_str().and_then(parse_vault_time),
+                stages: match idx {
+                    0 => vec![CURRENT_STAGE.to_string()],
+                    1 => vec![PREVIOUS_STAGE.to_string()],
+                    _ => Vec::new(),
+                },
+            })
+            .collect())
+    }
+
+    async fn create(&self, new_secret: &NewSecret) -> Result<Option<String>, CliError> {
+        if new_secret.kms_key_id.is_some() {
+            return Err(CliError::VaultError(
+                "Vault encrypts secrets with its own keys, --kms-key-id is not supported."
+                    .to_string(),
+            ));
+        }
+
+        let mut custom_metadata = new_secret.tags.clone();
+        if let Some(description) = &new_secret.description {
+            custom_metadata.insert(DESCRIPTION_KEY.to_string(), description.clone());
+        }
+
+        // the first version only succeeds when the secret doesn't exist yet
+        let version_id = self
+            .put(
+                new_secret.name.clone(),
+                "{}".to_string(),
+                Some("0".to_string()),
+            )
+            .await
+            .map_err(|e| match e {
+                CliError::RemoteConflictError(_) => {
+                    CliError::VaultError(format!("secret {} already exists", new_secret.name))
+                }
+                e => e,
+            })?;
+
+        let (status, body) = self
+            .send(
+                Method::Post,
+                &self.metadata_path(&new_secret.name),
+                Some(json!({ "custom_metadata": custom_metadata })),
+            )
+            .await?;
+        expect_ok(status, body, Some(&new_secret.name))?;
+
+        Ok(version_id)
+    }
+
+    async fn delete(
+        &self,
+        secret_id: String,
+        _recovery_days: i64,
+    ) -> Result<Option<String>, CliError> {
+        self.metadata(&secret_id).await?;
+
+        // soft delete, the data is kept until the version is destroyed
+        let (status, body) = self
+            .send(Method::Delete, &self.data_path(&secret_id), None)
+            .await?;
+        expect_ok(status, body, Some(&secret_id))?;
+
+        Ok(None)
+    }
+
+    async fn restore(&self, secret_id: String) -> Result<(), CliError> {
+        let (status, body) = self
+            .send(Method::Get, &self.metadata_path(&secret_id), None)
+            .await?;
+        if status == 404 {
+            return Err(Self::not_found(&secret_id));
+        }
+        let metadata = expect_ok(status, body, Some(&secret_id))?;
+
+        let current = metadata["data"]["current_version"].as_u64().unwrap_or(0);
+        let (status, body) = self
+            .send(
+                Method::Post,
+                &format!("{}/undelete/{}", self.mount, encode_path(&secret_id)),
+                Some(json!({ "versions": [current] })),
+            )
+            .await?;
+        expect_ok(status, body, Some(&secret_id))?;
+
+        Ok(())
+    }
+
+    fn has_recovery_window(&self) -> bool {
+        false
+    }
+}
+
+fn with_headers<B>(
+    mut request: ureq::RequestBuilder<B>,
+    token: &str,
+    namespace: &Option<String>,
+) -> ureq::RequestBuilder<B> {
+    if !token.is_empty() {
+        request = request.header("X-Vault-Token", token);
+    }
+    if let Some(namespace) = namespace {
+        request = request.header("X-Vault-Namespace", namespace);
+    }
+    request
+}
+
+// a 404 of a request for a secret means the secret doesn't exist
+fn expect_ok(status: u16, body: Value, secret_id: Option<&str>) -> Result<Value, CliError> {
+    if (200..300).contains(&status) {
+        return Ok(body);
+    }
+    // the statuses with a class of their own get the exit code of that class
+    let message = format!("{} ({})", error_message(&body), status);
+    Err(match status {
+        403 => CliError::AccessDeniedError(message),
+        429 => CliError::ThrottlingError(format!(
+            "{}. Gave up after retrying, try again later or raise --max-attempts",
+            message
+        )),
+        404 => match secret_id {
+            Some(secret_id) => Vault::not_found(secret_id),
+            None => CliError::VaultError(format!("{}, check the address and the mounts", message)),
+        },
+        _ => CliError::VaultError(message),
+    })
+}
+
+fn request_error(url: &str, error: ureq::Error) -> CliError {
+    let message = format!("{}: {}", url, error);
+    match error {
+        ureq::Error::Timeout(_) => {
+            CliError::TimeoutError(format!("{}. Raise --timeout to wait longer", message))
+        }
+        ureq::Error::Io(_) | ureq::Error::ConnectionFailed | ureq::Error::HostNotFound => {
+            CliError::NetworkError(message)
+        }
+        _ => CliError::VaultError(message),
+    }
+}
+
+// exponential backoff with full jitter
+fn backoff(attempt: u32) -> Duration {
+    let cap = RETRY_BASE_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(RETRY_MAX_DELAY);
+    let jitter = RandomState::new().build_hasher().finish() % (cap.as_millis() as u64 + 1);
+    Duration::from_millis(jitter)
+}
+
+fn error_message(body: &Value) -> String {
+    let errors: Vec<&str> = body["errors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    if errors.is_empty() {
+        "request failed".to_string()
+    } else {
+        errors.join(", ")
+    }
+}
+
+fn is_readable(version: &Value) -> bool {
+    !version.is_null()
+        && version["deletion_time"].as_str().unwrap_or("").is_empty()
+        && !version["destroyed"].as_bool().unwrap_or(false)
+}
+
+fn version_number(version_id: &str) -> Result<u64, CliError> {
+    version_id.parse().map_err(|_| {
+        CliError::VaultError(format!("'{}' is not a Vault version number", version_id))
+    })
+}
+
+// Vault reports RFC 3339 times with nanoseconds
+fn parse_vault_time(time: &str) -> Option<String> {
+    let secs = DateTime::from_str(time, Format::DateTime).ok()?.secs();
+    DateTime::from_secs(secs).fmt(Format::DateTime).ok()
+}
+
+// escapes everything but unreserved characters in each segment of the path
+fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expect_ok;
+    use crate::tools::error::CliError;
+    use serde_json::json;
+
+    #[test]
+    fn not_found_errors_name_the_secret() {
+        let body = json!({ "errors": [] });
+
+        let result = expect_ok(404, body.clone(), Some("team/api"));
+        assert!(matches!(result, Err(CliError::NotFoundError(id)) if id == "team/api"));
+        // a missing mount or auth method is not a missing secret
+        let result = expect_ok(404, body, None);
+        assert!(matches!(result, Err(CliError::VaultError(_))));
+    }
+}
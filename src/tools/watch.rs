@@ -0,0 +1,130 @@
+use anyhow::Result;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+use super::error::CliError;
+
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    rx: UnboundedReceiver<()>,
+    pending: bool,
+}
+
+impl FileWatcher {
+    pub fn new(filepath: &Path) -> Result<Self, CliError> {
+        let file_name = filepath.file_name().map(|name| name.to_owned());
+        let (tx, rx) = unbounded_channel();
+
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            let Ok(event) = res else {
+                return;
+            };
+            if !matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) {
+                return;
+            }
+            if event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == file_name.as_deref())
+            {
+                let _ = tx.send(());
+            }
+        })
+        .map_err(|e| CliError::WatchError(e.to_string()))?;
+
+        // watch the directory, since atomic writes replace the file itself
+        let parent = match filepath.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        watcher
+            .watch(parent, RecursiveMode::NonRecursive)
+            .map_err(|e| CliError::WatchError(e.to_string()))?;
+
+        Ok(FileWatcher {
+            _watcher: watcher,
+            rx,
+            pending: false,
+        })
+    }
+
+    pub async fn changed(&mut self, debounce: Duration) {
+        // remember a received event in case this future is cancelled while debouncing
+        if !self.pending {
+            if self.rx.recv().await.is_none() {
+                return std::future::pending().await;
+            }
+            self.pending = true;
+        }
+
+        // wait until the file has been quiet for the debounce duration
+        while let Ok(Some(())) = tokio::time::timeout(debounce, self.rx.recv()).await {}
+        self.pending = false;
+    }
+
+    pub fn drain(&mut self) {
+        while self.rx.try_recv().is_ok() {}
+        self.pending = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileWatcher;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("tc-secrets-watch-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(".env")
+    }
+
+    #[tokio::test]
+    async fn bursts_of_edits_are_reported_once() {
+        let path = temp_file("burst");
+        std::fs::write(&path, "A=0").unwrap();
+        let mut watcher = FileWatcher::new(&path).unwrap();
+
+        for value in 1..=5 {
+            std::fs::write(&path, format!("A={}", value)).unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let changed = tokio::time::timeout(Duration::from_secs(5), watcher.changed(DEBOUNCE));
+        assert!(changed.await.is_ok(), "the edits were not reported");
+        let changed = tokio::time::timeout(DEBOUNCE * 2, watcher.changed(DEBOUNCE));
+        assert!(changed.await.is_err(), "the edits were reported twice");
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn other_files_and_drained_edits_are_ignored() {
+        let path = temp_file("drain");
+        let mut watcher = FileWatcher::new(&path).unwrap();
+
+        std::fs::write(path.with_file_name(".env.bak.1"), "A=0").unwrap();
+        let changed = tokio::time::timeout(DEBOUNCE * 2, watcher.changed(DEBOUNCE));
+        assert!(
+            changed.await.is_err(),
+            "an edit of another file was reported"
+        );
+
+        std::fs::write(&path, "A=1").unwrap();
+        tokio::time::sleep(DEBOUNCE).await;
+        watcher.drain();
+        let changed = tokio::time::timeout(DEBOUNCE * 2, watcher.changed(DEBOUNCE));
+        assert!(changed.await.is_err(), "a drained edit was reported");
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,1505 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{
+    ACCOUNT_ID, S3_BUCKET, SESSION_ACCESS_KEY_ID, Sandbox, VAULT_ROLE_ID, VAULT_SECRET_ID,
+    VAULT_TOKEN, stderr, stdout,
+};
+use serde_json::json;
+
+const MANIFEST: &str = r#"
+[[files]]
+name = "dev"
+path = ".env"
+secret_id = "app"
+field_id = "dev"
+encryption = "none"
+"#;
+
+fn versioned(version: u32, body: &str) -> String {
+    format!("#do-not-edit--secrets-version {}\n{}", version, body)
+}
+
+#[test]
+fn auth_configures_credentials_and_reports_the_account() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.run(&["auth"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.aws_calls().trim(), "configure");
+    assert!(stderr(&output).contains(ACCOUNT_ID));
+}
+
+#[test]
+fn auth_configures_the_chosen_profile() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.run(&["auth", "--profile", "work"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.aws_calls().trim(), "configure --profile work");
+}
+
+#[test]
+fn missing_credentials_fail_without_prompting() {
+    let sandbox = Sandbox::new();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+
+    let output = sandbox.run(&["sync"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("tc-secrets auth"));
+    assert!(sandbox.aws_calls().is_empty());
+}
+
+#[test]
+fn credentials_are_read_from_the_chosen_profile() {
+    let sandbox = Sandbox::with_profile("work");
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+
+    let output = sandbox.run(&["status", "--profile", "work", "--region", "eu-west-1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = sandbox
+        .command(&["status", "--endpoint-url", &sandbox.server.url])
+        .env("AWS_PROFILE", "work")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn credentials_of_earlier_versions_are_still_used() {
+    let sandbox = Sandbox::with_profile(common::LEGACY_PROFILE_NAME);
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+
+    let output = sandbox.run(&["status"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn credentials_are_read_from_the_environment() {
+    let sandbox = Sandbox::new();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+
+    let output = sandbox
+        .command(&["status", "--endpoint-url", &sandbox.server.url])
+        .env("AWS_ACCESS_KEY_ID", "test")
+        .env("AWS_SECRET_ACCESS_KEY", "test")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn sync_pulls_remote_changes() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(2, "A=1\nB=2\n"))]);
+
+    let output = sandbox.run(&["sync"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let content = sandbox.read(".env");
+    assert!(content.contains("#do-not-edit--secrets-version 2"));
+    assert!(content.contains("B=2"));
+    assert_eq!(sandbox.server.version_count("app"), 1);
+}
+
+#[test]
+fn sync_pushes_local_edits() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(2, "A=1\nB=2\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n")), ("other", "C=3")]);
+
+    let output = sandbox.run(&["sync", "-f", "dev"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let remote = sandbox.server.field("app", "dev").unwrap();
+    assert!(remote.contains("#do-not-edit--secrets-version 2"));
+    assert!(remote.contains("B=2"));
+    assert_eq!(sandbox.server.field("app", "other").as_deref(), Some("C=3"));
+    assert_eq!(sandbox.server.version_count("app"), 2);
+}
+
+#[test]
+fn concurrent_writes_are_retried_on_the_latest_version() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(2, "A=2\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+
+    sandbox.server.race(2);
+    let output = sandbox.run(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output)
+            .matches("Retrying with the latest version")
+            .count(),
+        2
+    );
+    assert!(sandbox.server.field("app", "dev").unwrap().contains("A=2"));
+    assert!(sandbox.server.field("app", "other").is_some());
+
+    sandbox.server.race(usize::MAX);
+    sandbox.write(".env", &versioned(3, "A=3\n"));
+    let output = sandbox.run(&["push"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert!(stderr(&output).contains("gave up after 5 attempts"));
+}
+
+#[test]
+fn writes_overwritten_by_our_push_are_restored_or_reported() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(2, "A=2\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n")), ("prod", "B=1")]);
+
+    // another field written right before ours is kept
+    sandbox.server.overtake("prod", "B=2");
+    let output = sandbox.run(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Retrying with the latest version"));
+    assert!(sandbox.server.field("app", "dev").unwrap().contains("A=2"));
+    assert_eq!(sandbox.server.field("app", "prod").as_deref(), Some("B=2"));
+
+    // our own field written right before ours is a conflict
+    sandbox.write(".env", &versioned(3, "A=3\n"));
+    sandbox.server.overtake("dev", &versioned(3, "A=other\n"));
+    let output = sandbox.run(&["push"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert!(stderr(&output).contains("field app/dev was changed by someone else"));
+}
+
+#[test]
+fn sync_round_trips_encrypted_content() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.server.put_secret("app", &[("dev", "")]);
+    sandbox.write(
+        "a/.env",
+        "#do-not-edit--secrets-version 1\n#do-not-edit--secrets-id app\n#do-not-edit--secrets-field-id dev\nTOKEN=abc\n",
+    );
+
+    let output = sandbox.run(&["push", "-f", "a/.env", "-p", "hunter2"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let remote = sandbox.server.field("app", "dev").unwrap();
+    assert!(!remote.contains("TOKEN=abc"));
+
+    sandbox.write(
+        "b/.env",
+        "#do-not-edit--secrets-version 0\n#do-not-edit--secrets-id app\n#do-not-edit--secrets-field-id dev\n",
+    );
+    let output = sandbox.run(&["sync", "-f", "b/.env", "-p", "hunter2"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read("b/.env").contains("TOKEN=abc"));
+}
+
+#[test]
+fn sync_dry_run_reports_pending_changes() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(2, "A=2\n"))]);
+
+    let output = sandbox.run(&["sync", "--dry-run"]);
+
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("A=1"));
+    assert_eq!(sandbox.server.version_count("app"), 1);
+}
+
+#[test]
+fn sync_dry_run_without_remote_secrets_is_not_found() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".env", "A=1\n");
+
+    let output = sandbox.run(&["sync", "--filepath", ".env", "--dry-run"]);
+
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    assert_eq!(sandbox.read(".env"), "A=1\n");
+}
+
+#[test]
+fn push_and_pull_require_force_to_discard_changes() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+    let output = sandbox.run(&["sync"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    sandbox.write(".env", &versioned(1, "A=local\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(2, "A=remote\n"))]);
+
+    for command in ["push", "pull"] {
+        let output = sandbox.run(&[command]);
+        assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+        assert!(stderr(&output).contains("Use `--force`"));
+    }
+    assert!(sandbox.read(".env").contains("A=local"));
+    assert_eq!(sandbox.server.version_count("app"), 2);
+
+    let output = sandbox.run(&["pull", "--force"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("A=remote"));
+}
+
+#[test]
+fn leases_are_checked_before_resolving_conflicts() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+    let output = sandbox.run(&["sync"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    sandbox.write(".env", &versioned(1, "A=local\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(2, "A=remote\n"))]);
+    let as_owner = |owner: &str, args: &[&str]| {
+        sandbox
+            .command(args)
+            .args(["--endpoint-url", &sandbox.server.url])
+            .env("TC_SECRETS_OWNER", owner)
+            .output()
+            .unwrap()
+    };
+    let output = as_owner("alice", &["lock"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = as_owner("bob", &["sync"]);
+
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert!(stderr(&output).contains("locked by alice"));
+    assert!(!stdout(&output).contains("Conflict 1/1"));
+    assert!(sandbox.read(".env").contains("A=local"));
+}
+
+#[test]
+fn reset_overwrites_the_local_file_and_keeps_a_backup() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(3, "A=local\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(2, "A=remote\n"))]);
+
+    let output = sandbox.run(&["reset", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+
+    let output = sandbox.run(&["reset"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("A=remote"));
+    assert!(sandbox.read(".env.bak.1").contains("A=local"));
+
+    // only the version header differs, so there is nothing to reset
+    sandbox.write(".env", &versioned(1, "A=remote\n"));
+    let output = sandbox.run(&["reset", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+}
+
+#[test]
+fn pull_and_sync_keep_backups_and_the_file_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    let path = sandbox.write(".env", &versioned(1, "A=1\n"));
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(2, "A=2\n"))]);
+
+    let output = sandbox.run(&["sync"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env.bak.1").contains("A=1"));
+    let mode =
+        |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    // the file keeps its mode, only new files are private
+    assert_eq!(mode(&path), 0o644);
+    assert_eq!(mode(&sandbox.project.join(".env.bak.1")), 0o600);
+
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(3, "A=3\n"))]);
+    let output = sandbox.run(&["pull", "--backups", "2"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("A=3"));
+    assert!(sandbox.read(".env.bak.1").contains("A=2"));
+    assert!(sandbox.read(".env.bak.2").contains("A=1"));
+
+    sandbox.server.put_secret(
+        "app",
+        &[(
+            "dev",
+            &versioned(
+                4, "A=4
+",
+            ),
+        )],
+    );
+    let output = sandbox.run(&["pull", "--backups", "0"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("A=4"));
+    assert!(sandbox.read(".env.bak.1").contains("A=2"));
+}
+
+#[test]
+fn insecure_permissions_are_reported_fixed_and_refused_in_strict_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode =
+        |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    let set_mode = |path: &std::path::Path, mode: u32| {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap()
+    };
+
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    let path = sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(2, "A=2\n"))]);
+    set_mode(&path, 0o644);
+    set_mode(&sandbox.project, 0o750);
+
+    // read-only commands only warn, even in strict mode
+    let output = sandbox.run(&["status", "--strict"]);
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+    assert!(stderr(&output).contains("insecure permissions 644"));
+    assert!(stderr(&output).contains("insecure permissions 750"));
+
+    let output = sandbox.run(&["pull", "--strict"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Refusing to write secrets into insecure locations"));
+    assert!(sandbox.read(".env").contains("A=1"));
+
+    let output = sandbox.run(&["pull", "--strict", "--fix"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("A=2"));
+    assert_eq!(mode(&path), 0o600);
+    assert_eq!(mode(&sandbox.project), 0o700);
+    assert_eq!(mode(&sandbox.project.join(".tc-secrets")), 0o700);
+}
+
+#[test]
+fn history_skips_unreadable_versions_and_rollback_to_the_current_content_is_a_no_op() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(3, "A=3\n"));
+    for version in 1..=3 {
+        let body = versioned(version, &format!("A={}\n", version));
+        sandbox.server.put_secret("app", &[("dev", &body)]);
+    }
+    sandbox.server.corrupt_version("app", "version-2");
+
+    let output = sandbox.run(&["history"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("version-1"));
+    assert!(stderr(&output).contains("1 of the listed versions could not be read"));
+
+    let output = sandbox.run(&["history", "--limit", "1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stdout(&output).contains("version-1"));
+    assert!(stdout(&output).contains("Showing the 1 newest of 3 versions"));
+
+    let output = sandbox.run(&["rollback", "--version-id", "version-3", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let output = sandbox.run(&["rollback", "--version-id", "version-1", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+    assert_eq!(sandbox.server.version_count("app"), 3);
+}
+
+#[test]
+fn rollback_without_a_terminal_needs_yes_and_respects_leases() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(2, "A=2\n"));
+    for version in 1..=2 {
+        let body = versioned(version, &format!("A={}\n", version));
+        sandbox.server.put_secret("app", &[("dev", &body)]);
+    }
+    let as_owner = |owner: &str, args: &[&str]| {
+        sandbox
+            .command(args)
+            .args(["--endpoint-url", &sandbox.server.url])
+            .env("TC_SECRETS_OWNER", owner)
+            .output()
+            .unwrap()
+    };
+
+    let output = as_owner("bob", &["rollback", "--version-id", "version-1"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("--yes"));
+
+    let output = as_owner("alice", &["lock"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let writes = sandbox.server.version_count("app");
+
+    let output = as_owner("bob", &["rollback", "--version-id", "version-1", "--yes"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert!(stderr(&output).contains("locked by alice"));
+    assert_eq!(sandbox.server.version_count("app"), writes);
+
+    let output = as_owner(
+        "bob",
+        &["rollback", "--version-id", "version-1", "--yes", "--steal"],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.server.field("app", "dev").unwrap().contains("A=1"));
+}
+
+#[test]
+fn diff_shows_the_remote_changes() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=local\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=remote\n"))]);
+
+    let output = sandbox.run(&["diff"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("A=local"));
+    assert!(stdout.contains("A=remote"));
+}
+
+#[test]
+fn endpoint_is_read_from_the_environment() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+
+    let output = sandbox
+        .command(&["status"])
+        .env("AWS_ENDPOINT_URL", &sandbox.server.url)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Everything is in sync."));
+}
+
+#[test]
+fn endpoint_is_read_from_the_manifest() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "[backend]\ntype = \"aws\"\nendpoint_url = \"{}\"\n{}",
+            sandbox.server.url, MANIFEST
+        ),
+    );
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+
+    let output = sandbox.command(&["status", "--json"]).output().unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let statuses: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(statuses[0]["state"], "synced");
+}
+
+#[test]
+fn status_exits_with_the_code_of_a_file_that_cannot_be_checked() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+
+    let output = sandbox.run(&["status", "--json"]);
+
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    let statuses: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(statuses[0]["state"], "error");
+    assert!(stderr(&output).contains("AWS Account ID"));
+}
+
+#[test]
+fn roles_are_assumed_once_per_session() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "{}role_arn = \"arn:aws:iam::{}:role/deploy\"\nexternal_id = \"ci\"\n",
+            MANIFEST, ACCOUNT_ID
+        ),
+    );
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+
+    for _ in 0..2 {
+        let output = sandbox.run(&["status"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+
+    let requests = sandbox.server.assume_role_requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].contains("ExternalId=ci"));
+    let access_key_ids = sandbox.server.access_key_ids();
+    assert!(!access_key_ids.is_empty());
+    assert!(access_key_ids.iter().all(|id| id == SESSION_ACCESS_KEY_ID));
+
+    let sessions = sandbox.root.join(".cache/tc-secrets/sessions");
+    let session = std::fs::read_dir(&sessions)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    let mode = session.metadata().unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn mfa_codes_are_not_read_without_a_terminal() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(".tc-secrets.toml", MANIFEST);
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+
+    let output = sandbox.run(&[
+        "status",
+        "--role-arn",
+        "arn:aws:iam::123456789012:role/deploy",
+        "--mfa-serial",
+        "arn:aws:iam::123456789012:mfa/tester",
+    ]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("MFA code"), "{}", stderr(&output));
+    assert!(sandbox.server.assume_role_requests().is_empty());
+}
+
+#[test]
+fn list_pages_through_all_secrets() {
+    let sandbox = Sandbox::with_credentials();
+    for name in ["app-a", "app-b", "app-c", "app-d", "other"] {
+        sandbox
+            .server
+            .put_secret(name, &[("dev", "A=1"), ("prod", "A=2")]);
+    }
+    sandbox
+        .server
+        .describe_secret("app-c", "Payments service", &[("team", "payments")]);
+
+    let output = sandbox.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let listing = stdout(&output);
+    assert!(listing.contains("other"));
+    assert!(listing.contains("dev,prod"));
+    assert!(listing.contains("Payments service"));
+
+    let output = sandbox.run(&["list", "--prefix", "app-", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let listing: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    let names: Vec<&str> = listing
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|secret| secret["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["app-a", "app-b", "app-c", "app-d"]);
+}
+
+#[test]
+fn list_filters_by_tag() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.server.put_secret("payments", &[("dev", "A=1")]);
+    sandbox.server.put_secret("search", &[("dev", "A=1")]);
+    sandbox.server.put_secret("billing", &[("dev", "A=1")]);
+    sandbox
+        .server
+        .describe_secret("payments", "", &[("team", "payments"), ("env", "prod")]);
+    sandbox
+        .server
+        .describe_secret("search", "", &[("team", "search"), ("env", "payments")]);
+
+    let output = sandbox.run(&["list", "--tag", "team=payments", "--json"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let listing: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    let listing = listing.as_array().unwrap();
+    assert_eq!(listing.len(), 1);
+    assert_eq!(listing[0]["name"], "payments");
+    assert_eq!(listing[0]["fields"], serde_json::json!(["dev"]));
+}
+
+#[test]
+fn throttled_requests_are_retried() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.server.put_secret("payments", &[("dev", "A=1")]);
+
+    sandbox.server.throttle(2);
+    let output = sandbox.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("payments"));
+
+    sandbox.server.throttle(usize::MAX);
+    let output = sandbox.run(&["list", "--max-attempts", "2"]);
+    assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+    assert!(stderr(&output).contains("--max-attempts"));
+}
+
+#[test]
+fn missing_secrets_are_not_followed_by_suggestions_without_a_terminal() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.server.put_secret("payments", &[("dev", "A=1")]);
+    sandbox.server.put_secret("users", &[("dev", "B=1")]);
+
+    let output = sandbox.run(&["delete", "paymnets", "--yes"]);
+
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    assert!(stderr(&output).contains("paymnets"), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("did you mean"));
+}
+
+#[test]
+fn unreachable_endpoints_fail_with_a_network_error() {
+    let sandbox = Sandbox::with_credentials();
+
+    let output = sandbox
+        .command(&[
+            "list",
+            "--endpoint-url",
+            "http://127.0.0.1:1",
+            "--max-attempts",
+            "1",
+        ])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(7), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("tc-secrets auth"));
+}
+
+#[test]
+fn secrets_are_created_deleted_and_restored() {
+    let sandbox = Sandbox::with_credentials();
+
+    let output = sandbox.run(&[
+        "create",
+        "payments",
+        "--description",
+        "Payments service",
+        "--tag",
+        "team=payments",
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.version_count("payments"), 1);
+    assert_eq!(
+        sandbox.server.description("payments").as_deref(),
+        Some("Payments service")
+    );
+    assert_eq!(
+        sandbox.server.tags("payments"),
+        [("team".to_string(), "payments".to_string())]
+    );
+
+    let output = sandbox.run(&["delete", "payments", "--recovery-days", "7", "--yes"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.server.is_deleted("payments"));
+    assert!(stdout(&output).contains("tc-secrets restore payments"));
+
+    let output = sandbox.run(&["restore", "payments"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!sandbox.server.is_deleted("payments"));
+}
+
+#[test]
+fn delete_requires_confirmation() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.server.put_secret("payments", &[("dev", "A=1")]);
+
+    let output = sandbox.run(&["delete", "payments"]);
+
+    assert!(!output.status.success());
+    assert!(!sandbox.server.is_deleted("payments"));
+}
+
+#[test]
+fn fields_are_renamed_in_a_single_version() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n")), ("prod", "B=2")]);
+    sandbox.write(
+        "svc/.env",
+        "#do-not-edit--secrets-version 1\n#do-not-edit--secrets-id app\n#do-not-edit--secrets-field-id dev\nA=1\n",
+    );
+
+    let output = sandbox.run(&["field", "mv", "app/dev", "app/staging"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.version_count("app"), 2);
+    assert_eq!(sandbox.server.field("app", "dev"), None);
+    assert!(sandbox.server.field("app", "staging").is_some());
+    assert!(
+        sandbox
+            .read("svc/.env")
+            .contains("#do-not-edit--secrets-field-id staging")
+    );
+}
+
+#[test]
+fn fields_are_moved_and_copied_across_secrets() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox
+        .server
+        .put_secret("old", &[("dev", "A=1"), ("prod", "B=2")]);
+    sandbox.server.put_secret("new", &[("prod", "C=3")]);
+
+    let output = sandbox.run(&["field", "cp", "old/dev", "new/dev"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.field("old", "dev").as_deref(), Some("A=1"));
+    assert_eq!(sandbox.server.field("new", "dev").as_deref(), Some("A=1"));
+
+    let output = sandbox.run(&["field", "mv", "old/prod", "new/prod"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(sandbox.server.field("new", "prod").as_deref(), Some("C=3"));
+
+    let output = sandbox.run(&["field", "mv", "old/prod", "new/prod", "--force"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.field("old", "prod"), None);
+    assert_eq!(sandbox.server.field("new", "prod").as_deref(), Some("B=2"));
+
+    let output = sandbox.run(&["field", "rm", "old/dev", "--yes"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.field("old", "dev"), None);
+
+    let output = sandbox.run(&["field", "ls", "new"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("dev"));
+    assert!(stdout.contains("prod"));
+}
+
+#[test]
+fn moves_respect_leases_and_undo_the_copy_when_the_source_cannot_be_changed() {
+    let sandbox = Sandbox::with_credentials();
+    let lease = |owner: &str| {
+        json!({ "lease": { "owner": owner, "reason": null, "expires_at": 4_000_000_000i64 } })
+            .to_string()
+    };
+    sandbox.server.put_secret(
+        "old",
+        &[
+            ("dev", "A=1"),
+            ("prod", "B=2"),
+            ("tc-secrets:lease:prod", &lease("bob")),
+        ],
+    );
+    sandbox.server.put_secret(
+        "new",
+        &[("prod", "C=3"), ("tc-secrets:lease:prod", &lease("alice"))],
+    );
+    let as_bob = |args: &[&str]| {
+        sandbox
+            .command(args)
+            .args(["--endpoint-url", &sandbox.server.url])
+            .env("TC_SECRETS_OWNER", "bob")
+            .output()
+            .unwrap()
+    };
+
+    let output = as_bob(&["field", "mv", "old/prod", "new/prod", "--force"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert!(stderr(&output).contains("locked by alice"));
+    assert_eq!(sandbox.server.field("new", "prod").as_deref(), Some("C=3"));
+
+    sandbox.server.put_secret("new", &[("prod", "C=3")]);
+    let output = as_bob(&["field", "mv", "old/prod", "new/prod", "--force"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.field("new", "prod").as_deref(), Some("B=2"));
+    assert_eq!(sandbox.server.field("old", "prod"), None);
+    assert_eq!(sandbox.server.field("old", "tc-secrets:lease:prod"), None);
+
+    sandbox.server.deny_writes("old");
+    let output = as_bob(&["field", "mv", "old/dev", "new/dev"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(stderr(&output).contains("was removed again"));
+    assert_eq!(sandbox.server.field("old", "dev").as_deref(), Some("A=1"));
+    assert_eq!(sandbox.server.field("new", "dev"), None);
+}
+
+#[test]
+fn parameter_store_is_synced_and_versioned() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!("[backend]\ntype = \"ssm\"\npath = \"/team\"\n{}", MANIFEST),
+    );
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox
+        .server
+        .put_parameter("/team/app/dev", &versioned(2, "A=1\nB=2\n"));
+
+    let output = sandbox.run(&["sync"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("B=2"));
+
+    sandbox.write(".env", &versioned(3, "A=1\nB=3\n"));
+    let output = sandbox.run(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        sandbox
+            .server
+            .parameter("/team/app/dev")
+            .unwrap()
+            .contains("B=3")
+    );
+    assert_eq!(sandbox.server.parameter_version_count("/team/app/dev"), 2);
+
+    // a concurrent write to the same parameter is only noticed by the skipped version
+    sandbox.write(".env", &versioned(4, "A=1\nB=4\n"));
+    sandbox
+        .server
+        .overtake_parameter("/team/app/dev", &versioned(4, "A=1\nB=other\n"));
+    let output = sandbox.run(&["push"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("field app/dev was changed by someone else"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(sandbox.server.parameter_version_count("/team/app/dev"), 4);
+
+    let output = sandbox.run(&["history"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // parameters are written one at a time, so a failed deletion leaves the new field behind
+    sandbox.server.deny_parameter_writes("/team/app/dev");
+    let output = sandbox.run(&["field", "mv", "app/dev", "app/staging"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("partially updated, the fields staging were already written"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(sandbox.server.parameter("/team/app/dev").is_some());
+    assert!(sandbox.server.parameter("/team/app/staging").is_some());
+}
+
+#[test]
+fn files_can_use_parameter_store_next_to_secrets_manager() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "{}\n[[files]]\nname = \"ci\"\npath = \"ci.env\"\nsecret_id = \"app\"\nfield_id = \"ci\"\nencryption = \"none\"\nbackend = {{ type = \"ssm\" }}\n",
+            MANIFEST
+        ),
+    );
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox.write("ci.env", &versioned(1, "C=1\n"));
+    sandbox
+        .server
+        .put_secret("app", &[("dev", &versioned(1, "A=1\n"))]);
+    sandbox
+        .server
+        .put_parameter("/tc-secrets/app/ci", &versioned(2, "C=2\n"));
+
+    let output = sandbox.run(&["sync"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read("ci.env").contains("C=2"));
+    assert_eq!(sandbox.server.field("app", "ci"), None);
+    assert_eq!(sandbox.server.version_count("app"), 1);
+
+    // both files use a secret called app, but each in its own store
+    let output = sandbox.run(&["status", "--json"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    let statuses: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(statuses[1]["state"], "synced");
+}
+
+#[test]
+fn vault_secrets_are_pushed_with_check_and_set() {
+    let sandbox = Sandbox::new();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "[backend]\ntype = \"vault\"\naddress = \"{}\"\n{}",
+            sandbox.server.url, MANIFEST
+        ),
+    );
+    sandbox.write(".env", &versioned(2, "A=1\nB=2\n"));
+    sandbox.server.put_kv(
+        "app",
+        json!({ "dev": versioned(1, "A=1\n"), "prod": "C=3" }),
+    );
+    let vault = |args: &[&str]| {
+        sandbox
+            .command(args)
+            .env("VAULT_TOKEN", VAULT_TOKEN)
+            .output()
+            .unwrap()
+    };
+
+    let output = vault(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.kv_version_count("app"), 2);
+    assert!(
+        sandbox
+            .server
+            .kv_field("app", "dev")
+            .unwrap()
+            .contains("B=2")
+    );
+    assert_eq!(
+        sandbox.server.kv_field("app", "prod").as_deref(),
+        Some("C=3")
+    );
+
+    let output = vault(&["history"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("current"));
+
+    let output = vault(&["rollback", "--version-id", "2", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let output = vault(&["rollback", "--version-id", "1", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+    assert!(stdout(&output).contains("  - B"), "{}", stdout(&output));
+    assert_eq!(sandbox.server.kv_version_count("app"), 2);
+
+    let output = sandbox.command(&["status"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("VAULT_TOKEN"));
+}
+
+#[test]
+fn vault_check_and_set_rejections_are_retried_on_the_latest_version() {
+    let sandbox = Sandbox::new();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "[backend]\ntype = \"vault\"\naddress = \"{}\"\n{}",
+            sandbox.server.url, MANIFEST
+        ),
+    );
+    sandbox.write(".env", &versioned(2, "A=2\n"));
+    sandbox
+        .server
+        .put_kv("app", json!({ "dev": versioned(1, "A=1\n") }));
+    let vault = |args: &[&str]| {
+        sandbox
+            .command(args)
+            .env("VAULT_TOKEN", VAULT_TOKEN)
+            .output()
+            .unwrap()
+    };
+
+    sandbox.server.race_kv(1);
+    let output = vault(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Retrying with the latest version"));
+    assert_eq!(sandbox.server.kv_version_count("app"), 3);
+    assert!(
+        sandbox
+            .server
+            .kv_field("app", "dev")
+            .unwrap()
+            .contains("A=2")
+    );
+    assert!(sandbox.server.kv_field("app", "other").is_some());
+
+    sandbox.write(".env", &versioned(3, "A=3\n"));
+    sandbox.server.race_kv(5);
+    let output = vault(&["push"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert!(stderr(&output).contains("gave up after 5 attempts"));
+    assert!(
+        sandbox
+            .server
+            .kv_field("app", "dev")
+            .unwrap()
+            .contains("A=2")
+    );
+}
+
+#[test]
+fn vault_errors_exit_with_the_code_of_their_class() {
+    let sandbox = Sandbox::new();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "[backend]\ntype = \"vault\"\naddress = \"{}\"\n",
+            sandbox.server.url
+        ),
+    );
+    sandbox.server.put_kv("app", json!({ "dev": "A=1" }));
+    let vault = |token: &str, args: &[&str]| {
+        sandbox
+            .command(args)
+            .env("VAULT_TOKEN", token)
+            .output()
+            .unwrap()
+    };
+
+    let output = vault("wrong", &["field", "ls", "app"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(stderr(&output).contains("permission denied"));
+
+    let output = vault(VAULT_TOKEN, &["field", "ls", "missing"]);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+
+    // throttled requests are retried like those of AWS
+    sandbox.server.throttle_kv(2);
+    let output = vault(VAULT_TOKEN, &["field", "ls", "app"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("dev"));
+
+    sandbox.server.throttle_kv(usize::MAX);
+    let output = vault(VAULT_TOKEN, &["field", "ls", "app", "--max-attempts", "2"]);
+    assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+    assert!(stderr(&output).contains("--max-attempts"));
+}
+
+#[test]
+fn vault_secrets_are_managed_with_approle() {
+    let sandbox = Sandbox::new();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "[backend]\ntype = \"vault\"\naddress = \"{}\"\nrole_id = \"{}\"\n",
+            sandbox.server.url, VAULT_ROLE_ID
+        ),
+    );
+    let vault = |args: &[&str]| {
+        sandbox
+            .command(args)
+            .env("VAULT_SECRET_ID", VAULT_SECRET_ID)
+            .output()
+            .unwrap()
+    };
+
+    let output = vault(&["create", "team/api", "-d", "API keys", "--tag", "env=dev"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        sandbox.server.kv_custom_metadata("team/api"),
+        json!({ "description": "API keys", "env": "dev" })
+    );
+
+    let output = vault(&["list", "--tag", "env=dev", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("team/api"));
+
+    // the delete takes effect right away, so there is no recovery window to choose
+    let output = vault(&["delete", "team/api", "--recovery-days", "7", "--yes"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.server.kv_is_deleted("team/api"));
+    assert!(stderr(&output).contains("`--recovery-days` is ignored"));
+    assert!(stdout(&output).contains("has been deleted"));
+
+    let output = vault(&["restore", "team/api"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!sandbox.server.kv_is_deleted("team/api"));
+    assert_eq!(sandbox.server.approle_logins(), 4);
+}
+
+#[test]
+fn s3_objects_are_written_conditionally_and_versioned() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "[backend]\ntype = \"s3\"\nbucket = \"{}\"\nprefix = \"team/\"\n{}",
+            S3_BUCKET, MANIFEST
+        ),
+    );
+    sandbox.write(".env", &versioned(1, "A=1\n"));
+    sandbox.server.put_object(
+        "team/app.json",
+        &json!({ "dev": versioned(2, "A=1\nB=2\n"), "prod": "C=3" }).to_string(),
+    );
+
+    let output = sandbox.run(&["sync"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("B=2"));
+
+    sandbox.write(".env", &versioned(3, "A=1\nB=3\n"));
+    let output = sandbox.run(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.object_version_count("team/app.json"), 2);
+    let object: serde_json::Value =
+        serde_json::from_str(&sandbox.server.object("team/app.json").unwrap()).unwrap();
+    assert!(object["dev"].as_str().unwrap().contains("B=3"));
+    assert_eq!(object["prod"], "C=3");
+
+    let output = sandbox.run(&["history"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("previous"));
+
+    let output = sandbox.run(&["rollback", "--version-id", "v2", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let output = sandbox.run(&["rollback", "--version-id", "v1", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+    assert!(stdout(&output).contains("  ~ B"), "{}", stdout(&output));
+    assert_eq!(sandbox.server.object_version_count("team/app.json"), 2);
+
+    // a write of someone else between the read and the write fails the ETag condition
+    sandbox.write(".env", &versioned(4, "A=1\nB=4\n"));
+    sandbox.server.race_objects(1);
+    let output = sandbox.run(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Retrying with the latest version"));
+    let object: serde_json::Value =
+        serde_json::from_str(&sandbox.server.object("team/app.json").unwrap()).unwrap();
+    assert!(object["dev"].as_str().unwrap().contains("B=4"));
+    assert!(object["other"].is_string());
+
+    sandbox.write(".env", &versioned(5, "A=1\nB=5\n"));
+    sandbox.server.race_objects(5);
+    let output = sandbox.run(&["push"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    let object: serde_json::Value =
+        serde_json::from_str(&sandbox.server.object("team/app.json").unwrap()).unwrap();
+    assert!(object["dev"].as_str().unwrap().contains("B=4"));
+}
+
+#[test]
+fn s3_secrets_are_created_deleted_and_restored() {
+    let sandbox = Sandbox::with_credentials();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!("[backend]\ntype = \"s3\"\nbucket = \"{}\"\n", S3_BUCKET),
+    );
+
+    let output = sandbox.run(&["create", "payments", "-d", "Payments", "--tag", "team=a b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        sandbox.server.object_tags("payments.json"),
+        [("team".to_string(), "a b".to_string())]
+    );
+
+    let output = sandbox.run(&["create", "payments"]);
+    assert!(!output.status.success());
+    assert_eq!(sandbox.server.object_version_count("payments.json"), 1);
+
+    let output = sandbox.run(&["list", "--tag", "team=a b", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Payments"));
+
+    let output = sandbox.run(&["delete", "payments", "--recovery-days", "7", "--yes"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.server.object("payments.json"), None);
+    assert!(stderr(&output).contains("`--recovery-days` is ignored"));
+    assert!(stdout(&output).contains("has been deleted"));
+
+    let output = sandbox.run(&["restore", "payments"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        sandbox.server.object("payments.json").as_deref(),
+        Some("{}")
+    );
+
+    sandbox.server.set_bucket_versioning(false);
+    let output = sandbox.run(&["delete", "payments", "--yes"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("versioning is disabled"));
+}
+
+#[test]
+fn sqlite_keeps_every_version_of_every_field() {
+    let sandbox = Sandbox::new();
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!(
+            "[backend]\ntype = \"sqlite\"\npath = \"team.db\"\n{}",
+            MANIFEST
+        ),
+    );
+
+    let output = sandbox.run(&["create", "app", "-d", "App", "--tag", "env=dev"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // the database is plain SQLite, so other tools can write to it as well
+    let db = rusqlite::Connection::open(sandbox.project.join("team.db")).unwrap();
+    db.execute_batch(&format!(
+        "INSERT INTO versions VALUES ('app', 2, '2024-01-01T00:00:00Z');
+         INSERT INTO fields VALUES ('app', 2, 'dev', '{}');
+         INSERT INTO fields VALUES ('app', 2, 'prod', 'B=1');
+         UPDATE secrets SET version = 2 WHERE name = 'app';",
+        versioned(1, "A=1\n")
+    ))
+    .unwrap();
+    let rows = |db: &rusqlite::Connection| -> Vec<(i64, String, String)> {
+        let mut stmt = db
+            .prepare("SELECT version, field, value FROM fields WHERE secret = 'app' ORDER BY version, field")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    };
+    let version = |db: &rusqlite::Connection| -> i64 {
+        db.query_row(
+            "SELECT version FROM secrets WHERE name = 'app'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+
+    sandbox.write(".env", &versioned(2, "A=2\n"));
+    let output = sandbox.run(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // every version keeps a row per field, and the history of a field is a plain query away
+    let history = rows(&db);
+    let row =
+        |version: i64, field: &str, value: &str| (version, field.to_string(), value.to_string());
+    assert_eq!(
+        history,
+        vec![
+            row(2, "dev", &versioned(1, "A=1\n")),
+            row(2, "prod", "B=1"),
+            row(3, "dev", &history[2].2),
+            row(3, "prod", "B=1"),
+        ]
+    );
+    assert!(history[2].2.contains("A=2"));
+    assert_eq!(version(&db), 3);
+
+    // a failed write rolls back the whole version
+    db.execute_batch(
+        "CREATE TRIGGER reject_prod BEFORE INSERT ON fields WHEN NEW.field = 'prod'
+         BEGIN SELECT RAISE(ABORT, 'prod is read-only'); END;",
+    )
+    .unwrap();
+    sandbox.write(".env", &versioned(3, "A=3\n"));
+    let output = sandbox.run(&["push"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("prod is read-only"));
+    assert_eq!(rows(&db), history);
+    assert_eq!(version(&db), 3);
+    db.execute_batch("DROP TRIGGER reject_prod;").unwrap();
+
+    // a write of someone else since the last sync is a conflict instead of being overwritten
+    db.execute_batch(
+        "INSERT INTO versions VALUES ('app', 4, '2024-01-02T00:00:00Z');
+         INSERT INTO fields SELECT secret, 4, field, value FROM fields WHERE secret = 'app' AND version = 3;
+         UPDATE fields SET value = 'B=2' WHERE secret = 'app' AND version = 4 AND field = 'prod';
+         UPDATE fields SET value = 'A=other' WHERE secret = 'app' AND version = 4 AND field = 'dev';
+         UPDATE secrets SET version = 4 WHERE name = 'app';",
+    )
+    .unwrap();
+    let output = sandbox.run(&["push"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert_eq!(version(&db), 4);
+    let output = sandbox.run(&["pull", "--force"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.read(".env").contains("A=other"));
+
+    let output = sandbox.run(&["history"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("previous"));
+
+    let output = sandbox.run(&["rollback", "--version-id", "2", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+
+    let output = sandbox.run(&["list", "--tag", "env=dev", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("App"));
+
+    let output = sandbox.run(&["delete", "app", "--yes"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = sandbox.run(&["sync"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("scheduled for deletion"));
+
+    let output = sandbox.run(&["restore", "app"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = sandbox.run(&["sync"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+fn secrets_file(fields: serde_json::Value) -> String {
+    json!({ "secrets": { "app": { "version": 1, "fields": fields } } }).to_string()
+}
+
+#[test]
+fn git_file_keeps_secrets_and_history_in_the_repository() {
+    let sandbox = Sandbox::new();
+    sandbox.git(&["init", "-q", "-b", "main"]);
+    sandbox.write(
+        ".tc-secrets.toml",
+        &format!("[backend]\ntype = \"git\"\n{}", MANIFEST),
+    );
+    sandbox.write(
+        "secrets.enc.json",
+        &secrets_file(json!({ "dev": versioned(1, "A=1\n") })),
+    );
+    sandbox.git(&["add", "secrets.enc.json"]);
+    sandbox.git(&["commit", "-q", "-m", "first"]);
+
+    sandbox.write(".env", &versioned(2, "A=2\n"));
+    let output = sandbox.run(&["push"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let content = sandbox.read("secrets.enc.json");
+    assert!(content.contains("A=2"));
+    assert!(content.contains("\"version\": 2"));
+    sandbox.git(&["commit", "-q", "-am", "second"]);
+
+    let output = sandbox.run(&["history"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("current"));
+    assert!(stdout.contains("previous"));
+
+    let output = sandbox.run(&["rollback", "--version-id", "1", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+}
+
+#[test]
+fn git_merges_of_the_secrets_file_are_resolved_by_field() {
+    let sandbox = Sandbox::new();
+    sandbox.git(&["init", "-q", "-b", "main"]);
+    sandbox.write(".tc-secrets.toml", "[backend]\ntype = \"git\"\n");
+    sandbox.write(
+        "secrets.enc.json",
+        &secrets_file(json!({ "dev": "A=1", "prod": "B=1" })),
+    );
+
+    let output = sandbox.run(&["merge-driver", "install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        sandbox
+            .read(".gitattributes")
+            .contains("/secrets.enc.json merge=tc-secrets")
+    );
+    assert_eq!(sandbox.read(".gitignore"), ".tc-secrets/\n");
+    assert!(stdout(&output).contains(".gitignore"));
+    let output = sandbox.run(&["merge-driver", "install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.read(".gitignore"), ".tc-secrets/\n");
+    assert!(!stdout(&output).contains(".gitignore"));
+    sandbox.git(&["add", "."]);
+    sandbox.git(&["commit", "-q", "-m", "base"]);
+
+    sandbox.git(&["checkout", "-q", "-b", "other"]);
+    sandbox.write(
+        "secrets.enc.json",
+        &json!({ "secrets": { "app": { "version": 2, "fields": { "dev": "A=1", "prod": "B=2" } } } })
+            .to_string(),
+    );
+    sandbox.git(&["commit", "-q", "-am", "prod"]);
+    sandbox.git(&["checkout", "-q", "main"]);
+    sandbox.write(
+        "secrets.enc.json",
+        &json!({ "secrets": { "app": { "version": 2, "fields": { "dev": "A=2", "prod": "B=1" } } } })
+            .to_string(),
+    );
+    sandbox.git(&["commit", "-q", "-am", "dev"]);
+
+    sandbox.git(&["merge", "-q", "--no-edit", "other"]);
+    let merged: serde_json::Value =
+        serde_json::from_str(&sandbox.read("secrets.enc.json")).unwrap();
+    assert_eq!(merged["secrets"]["app"]["fields"]["dev"], "A=2");
+    assert_eq!(merged["secrets"]["app"]["fields"]["prod"], "B=2");
+    assert_eq!(merged["secrets"]["app"]["version"], 3);
+
+    let driver = sandbox.git(&["config", "merge.tc-secrets.driver"]);
+    assert_eq!(
+        stdout(&driver).trim(),
+        "tc-secrets merge-driver run %O %A %B"
+    );
+}
+
+#[test]
+fn git_merges_of_the_same_field_stop_with_a_conflict() {
+    let sandbox = Sandbox::new();
+    sandbox.git(&["init", "-q", "-b", "main"]);
+    sandbox.write(".tc-secrets.toml", "[backend]\ntype = \"git\"\n");
+    sandbox.write(
+        "secrets.enc.json",
+        &secrets_file(json!({ "dev": "A=1", "prod": "B=1" })),
+    );
+    let output = sandbox.run(&["merge-driver", "install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    sandbox.git(&["add", "."]);
+    sandbox.git(&["commit", "-q", "-m", "base"]);
+
+    sandbox.git(&["checkout", "-q", "-b", "other"]);
+    sandbox.write(
+        "secrets.enc.json",
+        &json!({ "secrets": { "app": { "version": 2, "fields": { "dev": "A=3", "prod": "B=1" } } } })
+            .to_string(),
+    );
+    sandbox.git(&["commit", "-q", "-am", "their dev"]);
+    sandbox.git(&["checkout", "-q", "main"]);
+    sandbox.write(
+        "secrets.enc.json",
+        &json!({ "secrets": { "app": { "version": 2, "fields": { "dev": "A=2", "prod": "B=1" } } } })
+            .to_string(),
+    );
+    sandbox.git(&["commit", "-q", "-am", "our dev"]);
+
+    let output = sandbox.try_git(&["merge", "-q", "--no-edit", "other"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("app/dev"), "{}", stderr(&output));
+    let merged: serde_json::Value =
+        serde_json::from_str(&sandbox.read("secrets.enc.json")).unwrap();
+    assert_eq!(merged["secrets"]["app"]["fields"]["dev"], "A=2");
+    let status = sandbox.git(&["status", "--porcelain"]);
+    assert!(stdout(&status).contains("UU secrets.enc.json"));
+
+    sandbox.write("base.json", &secrets_file(json!({ "dev": "A=1" })));
+    sandbox.write("ours.json", &secrets_file(json!({ "dev": "A=2" })));
+    sandbox.write("theirs.json", &secrets_file(json!({ "dev": "A=3" })));
+    let output = sandbox.run(&[
+        "merge-driver",
+        "run",
+        "base.json",
+        "ours.json",
+        "theirs.json",
+    ]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("app/dev"));
+    assert!(sandbox.read("ours.json").contains("A=2"));
+}
@@ -0,0 +1,896 @@
+// A local stand-in for AWS Secrets Manager, STS and the other backends, and a sandbox to run the CLI in.
+#![allow(dead_code)]
+
+mod s3;
+mod ssm;
+mod vault;
+
+pub use s3::BUCKET as S3_BUCKET;
+pub use vault::{ROLE_ID as VAULT_ROLE_ID, SECRET_ID as VAULT_SECRET_ID, TOKEN as VAULT_TOKEN};
+
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub const ACCOUNT_ID: &str = "123456789012";
+pub const LEGACY_PROFILE_NAME: &str = "tc-secrets-cli-profile";
+pub const SESSION_ACCESS_KEY_ID: &str = "ASIASESSION";
+// small pages so listing has to follow the next tokens
+const PAGE_SIZE: usize = 2;
+
+struct StoredVersion {
+    version_id: String,
+    value: String,
+}
+
+#[derive(Default, Clone)]
+struct Metadata {
+    description: Option<String>,
+    tags: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct State {
+    secrets: HashMap<String, Vec<StoredVersion>>,
+    metadata: HashMap<String, Metadata>,
+    // secrets scheduled for deletion
+    deleted: HashSet<String>,
+    next_version: usize,
+    // form bodies of the AssumeRole requests
+    assume_role_requests: Vec<String>,
+    // access key ids that signed the Secrets Manager requests
+    access_key_ids: Vec<String>,
+    // number of upcoming Secrets Manager requests to throttle
+    throttled: usize,
+    // number of upcoming secret writes followed by a write of someone else
+    races: usize,
+    // field and value of a write of someone else that lands right before the next secret write
+    overtake: Option<(String, String)>,
+    // secrets whose writes are denied
+    read_only: HashSet<String>,
+    parameters: ssm::Parameters,
+    kv: vault::Kv,
+    bucket: s3::Bucket,
+}
+
+impl State {
+    fn put(&mut self, name: &str, value: String) -> String {
+        self.next_version += 1;
+        let version_id = format!("version-{}", self.next_version);
+        self.put_version(name, version_id, value)
+    }
+
+    fn put_version(&mut self, name: &str, version_id: String, value: String) -> String {
+        self.secrets
+            .entry(name.to_string())
+            .or_default()
+            .push(StoredVersion {
+                version_id: version_id.clone(),
+                value,
+            });
+        version_id
+    }
+}
+
+pub struct MockServer {
+    pub url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = state.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = server_state.clone();
+                std::thread::spawn(move || serve_connection(stream, state));
+            }
+        });
+
+        MockServer { url, state }
+    }
+
+    pub fn put_secret(&self, name: &str, fields: &[(&str, &str)]) {
+        let fields: HashMap<&str, &str> = fields.iter().copied().collect();
+        self.state
+            .lock()
+            .unwrap()
+            .put(name, serde_json::to_string(&fields).unwrap());
+    }
+
+    pub fn describe_secret(&self, name: &str, description: &str, tags: &[(&str, &str)]) {
+        self.state.lock().unwrap().metadata.insert(
+            name.to_string(),
+            Metadata {
+                description: Some(description.to_string()),
+                tags: tags
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            },
+        );
+    }
+
+    pub fn description(&self, name: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.metadata.get(name)?.description.clone()
+    }
+
+    pub fn tags(&self, name: &str) -> Vec<(String, String)> {
+        let state = self.state.lock().unwrap();
+        state
+            .metadata
+            .get(name)
+            .map(|metadata| metadata.tags.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn is_deleted(&self, name: &str) -> bool {
+        self.state.lock().unwrap().deleted.contains(name)
+    }
+
+    pub fn field(&self, name: &str, field: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let latest = state.secrets.get(name)?.last()?;
+        let fields: HashMap<String, String> = serde_json::from_str(&latest.value).unwrap();
+        fields.get(field).cloned()
+    }
+
+    pub fn assume_role_requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().assume_role_requests.clone()
+    }
+
+    pub fn access_key_ids(&self) -> Vec<String> {
+        self.state.lock().unwrap().access_key_ids.clone()
+    }
+
+    pub fn throttle(&self, requests: usize) {
+        self.state.lock().unwrap().throttled = requests;
+    }
+
+    pub fn corrupt_version(&self, name: &str, version_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        let versions = state.secrets.get_mut(name).unwrap();
+        let version = versions.iter_mut().find(|v| v.version_id == version_id);
+        version.unwrap().value = "not json".to_string();
+    }
+
+    pub fn overtake(&self, field: &str, value: &str) {
+        self.state.lock().unwrap().overtake = Some((field.to_string(), value.to_string()));
+    }
+
+    pub fn race(&self, writes: usize) {
+        self.state.lock().unwrap().races = writes;
+    }
+
+    pub fn deny_writes(&self, name: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .read_only
+            .insert(name.to_string());
+    }
+
+    pub fn put_parameter(&self, name: &str, value: &str) {
+        self.state.lock().unwrap().parameters.put(name, value);
+    }
+
+    pub fn overtake_parameter(&self, name: &str, value: &str) {
+        self.state.lock().unwrap().parameters.overtake =
+            Some((name.to_string(), value.to_string()));
+    }
+
+    pub fn deny_parameter_writes(&self, name: &str) {
+        self.state.lock().unwrap().parameters.deny_writes(name);
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<String> {
+        self.state.lock().unwrap().parameters.get(name)
+    }
+
+    pub fn parameter_version_count(&self, name: &str) -> usize {
+        self.state.lock().unwrap().parameters.version_count(name)
+    }
+
+    pub fn put_kv(&self, path: &str, data: Value) {
+        self.state.lock().unwrap().kv.put(path, data);
+    }
+
+    pub fn kv_field(&self, path: &str, key: &str) -> Option<String> {
+        self.state.lock().unwrap().kv.get(path, key)
+    }
+
+    pub fn kv_version_count(&self, path: &str) -> usize {
+        self.state.lock().unwrap().kv.version_count(path)
+    }
+
+    pub fn kv_is_deleted(&self, path: &str) -> bool {
+        self.state.lock().unwrap().kv.is_deleted(path)
+    }
+
+    pub fn kv_custom_metadata(&self, path: &str) -> Value {
+        self.state.lock().unwrap().kv.custom_metadata(path)
+    }
+
+    pub fn throttle_kv(&self, requests: usize) {
+        self.state.lock().unwrap().kv.throttled = requests;
+    }
+
+    pub fn race_kv(&self, writes: usize) {
+        self.state.lock().unwrap().kv.races = writes;
+    }
+
+    pub fn approle_logins(&self) -> usize {
+        self.state.lock().unwrap().kv.logins
+    }
+
+    pub fn put_object(&self, key: &str, body: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .bucket
+            .put(key, Some(body.to_string()));
+    }
+
+    pub fn object(&self, key: &str) -> Option<String> {
+        self.state.lock().unwrap().bucket.get(key)
+    }
+
+    pub fn object_version_count(&self, key: &str) -> usize {
+        self.state.lock().unwrap().bucket.version_count(key)
+    }
+
+    pub fn object_tags(&self, key: &str) -> Vec<(String, String)> {
+        self.state.lock().unwrap().bucket.tags(key)
+    }
+
+    pub fn set_bucket_versioning(&self, enabled: bool) {
+        self.state.lock().unwrap().bucket.versioning = enabled;
+    }
+
+    pub fn race_objects(&self, writes: usize) {
+        self.state.lock().unwrap().bucket.races = writes;
+    }
+
+    pub fn version_count(&self, name: &str) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .secrets
+            .get(name)
+            .map_or(0, |versions| versions.len())
+    }
+}
+
+fn serve_connection(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+
+    // keep serving requests on the connection until the client closes it
+    loop {
+        let mut headers = HashMap::new();
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut request_line = line.split_whitespace();
+        let method = request_line.next().unwrap_or_default().to_string();
+        let target = request_line.next().unwrap_or_default().to_string();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+
+        let length = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        let body = String::from_utf8_lossy(&body).to_string();
+
+        let mut extra_headers = Vec::new();
+        let (status, content_type, response) = match headers.get("x-amz-target") {
+            Some(target) => {
+                let operation = target.rsplit('.').next().unwrap_or_default();
+                let request: Value = serde_json::from_str(&body).unwrap_or(Value::Null);
+                let mut state = state.lock().unwrap();
+                if let Some(access_key_id) = headers
+                    .get("authorization")
+                    .and_then(|auth| auth.split("Credential=").nth(1))
+                    .and_then(|credential| credential.split('/').next())
+                {
+                    state.access_key_ids.push(access_key_id.to_string());
+                }
+                let result = if target.starts_with("AmazonSSM.") {
+                    ssm::handle(&mut state.parameters, operation, &request)
+                } else {
+                    secrets_manager(&mut state, operation, &request)
+                };
+                match result {
+                    Ok(response) => (200, "application/x-amz-json-1.1", response.to_string()),
+                    Err((kind, message)) => (
+                        400,
+                        "application/x-amz-json-1.1",
+                        json!({ "__type": kind, "message": message }).to_string(),
+                    ),
+                }
+            }
+            None if target.starts_with("/v1/") => {
+                let request: Value = serde_json::from_str(&body).unwrap_or(Value::Null);
+                let (status, response) = vault::handle(
+                    &mut state.lock().unwrap().kv,
+                    &method,
+                    &target,
+                    headers.get("x-vault-token").map(String::as_str),
+                    &request,
+                );
+                let response = if response.is_null() {
+                    String::new()
+                } else {
+                    response.to_string()
+                };
+                (status, "application/json", response)
+            }
+            None if target.starts_with(&format!("/{}", s3::BUCKET)) => {
+                let response = s3::handle(
+                    &mut state.lock().unwrap().bucket,
+                    &method,
+                    &target,
+                    &headers,
+                    &body,
+                );
+                extra_headers = response.headers;
+                (response.status, "application/xml", response.body)
+            }
+            None if body.contains("Action=GetCallerIdentity") => {
+                (200, "text/xml", caller_identity())
+            }
+            None if body.contains("Action=AssumeRole") => {
+                state
+                    .lock()
+                    .unwrap()
+                    .assume_role_requests
+                    .push(body.clone());
+                (200, "text/xml", assume_role())
+            }
+            None => (400, "text/plain", format!("unsupported request: {}", body)),
+        };
+
+        let extra_headers: String = extra_headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        // HEAD responses announce the length of the body they leave out
+        let length = response.len();
+        let response = if method == "HEAD" {
+            String::new()
+        } else {
+            response
+        };
+        let reply = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}\r\n{}",
+            status,
+            match status {
+                200 => "OK",
+                204 => "No Content",
+                403 => "Forbidden",
+                404 => "Not Found",
+                405 => "Method Not Allowed",
+                412 => "Precondition Failed",
+                _ => "Bad Request",
+            },
+            content_type,
+            length,
+            extra_headers,
+            response
+        );
+        if stream.write_all(reply.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+fn secrets_manager(
+    state: &mut State,
+    operation: &str,
+    request: &Value,
+) -> Result<Value, (&'static str, String)> {
+    if state.throttled > 0 {
+        state.throttled -= 1;
+        return Err(("ThrottlingException", "Rate exceeded".to_string()));
+    }
+
+    let secret_id = request["SecretId"].as_str().unwrap_or_default().to_string();
+    let not_found = || {
+        (
+            "ResourceNotFoundException",
+            format!(
+                "Secrets Manager can't find the specified secret {}",
+                secret_id
+            ),
+        )
+    };
+
+    let keeps_deleted = ["CreateSecret", "RestoreSecret", "ListSecrets"];
+    if state.deleted.contains(&secret_id) && !keeps_deleted.contains(&operation) {
+        return Err((
+            "InvalidRequestException",
+            format!("secret {} is marked for deletion", secret_id),
+        ));
+    }
+
+    match operation {
+        "GetSecretValue" => {
+            let versions = state.secrets.get(&secret_id).ok_or_else(not_found)?;
+            let version = match request["VersionId"].as_str() {
+                Some(version_id) => versions.iter().find(|v| v.version_id == version_id),
+                None => versions.last(),
+            }
+            .ok_or_else(not_found)?;
+
+            Ok(json!({
+                "ARN": arn(&secret_id),
+                "Name": secret_id,
+                "VersionId": version.version_id,
+                "SecretString": version.value,
+                "CreatedDate": 1_700_000_000.0,
+            }))
+        }
+        "PutSecretValue" => {
+            if !state.secrets.contains_key(&secret_id) {
+                return Err(not_found());
+            }
+            if state.read_only.contains(&secret_id) {
+                return Err((
+                    "AccessDeniedException",
+                    format!("not authorized to write secret {}", secret_id),
+                ));
+            }
+            let value = request["SecretString"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            // someone else writes after the writer checked the version, and gets overwritten
+            if let Some((field, field_value)) = state.overtake.take() {
+                let latest = &state.secrets[&secret_id].last().unwrap().value;
+                let mut fields: HashMap<String, String> = serde_json::from_str(latest).unwrap();
+                fields.insert(field, field_value);
+                state.put(&secret_id, serde_json::to_string(&fields).unwrap());
+            }
+            let version_id = match request["ClientRequestToken"].as_str() {
+                Some(token) => state.put_version(&secret_id, token.to_string(), value.clone()),
+                None => state.put(&secret_id, value.clone()),
+            };
+
+            // someone else writes another field before the writer can check its version
+            if state.races > 0 {
+                state.races -= 1;
+                let mut fields: HashMap<String, String> = serde_json::from_str(&value).unwrap();
+                fields.insert("other".to_string(), format!("race-{}", state.next_version));
+                state.put(&secret_id, serde_json::to_string(&fields).unwrap());
+            }
+
+            Ok(json!({
+                "ARN": arn(&secret_id),
+                "Name": secret_id,
+                "VersionId": version_id,
+                "VersionStages": ["AWSCURRENT"],
+            }))
+        }
+        "DescribeSecret" => {
+            let versions = state.secrets.get(&secret_id).ok_or_else(not_found)?;
+
+            Ok(json!({
+                "ARN": arn(&secret_id),
+                "Name": secret_id,
+                "LastChangedDate": 1_700_000_000.0,
+                "VersionIdsToStages": stages(versions),
+            }))
+        }
+        "ListSecretVersionIds" => {
+            let versions = state.secrets.get(&secret_id).ok_or_else(not_found)?;
+            let stages = stages(versions);
+
+            Ok(json!({
+                "ARN": arn(&secret_id),
+                "Name": secret_id,
+                "Versions": versions.iter().enumerate().map(|(i, v)| json!({
+                    "VersionId": v.version_id,
+                    "VersionStages": stages.get(&v.version_id).cloned().unwrap_or_default(),
+                    "CreatedDate": 1_700_000_000.0 + i as f64,
+                })).collect::<Vec<_>>(),
+            }))
+        }
+        "ListSecrets" => {
+            let mut names: Vec<&String> = state
+                .secrets
+                .keys()
+                .filter(|name| !state.deleted.contains(*name))
+                .filter(|name| {
+                    let metadata = state.metadata.get(*name).cloned().unwrap_or_default();
+                    matches_filters(name, &metadata, &request["Filters"])
+                })
+                .collect();
+            names.sort();
+
+            let start: usize = request["NextToken"]
+                .as_str()
+                .map_or(0, |token| token.parse().unwrap());
+            let end = (start + PAGE_SIZE).min(names.len());
+            let mut response = json!({
+                "SecretList": names[start..end].iter().map(|name| {
+                    let metadata = state.metadata.get(*name).cloned().unwrap_or_default();
+                    json!({
+                        "ARN": arn(name),
+                        "Name": name,
+                        "Description": metadata.description,
+                        "LastChangedDate": 1_700_000_000.0,
+                        "Tags": metadata.tags.iter().map(|(key, value)| json!({
+                            "Key": key,
+                            "Value": value,
+                        })).collect::<Vec<_>>(),
+                    })
+                }).collect::<Vec<_>>(),
+            });
+            if end < names.len() {
+                response["NextToken"] = json!(end.to_string());
+            }
+
+            Ok(response)
+        }
+        "CreateSecret" => {
+            let name = request["Name"].as_str().unwrap_or_default().to_string();
+            if state.secrets.contains_key(&name) {
+                return Err((
+                    "ResourceExistsException",
+                    format!("the secret {} already exists", name),
+                ));
+            }
+            let value = request["SecretString"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            let version_id = state.put(&name, value);
+            let tags = request["Tags"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|tag| {
+                    (
+                        tag["Key"].as_str().unwrap_or_default().to_string(),
+                        tag["Value"].as_str().unwrap_or_default().to_string(),
+                    )
+                })
+                .collect();
+            state.metadata.insert(
+                name.clone(),
+                Metadata {
+                    description: request["Description"].as_str().map(String::from),
+                    tags,
+                },
+            );
+
+            Ok(json!({
+                "ARN": arn(&name),
+                "Name": name,
+                "VersionId": version_id,
+            }))
+        }
+        "DeleteSecret" => {
+            if !state.secrets.contains_key(&secret_id) {
+                return Err(not_found());
+            }
+            let days = request["RecoveryWindowInDays"].as_i64().unwrap_or(30);
+            state.deleted.insert(secret_id.clone());
+
+            Ok(json!({
+                "ARN": arn(&secret_id),
+                "Name": secret_id,
+                "DeletionDate": 1_700_000_000.0 + (days * 24 * 60 * 60) as f64,
+            }))
+        }
+        "RestoreSecret" => {
+            if !state.secrets.contains_key(&secret_id) {
+                return Err(not_found());
+            }
+            state.deleted.remove(&secret_id);
+
+            Ok(json!({
+                "ARN": arn(&secret_id),
+                "Name": secret_id,
+            }))
+        }
+        _ => Err((
+            "InvalidRequestException",
+            format!("unsupported operation {}", operation),
+        )),
+    }
+}
+
+// Secrets Manager matches name prefixes, tag keys and tag values independently
+fn matches_filters(name: &str, metadata: &Metadata, filters: &Value) -> bool {
+    filters.as_array().into_iter().flatten().all(|filter| {
+        let values: Vec<&str> = filter["Values"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        values.iter().any(|value| match filter["Key"].as_str() {
+            Some("name") => name.to_lowercase().starts_with(&value.to_lowercase()),
+            Some("tag-key") => metadata.tags.iter().any(|(key, _)| key == value),
+            Some("tag-value") => metadata.tags.iter().any(|(_, v)| v == value),
+            _ => false,
+        })
+    })
+}
+
+fn stages(versions: &[StoredVersion]) -> HashMap<String, Vec<&'static str>> {
+    versions
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(idx, version)| {
+            let stages = match idx {
+                0 => vec!["AWSCURRENT"],
+                1 => vec!["AWSPREVIOUS"],
+                _ => Vec::new(),
+            };
+            (version.version_id.clone(), stages)
+        })
+        .collect()
+}
+
+fn arn(name: &str) -> String {
+    format!(
+        "arn:aws:secretsmanager:us-east-1:{}:secret:{}",
+        ACCOUNT_ID, name
+    )
+}
+
+fn caller_identity() -> String {
+    format!(
+        "<GetCallerIdentityResponse xmlns=\"https://sts.amazonaws.com/doc/2011-06-15/\">\
+         <GetCallerIdentityResult>\
+         <Arn>arn:aws:iam::{0}:user/tester</Arn>\
+         <UserId>AIDATESTER</UserId>\
+         <Account>{0}</Account>\
+         </GetCallerIdentityResult>\
+         <ResponseMetadata><RequestId>mock</RequestId></ResponseMetadata>\
+         </GetCallerIdentityResponse>",
+        ACCOUNT_ID
+    )
+}
+
+fn assume_role() -> String {
+    format!(
+        "<AssumeRoleResponse xmlns=\"https://sts.amazonaws.com/doc/2011-06-15/\">\
+         <AssumeRoleResult>\
+         <Credentials>\
+         <AccessKeyId>{0}</AccessKeyId>\
+         <SecretAccessKey>session-secret</SecretAccessKey>\
+         <SessionToken>session-token</SessionToken>\
+         <Expiration>2099-01-01T00:00:00Z</Expiration>\
+         </Credentials>\
+         <AssumedRoleUser>\
+         <Arn>arn:aws:sts::{1}:assumed-role/deploy/tc-secrets</Arn>\
+         <AssumedRoleId>AROADEPLOY:tc-secrets</AssumedRoleId>\
+         </AssumedRoleUser>\
+         </AssumeRoleResult>\
+         <ResponseMetadata><RequestId>mock</RequestId></ResponseMetadata>\
+         </AssumeRoleResponse>",
+        SESSION_ACCESS_KEY_ID, ACCOUNT_ID
+    )
+}
+
+fn percent_decode(path: &str) -> String {
+    let mut decoded = Vec::new();
+    let bytes = path.as_bytes();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%'
+            && let Some(byte) = path
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+static SANDBOX_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// a temporary project directory with its own AWS configuration and a fake `aws` CLI
+pub struct Sandbox {
+    pub root: PathBuf,
+    pub project: PathBuf,
+    pub server: MockServer,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "tc-secrets-test-{}-{}",
+            std::process::id(),
+            SANDBOX_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let project = root.join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(root.join("bin")).unwrap();
+
+        // `aws configure [--profile <name>]` only writes the credentials of the profile
+        let aws = root.join("bin").join("aws");
+        std::fs::write(
+            &aws,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> \"{}\"\nprofile=default\nif [ \"$2\" = \"--profile\" ]; then profile=\"$3\"; fi\nprintf '[%s]\\naws_access_key_id = test\\naws_secret_access_key = test\\n' \"$profile\" > \"{}\"\n",
+                root.join("aws-calls").display(),
+                root.join("credentials").display()
+            ),
+        )
+        .unwrap();
+        set_mode(&aws, 0o755);
+        set_mode(&project, 0o700);
+
+        Sandbox {
+            root,
+            project,
+            server: MockServer::start(),
+        }
+    }
+
+    pub fn with_credentials() -> Self {
+        Sandbox::with_profile("default")
+    }
+
+    pub fn with_profile(profile: &str) -> Self {
+        let sandbox = Sandbox::new();
+        std::fs::write(
+            sandbox.root.join("credentials"),
+            format!(
+                "[{}]\naws_access_key_id = test\naws_secret_access_key = test\n",
+                profile
+            ),
+        )
+        .unwrap();
+        sandbox
+    }
+
+    pub fn write(&self, name: &str, content: &str) -> PathBuf {
+        let path = self.project.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(&path, content).unwrap();
+        set_mode(&path, 0o600);
+        path
+    }
+
+    pub fn read(&self, name: &str) -> String {
+        std::fs::read_to_string(self.project.join(name)).unwrap()
+    }
+
+    // runs git in the project with a fixed identity
+    pub fn git(&self, args: &[&str]) -> Output {
+        let output = self.try_git(args);
+        assert!(
+            output.status.success(),
+            "git {:?}: {}",
+            args,
+            stderr(&output)
+        );
+        output
+    }
+
+    // runs git with the CLI on the PATH, for the merge driver
+    pub fn try_git(&self, args: &[&str]) -> Output {
+        let bin = Path::new(env!("CARGO_BIN_EXE_tc-secrets"))
+            .parent()
+            .unwrap();
+        let path = format!(
+            "{}:{}",
+            bin.display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+
+        Command::new("git")
+            .args(args)
+            .current_dir(&self.project)
+            .env("PATH", path)
+            .env("HOME", &self.root)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .unwrap()
+    }
+
+    pub fn aws_calls(&self) -> String {
+        std::fs::read_to_string(self.root.join("aws-calls")).unwrap_or_default()
+    }
+
+    // runs the CLI against the mock server, passing the endpoint on the command line
+    pub fn run(&self, args: &[&str]) -> Output {
+        let mut command = self.command(args);
+        command.args(["--endpoint-url", &self.server.url]);
+        command.output().unwrap()
+    }
+
+    pub fn command(&self, args: &[&str]) -> Command {
+        let path = format!(
+            "{}:{}",
+            self.root.join("bin").display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_tc-secrets"));
+        command
+            .args(args)
+            .current_dir(&self.project)
+            .env("PATH", path)
+            .env("HOME", &self.root)
+            .env("AWS_SHARED_CREDENTIALS_FILE", self.root.join("credentials"))
+            .env("AWS_CONFIG_FILE", self.root.join("config"))
+            .env("AWS_REGION", "us-east-1")
+            .env("AWS_EC2_METADATA_DISABLED", "true")
+            .env_remove("XDG_CACHE_HOME")
+            .env_remove("AWS_ENDPOINT_URL")
+            .env_remove("AWS_PROFILE")
+            .env_remove("AWS_ACCESS_KEY_ID")
+            .env_remove("AWS_SECRET_ACCESS_KEY")
+            .env_remove("AWS_SESSION_TOKEN")
+            .env_remove("VAULT_ADDR")
+            .env_remove("VAULT_TOKEN")
+            .env_remove("VAULT_NAMESPACE")
+            .env_remove("VAULT_ROLE_ID")
+            .env_remove("VAULT_SECRET_ID");
+        command
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+fn set_mode(path: &Path, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
@@ -0,0 +1,413 @@
+// A local stand-in for a single S3 bucket, addressed path-style like MinIO.
+
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+use super::{PAGE_SIZE, percent_decode};
+
+pub const BUCKET: &str = "tc-secrets-bucket";
+
+pub struct ObjectVersion {
+    version_id: String,
+    etag: String,
+    // None for delete markers
+    body: Option<String>,
+    description: Option<String>,
+    tags: Vec<(String, String)>,
+    kms_key_id: Option<String>,
+    modified: u64,
+}
+
+pub struct Bucket {
+    // versions of each key, oldest first
+    objects: BTreeMap<String, Vec<ObjectVersion>>,
+    pub versioning: bool,
+    // number of upcoming conditional writes preceded by a write of someone else
+    pub races: usize,
+    clock: u64,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Bucket {
+            objects: BTreeMap::new(),
+            versioning: true,
+            races: 0,
+            clock: 0,
+        }
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Bucket {
+    pub fn put(&mut self, key: &str, body: Option<String>) -> &ObjectVersion {
+        self.clock += 1;
+        let version = ObjectVersion {
+            version_id: if self.versioning {
+                format!("v{}", self.clock)
+            } else {
+                "null".to_string()
+            },
+            etag: format!("\"etag-{}\"", self.clock),
+            body,
+            description: None,
+            tags: Vec::new(),
+            kms_key_id: None,
+            modified: self.clock,
+        };
+
+        let versions = self.objects.entry(key.to_string()).or_default();
+        if !self.versioning {
+            versions.clear();
+        }
+        versions.push(version);
+        versions.last().unwrap()
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.latest(key)?.body.clone()
+    }
+
+    pub fn version_count(&self, key: &str) -> usize {
+        self.objects.get(key).map_or(0, |versions| {
+            versions
+                .iter()
+                .filter(|version| version.body.is_some())
+                .count()
+        })
+    }
+
+    pub fn tags(&self, key: &str) -> Vec<(String, String)> {
+        self.latest(key)
+            .map(|version| version.tags.clone())
+            .unwrap_or_default()
+    }
+
+    fn latest(&self, key: &str) -> Option<&ObjectVersion> {
+        self.objects.get(key)?.last()
+    }
+
+    fn find(&self, key: &str, version_id: Option<&String>) -> Option<&ObjectVersion> {
+        match version_id {
+            Some(version_id) => self
+                .objects
+                .get(key)?
+                .iter()
+                .find(|version| &version.version_id == version_id),
+            None => self.current(key),
+        }
+    }
+
+    // the latest version unless it's a delete marker
+    fn current(&self, key: &str) -> Option<&ObjectVersion> {
+        self.latest(key).filter(|version| version.body.is_some())
+    }
+}
+
+pub fn handle(
+    bucket: &mut Bucket,
+    method: &str,
+    target: &str,
+    headers: &HashMap<String, String>,
+    body: &str,
+) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query: HashMap<String, String> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key.to_string(), percent_decode(value))
+        })
+        .collect();
+    let key = percent_decode(
+        path.trim_start_matches('/')
+            .trim_start_matches(BUCKET)
+            .trim_start_matches('/'),
+    );
+
+    if key.is_empty() {
+        return match method {
+            "GET" if query.contains_key("versioning") => xml(format!(
+                "<VersioningConfiguration><Status>{}</Status></VersioningConfiguration>",
+                if bucket.versioning {
+                    "Enabled"
+                } else {
+                    "Suspended"
+                }
+            )),
+            "GET" if query.contains_key("versions") => list_versions(bucket, &query),
+            "GET" if query.get("list-type").map(String::as_str) == Some("2") => {
+                list_objects(bucket, &query)
+            }
+            _ => error(405, "MethodNotAllowed"),
+        };
+    }
+
+    let version_id = query.get("versionId");
+
+    match method {
+        "HEAD" | "GET" if query.contains_key("tagging") => {
+            let Some(version) = bucket.find(&key, version_id) else {
+                return error(404, "NoSuchKey");
+            };
+            let tags: String = version
+                .tags
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "<Tag><Key>{}</Key><Value>{}</Value></Tag>",
+                        escape(key),
+                        escape(value)
+                    )
+                })
+                .collect();
+            xml(format!("<Tagging><TagSet>{}</TagSet></Tagging>", tags))
+        }
+        "HEAD" | "GET" => {
+            let Some(version) = bucket
+                .find(&key, version_id)
+                .filter(|version| version.body.is_some())
+            else {
+                return error(404, "NoSuchKey");
+            };
+            let mut headers = vec![
+                ("ETag".to_string(), version.etag.clone()),
+                ("Last-Modified".to_string(), http_date(version.modified)),
+            ];
+            if bucket.versioning {
+                headers.push(("x-amz-version-id".to_string(), version.version_id.clone()));
+            }
+            if let Some(description) = &version.description {
+                headers.push(("x-amz-meta-description".to_string(), description.clone()));
+            }
+            if let Some(kms_key_id) = &version.kms_key_id {
+                headers.push((
+                    "x-amz-server-side-encryption".to_string(),
+                    "aws:kms".to_string(),
+                ));
+                headers.push((
+                    "x-amz-server-side-encryption-aws-kms-key-id".to_string(),
+                    kms_key_id.clone(),
+                ));
+            }
+
+            Response {
+                status: 200,
+                headers,
+                body: if method == "GET" {
+                    version.body.clone().unwrap_or_default()
+                } else {
+                    String::new()
+                },
+            }
+        }
+        "PUT" => {
+            // someone else writes another field after the writer read the object
+            if bucket.races > 0 && headers.contains_key("if-match") {
+                bucket.races -= 1;
+                let mut fields: Value =
+                    serde_json::from_str(&bucket.get(&key).unwrap_or_default()).unwrap();
+                fields["other"] = json!(format!("race-{}", bucket.clock));
+                bucket.put(&key, Some(fields.to_string()));
+            }
+            let current = bucket.current(&key).map(|version| version.etag.clone());
+            let precondition_met = match (headers.get("if-match"), headers.get("if-none-match")) {
+                (Some(etag), _) if etag == "*" => current.is_some(),
+                (Some(etag), _) => current.as_ref() == Some(etag),
+                (None, Some(_)) => current.is_none(),
+                (None, None) => true,
+            };
+            if !precondition_met {
+                return error(412, "PreconditionFailed");
+            }
+
+            let tags = headers
+                .get("x-amz-tagging")
+                .map(|tagging| {
+                    tagging
+                        .split('&')
+                        .filter_map(|pair| pair.split_once('='))
+                        .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let description = headers.get("x-amz-meta-description").cloned();
+            let kms_key_id = headers
+                .get("x-amz-server-side-encryption-aws-kms-key-id")
+                .cloned();
+
+            let versioning = bucket.versioning;
+            let version = bucket.put(&key, Some(body.to_string()));
+            let mut response_headers = vec![("ETag".to_string(), version.etag.clone())];
+            if versioning {
+                response_headers.push(("x-amz-version-id".to_string(), version.version_id.clone()));
+            }
+            let version = bucket.objects.get_mut(&key).unwrap().last_mut().unwrap();
+            version.tags = tags;
+            version.description = description;
+            version.kms_key_id = kms_key_id;
+
+            Response {
+                status: 200,
+                headers: response_headers,
+                body: String::new(),
+            }
+        }
+        "DELETE" => {
+            match version_id {
+                Some(version_id) => {
+                    if let Some(versions) = bucket.objects.get_mut(&key) {
+                        versions.retain(|version| &version.version_id != version_id);
+                    }
+                }
+                None if bucket.versioning => {
+                    bucket.put(&key, None);
+                }
+                None => {
+                    bucket.objects.remove(&key);
+                }
+            }
+            Response {
+                status: 204,
+                headers: Vec::new(),
+                body: String::new(),
+            }
+        }
+        _ => error(405, "MethodNotAllowed"),
+    }
+}
+
+fn list_objects(bucket: &Bucket, query: &HashMap<String, String>) -> Response {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let keys: Vec<(&String, &ObjectVersion)> = bucket
+        .objects
+        .keys()
+        .filter(|key| key.starts_with(&prefix))
+        .filter_map(|key| Some((key, bucket.current(key)?)))
+        .collect();
+
+    let start: usize = query
+        .get("continuation-token")
+        .map_or(0, |token| token.parse().unwrap());
+    let end = (start + PAGE_SIZE).min(keys.len());
+    let contents: String = keys[start..end]
+        .iter()
+        .map(|(key, version)| {
+            format!(
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                escape(key),
+                iso_date(version.modified),
+                escape(&version.etag),
+                version.body.as_ref().map_or(0, String::len)
+            )
+        })
+        .collect();
+    let next = if end < keys.len() {
+        format!(
+            "<IsTruncated>true</IsTruncated><NextContinuationToken>{}</NextContinuationToken>",
+            end
+        )
+    } else {
+        "<IsTruncated>false</IsTruncated>".to_string()
+    };
+
+    xml(format!(
+        "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys>{}{}</ListBucketResult>",
+        BUCKET,
+        escape(&prefix),
+        end - start,
+        PAGE_SIZE,
+        next,
+        contents
+    ))
+}
+
+fn list_versions(bucket: &Bucket, query: &HashMap<String, String>) -> Response {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let mut entries = String::new();
+    for (key, versions) in bucket
+        .objects
+        .iter()
+        .filter(|(key, _)| key.starts_with(&prefix))
+    {
+        for (idx, version) in versions.iter().enumerate().rev() {
+            let is_latest = idx + 1 == versions.len();
+            match &version.body {
+                Some(body) => entries.push_str(&format!(
+                    "<Version><Key>{}</Key><VersionId>{}</VersionId><IsLatest>{}</IsLatest><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Version>",
+                    escape(key),
+                    version.version_id,
+                    is_latest,
+                    iso_date(version.modified),
+                    escape(&version.etag),
+                    body.len()
+                )),
+                None => entries.push_str(&format!(
+                    "<DeleteMarker><Key>{}</Key><VersionId>{}</VersionId><IsLatest>{}</IsLatest><LastModified>{}</LastModified></DeleteMarker>",
+                    escape(key),
+                    version.version_id,
+                    is_latest,
+                    iso_date(version.modified)
+                )),
+            }
+        }
+    }
+
+    xml(format!(
+        "<ListVersionsResult><Name>{}</Name><Prefix>{}</Prefix><IsTruncated>false</IsTruncated>{}</ListVersionsResult>",
+        BUCKET,
+        escape(&prefix),
+        entries
+    ))
+}
+
+fn xml(body: String) -> Response {
+    Response {
+        status: 200,
+        headers: Vec::new(),
+        body: format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", body),
+    }
+}
+
+fn error(status: u16, code: &str) -> Response {
+    Response {
+        status,
+        headers: Vec::new(),
+        body: format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code><Message>{}</Message></Error>",
+            code, code
+        ),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// every write happens a second after the previous one on 2024-01-01
+fn iso_date(clock: u64) -> String {
+    format!(
+        "2024-01-01T{:02}:{:02}:{:02}.000Z",
+        clock / 3600,
+        clock / 60 % 60,
+        clock % 60
+    )
+}
+
+fn http_date(clock: u64) -> String {
+    format!(
+        "Mon, 01 Jan 2024 {:02}:{:02}:{:02} GMT",
+        clock / 3600,
+        clock / 60 % 60,
+        clock % 60
+    )
+}
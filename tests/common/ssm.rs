@@ -0,0 +1,233 @@
+// A local stand-in for SSM Parameter Store.
+
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{ACCOUNT_ID, PAGE_SIZE};
+
+struct ParameterVersion {
+    value: String,
+    kind: String,
+    description: Option<String>,
+    modified: f64,
+}
+
+#[derive(Default)]
+pub struct Parameters {
+    parameters: BTreeMap<String, Vec<ParameterVersion>>,
+    tags: HashMap<String, Vec<(String, String)>>,
+    // parameters whose writes and deletions are denied
+    read_only: HashSet<String>,
+    // parameter and value of a write of someone else that lands right before the next write of it
+    pub overtake: Option<(String, String)>,
+    clock: f64,
+}
+
+impl Parameters {
+    pub fn put(&mut self, name: &str, value: &str) -> i64 {
+        self.clock += 1.0;
+        let versions = self.parameters.entry(name.to_string()).or_default();
+        versions.push(ParameterVersion {
+            value: value.to_string(),
+            kind: "SecureString".to_string(),
+            description: None,
+            modified: 1_700_000_000.0 + self.clock,
+        });
+        versions.len() as i64
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        Some(self.parameters.get(name)?.last()?.value.clone())
+    }
+
+    pub fn deny_writes(&mut self, name: &str) {
+        self.read_only.insert(name.to_string());
+    }
+
+    pub fn version_count(&self, name: &str) -> usize {
+        self.parameters
+            .get(name)
+            .map_or(0, |versions| versions.len())
+    }
+
+    fn metadata(&self, name: &str, with_value: bool) -> Value {
+        let versions = &self.parameters[name];
+        let latest = versions.last().unwrap();
+        let mut parameter = json!({
+            "Name": name,
+            "ARN": format!("arn:aws:ssm:us-east-1:{}:parameter{}", ACCOUNT_ID, name),
+            "Type": latest.kind,
+            "Version": versions.len(),
+            "LastModifiedDate": latest.modified,
+            "DataType": "text",
+        });
+        if with_value {
+            parameter["Value"] = json!(latest.value);
+        } else if let Some(description) = &latest.description {
+            parameter["Description"] = json!(description);
+        }
+        parameter
+    }
+}
+
+pub fn handle(
+    state: &mut Parameters,
+    operation: &str,
+    request: &Value,
+) -> Result<Value, (&'static str, String)> {
+    let name = request["Name"].as_str().unwrap_or_default().to_string();
+    let not_found = || ("ParameterNotFound", format!("parameter {} not found", name));
+    let writes = ["PutParameter", "DeleteParameter"];
+    if writes.contains(&operation) && state.read_only.contains(&name) {
+        return Err((
+            "AccessDeniedException",
+            format!("not authorized to change parameter {}", name),
+        ));
+    }
+
+    match operation {
+        "PutParameter" => {
+            let overwrite = request["Overwrite"].as_bool().unwrap_or(false);
+            if state.parameters.contains_key(&name) && !overwrite {
+                return Err((
+                    "ParameterAlreadyExists",
+                    format!("parameter {} already exists", name),
+                ));
+            }
+            let value = request["Value"].as_str().unwrap_or_default();
+            if value.is_empty() {
+                return Err(("ValidationException", "empty value".to_string()));
+            }
+
+            if state
+                .overtake
+                .as_ref()
+                .is_some_and(|(parameter, _)| *parameter == name)
+            {
+                let (_, other) = state.overtake.take().unwrap();
+                state.put(&name, &other);
+            }
+            let version = state.put(&name, value);
+            let latest = state.parameters.get_mut(&name).unwrap().last_mut().unwrap();
+            latest.kind = request["Type"].as_str().unwrap_or("String").to_string();
+            latest.description = request["Description"].as_str().map(String::from);
+            if let Some(tags) = request["Tags"].as_array() {
+                let tags = tags
+                    .iter()
+                    .map(|tag| {
+                        (
+                            tag["Key"].as_str().unwrap_or_default().to_string(),
+                            tag["Value"].as_str().unwrap_or_default().to_string(),
+                        )
+                    })
+                    .collect();
+                state.tags.insert(name.clone(), tags);
+            }
+
+            Ok(json!({ "Version": version, "Tier": "Standard" }))
+        }
+        "DeleteParameter" => {
+            state.parameters.remove(&name).ok_or_else(not_found)?;
+            state.tags.remove(&name);
+            Ok(json!({}))
+        }
+        "GetParametersByPath" => {
+            let path = format!("{}/", request["Path"].as_str().unwrap_or_default());
+            let recursive = request["Recursive"].as_bool().unwrap_or(false);
+            let names: Vec<&String> = state
+                .parameters
+                .keys()
+                .filter(|name| {
+                    name.strip_prefix(&path)
+                        .is_some_and(|rest| recursive || !rest.contains('/'))
+                })
+                .collect();
+
+            let parameters = names
+                .iter()
+                .map(|name| state.metadata(name, true))
+                .collect();
+            Ok(page(parameters, request, "Parameters"))
+        }
+        "DescribeParameters" => {
+            let names: Vec<&String> = state
+                .parameters
+                .keys()
+                .filter(|name| matches_filters(name, &request["ParameterFilters"]))
+                .collect();
+
+            let parameters = names
+                .iter()
+                .map(|name| state.metadata(name, false))
+                .collect();
+            Ok(page(parameters, request, "Parameters"))
+        }
+        "GetParameterHistory" => {
+            let versions = state.parameters.get(&name).ok_or_else(not_found)?;
+            let history = versions
+                .iter()
+                .enumerate()
+                .map(|(idx, version)| {
+                    json!({
+                        "Name": name,
+                        "Type": version.kind,
+                        "Value": version.value,
+                        "Version": idx + 1,
+                        "LastModifiedDate": version.modified,
+                    })
+                })
+                .collect();
+            Ok(page(history, request, "Parameters"))
+        }
+        "ListTagsForResource" => {
+            let resource_id = request["ResourceId"].as_str().unwrap_or_default();
+            let tags = state.tags.get(resource_id).cloned().unwrap_or_default();
+            Ok(json!({
+                "TagList": tags.iter().map(|(key, value)| json!({
+                    "Key": key,
+                    "Value": value,
+                })).collect::<Vec<_>>(),
+            }))
+        }
+        _ => Err((
+            "InvalidRequestException",
+            format!("unsupported operation {}", operation),
+        )),
+    }
+}
+
+fn page(items: Vec<Value>, request: &Value, key: &str) -> Value {
+    let start: usize = request["NextToken"]
+        .as_str()
+        .map_or(0, |token| token.parse().unwrap());
+    let end = (start + PAGE_SIZE).min(items.len());
+
+    let mut response = json!({ key: items[start..end] });
+    if end < items.len() {
+        response["NextToken"] = json!(end.to_string());
+    }
+    response
+}
+
+fn matches_filters(name: &str, filters: &Value) -> bool {
+    filters.as_array().into_iter().flatten().all(|filter| {
+        let option = filter["Option"].as_str().unwrap_or("Equals");
+        let values: Vec<&str> = filter["Values"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        values
+            .iter()
+            .any(|value| match (filter["Key"].as_str(), option) {
+                (Some("Path"), "Recursive") => name.starts_with(&format!("{}/", value)),
+                (Some("Path"), _) => name
+                    .strip_prefix(&format!("{}/", value))
+                    .is_some_and(|rest| !rest.contains('/')),
+                (Some("Name"), "BeginsWith") => name.starts_with(value),
+                (Some("Name"), _) => name == *value,
+                _ => false,
+            })
+    })
+}
@@ -0,0 +1,257 @@
+// A local stand-in for the KV v2 secrets engine of Vault, mounted at `secret`.
+
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+
+use super::percent_decode;
+
+pub const TOKEN: &str = "root";
+pub const ROLE_ID: &str = "role";
+pub const SECRET_ID: &str = "approle-secret";
+const APPROLE_TOKEN: &str = "approle-token";
+
+struct KvVersion {
+    data: Value,
+    created: String,
+    deleted: bool,
+}
+
+#[derive(Default)]
+struct KvSecret {
+    versions: Vec<KvVersion>,
+    custom_metadata: Map<String, Value>,
+}
+
+#[derive(Default)]
+pub struct Kv {
+    secrets: BTreeMap<String, KvSecret>,
+    clock: u64,
+    // number of AppRole logins
+    pub logins: usize,
+    // number of upcoming writes preceded by a write of someone else
+    pub races: usize,
+    // number of upcoming requests to throttle
+    pub throttled: usize,
+}
+
+impl Kv {
+    pub fn put(&mut self, path: &str, data: Value) -> usize {
+        self.clock += 1;
+        let secret = self.secrets.entry(path.to_string()).or_default();
+        secret.versions.push(KvVersion {
+            data,
+            created: format!("2024-01-01T00:00:{:02}.123456789Z", self.clock % 60),
+            deleted: false,
+        });
+        secret.versions.len()
+    }
+
+    pub fn get(&self, path: &str, key: &str) -> Option<String> {
+        let latest = self.secrets.get(path)?.versions.last()?;
+        if latest.deleted {
+            return None;
+        }
+        latest.data[key].as_str().map(String::from)
+    }
+
+    pub fn version_count(&self, path: &str) -> usize {
+        self.secrets
+            .get(path)
+            .map_or(0, |secret| secret.versions.len())
+    }
+
+    pub fn is_deleted(&self, path: &str) -> bool {
+        self.secrets
+            .get(path)
+            .and_then(|secret| secret.versions.last())
+            .is_some_and(|version| version.deleted)
+    }
+
+    pub fn custom_metadata(&self, path: &str) -> Value {
+        self.secrets.get(path).map_or(Value::Null, |secret| {
+            Value::Object(secret.custom_metadata.clone())
+        })
+    }
+}
+
+pub fn handle(
+    state: &mut Kv,
+    method: &str,
+    target: &str,
+    token: Option<&str>,
+    request: &Value,
+) -> (u16, Value) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = percent_decode(path.trim_start_matches("/v1/"));
+
+    if path == "auth/approle/login" {
+        if request["role_id"] != ROLE_ID || request["secret_id"] != SECRET_ID {
+            return error(400, "invalid role or secret ID");
+        }
+        state.logins += 1;
+        return (200, json!({ "auth": { "client_token": APPROLE_TOKEN } }));
+    }
+    if token != Some(TOKEN) && token != Some(APPROLE_TOKEN) {
+        return error(403, "permission denied");
+    }
+    if state.throttled > 0 {
+        state.throttled -= 1;
+        return error(429, "rate limit quota exceeded");
+    }
+    if path == "auth/token/lookup-self" {
+        return (200, json!({ "data": { "display_name": "token" } }));
+    }
+
+    let query: Vec<(&str, &str)> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect();
+    let param = |name: &str| {
+        query
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+    };
+
+    let Some((kind, name)) = path
+        .strip_prefix("secret/")
+        .and_then(|rest| rest.split_once('/'))
+    else {
+        return error(404, "no handler for route");
+    };
+
+    match (method, kind) {
+        ("GET", "data") => {
+            let Some(secret) = state.secrets.get(name) else {
+                return error(404, "");
+            };
+            let number = param("version")
+                .and_then(|version| version.parse().ok())
+                .unwrap_or(secret.versions.len());
+            match secret.versions.get(number.wrapping_sub(1)) {
+                Some(version) if !version.deleted => (
+                    200,
+                    json!({
+                        "data": {
+                            "data": version.data,
+                            "metadata": { "version": number, "created_time": version.created },
+                        }
+                    }),
+                ),
+                _ => error(404, ""),
+            }
+        }
+        ("POST", "data") => {
+            // someone else writes another field after the writer read the secret
+            if state.races > 0 {
+                state.races -= 1;
+                let mut data = state.secrets[name].versions.last().unwrap().data.clone();
+                data["other"] = json!(format!("race-{}", state.clock));
+                state.put(name, data);
+            }
+            let current = state.version_count(name);
+            if let Some(cas) = request["options"]["cas"].as_u64()
+                && cas as usize != current
+            {
+                return error(
+                    400,
+                    "check-and-set parameter did not match the current version",
+                );
+            }
+            let version = state.put(name, request["data"].clone());
+            (200, json!({ "data": { "version": version } }))
+        }
+        ("DELETE", "data") => {
+            if let Some(version) = state
+                .secrets
+                .get_mut(name)
+                .and_then(|secret| secret.versions.last_mut())
+            {
+                version.deleted = true;
+            }
+            (204, Value::Null)
+        }
+        ("POST", "undelete") => {
+            let Some(secret) = state.secrets.get_mut(name) else {
+                return error(404, "");
+            };
+            for number in request["versions"].as_array().into_iter().flatten() {
+                if let Some(version) = number
+                    .as_u64()
+                    .and_then(|number| secret.versions.get_mut(number as usize - 1))
+                {
+                    version.deleted = false;
+                }
+            }
+            (204, Value::Null)
+        }
+        ("GET", "metadata") if param("list") == Some("true") => {
+            let folder = if name.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", name.trim_end_matches('/'))
+            };
+            let mut keys: Vec<String> = state
+                .secrets
+                .keys()
+                .filter_map(|path| path.strip_prefix(&folder))
+                .map(|rest| match rest.split_once('/') {
+                    Some((child, _)) => format!("{}/", child),
+                    None => rest.to_string(),
+                })
+                .collect();
+            keys.dedup();
+            if keys.is_empty() {
+                return error(404, "");
+            }
+            (200, json!({ "data": { "keys": keys } }))
+        }
+        ("GET", "metadata") => {
+            let Some(secret) = state.secrets.get(name) else {
+                return error(404, "");
+            };
+            let versions: Map<String, Value> = secret
+                .versions
+                .iter()
+                .enumerate()
+                .map(|(idx, version)| {
+                    (
+                        (idx + 1).to_string(),
+                        json!({
+                            "created_time": version.created,
+                            "deletion_time": if version.deleted { version.created.as_str() } else { "" },
+                            "destroyed": false,
+                        }),
+                    )
+                })
+                .collect();
+            (
+                200,
+                json!({
+                    "data": {
+                        "current_version": secret.versions.len(),
+                        "updated_time": secret.versions.last().map(|version| version.created.clone()),
+                        "custom_metadata": secret.custom_metadata,
+                        "versions": versions,
+                    }
+                }),
+            )
+        }
+        ("POST", "metadata") => {
+            let secret = state.secrets.entry(name.to_string()).or_default();
+            if let Some(custom_metadata) = request["custom_metadata"].as_object() {
+                secret.custom_metadata = custom_metadata.clone();
+            }
+            (204, Value::Null)
+        }
+        _ => error(405, "unsupported operation"),
+    }
+}
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+    let errors: Vec<&str> = Some(message)
+        .filter(|m| !m.is_empty())
+        .into_iter()
+        .collect();
+    (status, json!({ "errors": errors }))
+}